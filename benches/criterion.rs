@@ -1,21 +1,19 @@
-use std::{fs, time::Duration};
+use std::{
+    fs,
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
 
+use close_already::{
+    bench_support, Backend, Closer, DefaultCloser, FastCloseable,
+};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use tempfile::tempdir;
 
-#[cfg(feature = "backend-threadpool")]
-const BACKEND: &str = "threadpool";
-#[cfg(feature = "backend-blocking")]
-const BACKEND: &str = "blocking";
-#[cfg(feature = "backend-rayon")]
-const BACKEND: &str = "rayon";
-#[cfg(feature = "backend-async-std")]
-const BACKEND: &str = "async-std";
-#[cfg(feature = "backend-smol")]
-const BACKEND: &str = "smol";
-#[cfg(feature = "backend-tokio")]
-const BACKEND: &str = "tokio";
-
 fn reading_ufos(c: &mut Criterion) {
     let files = fs::read_dir("benches/data/Roboto-Regular.ufo/glyphs")
         .unwrap()
@@ -33,7 +31,7 @@ fn reading_ufos(c: &mut Criterion) {
         .measurement_time(Duration::from_secs(10))
         .bench_with_input(
             BenchmarkId::new(
-                format!("close_already {BACKEND}"),
+                format!("close_already {}", Backend::active().name()),
                 "Roboto-Regular.ufo",
             ),
             &files,
@@ -79,7 +77,7 @@ fn writing_ufos(c: &mut Criterion) {
         .measurement_time(Duration::from_secs(50))
         .bench_with_input(
             BenchmarkId::new(
-                format!("close_already {BACKEND}"),
+                format!("close_already {}", Backend::active().name()),
                 "Roboto-Regular.ufo",
             ),
             &files,
@@ -104,5 +102,154 @@ fn writing_ufos(c: &mut Criterion) {
     );
 }
 
-criterion_group!(criterion, reading_ufos, writing_ufos);
+/// The time spent in each phase of writing a set of files
+#[derive(Default)]
+struct Phases {
+    open: Duration,
+    write: Duration,
+    drop: Duration,
+    background_close: Duration,
+}
+
+impl Phases {
+    fn get(&self, phase: &str) -> Duration {
+        match phase {
+            "open" => self.open,
+            "write" => self.write,
+            "drop" => self.drop,
+            "background close" => self.background_close,
+            _ => unreachable!("unknown phase {phase}"),
+        }
+    }
+}
+
+/// Writes every file, timing each phase separately
+fn write_phases(files: &[(PathBuf, Vec<u8>)]) -> Phases {
+    let mut phases = Phases::default();
+    bench_support::reset();
+    for (path, bytes) in files {
+        let start = Instant::now();
+        let mut file = File::create(path).unwrap().fast_close();
+        let opened = Instant::now();
+        file.write_all(bytes).unwrap();
+        let written = Instant::now();
+        drop(file);
+        phases.open += opened - start;
+        phases.write += written - opened;
+        phases.drop += written.elapsed();
+    }
+    // Closures happen in the background, so wait for them all to finish
+    close_already::drain();
+    phases.background_close = bench_support::timings().close_time;
+    phases
+}
+
+fn writing_phases(c: &mut Criterion) {
+    let temp_dir = tempdir().unwrap();
+    let files = fs::read_dir("benches/data/Roboto-Regular.ufo/glyphs")
+        .unwrap()
+        .filter_map(|de| {
+            let de = de.unwrap();
+            let file_name = de.file_name();
+            let file_name = file_name.to_str().unwrap();
+            file_name.ends_with(".glif").then(|| {
+                let bytes = fs::read(de.path()).unwrap();
+                (temp_dir.path().join(file_name), bytes)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    bench_support::enable(true);
+    let mut group = c.benchmark_group(format!(
+        "Writing phases {}",
+        Backend::active().name()
+    ));
+    group
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(50));
+    for phase in ["open", "write", "drop", "background close"] {
+        group.bench_with_input(
+            BenchmarkId::new(phase, "Roboto-Regular.ufo"),
+            &files,
+            |b, files| {
+                b.iter_custom(|iters| {
+                    (0..iters).map(|_| write_phases(files).get(phase)).sum()
+                });
+            },
+        );
+    }
+    group.finish();
+    bench_support::enable(false);
+}
+
+/// Many threads dropping handles at once, to measure contention when
+/// submitting to the backend
+fn dropping_concurrently(c: &mut Criterion) {
+    const HANDLES_PER_THREAD: usize = 1000;
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join("file");
+    fs::write(&path, b"contended").unwrap();
+
+    let mut group =
+        c.benchmark_group(format!("Contention {}", Backend::active().name()));
+    group
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(20));
+    for threads in [1, 2, 4, 8, 16, 32] {
+        group.bench_with_input(
+            BenchmarkId::new("open and drop", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    thread::scope(|scope| {
+                        for _ in 0..threads {
+                            scope.spawn(|| {
+                                for _ in 0..HANDLES_PER_THREAD {
+                                    drop(
+                                        File::open(&path).unwrap().fast_close(),
+                                    );
+                                }
+                            });
+                        }
+                    });
+                });
+            },
+        );
+        // Handles are closed inline off Windows, so submit jobs to the
+        // backend directly to measure its contention on every platform
+        group.bench_with_input(
+            BenchmarkId::new("submit to backend", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    let (sender, receiver) = mpsc::channel::<()>();
+                    thread::scope(|scope| {
+                        for _ in 0..threads {
+                            let sender = sender.clone();
+                            scope.spawn(move || {
+                                for _ in 0..HANDLES_PER_THREAD {
+                                    let sender = sender.clone();
+                                    DefaultCloser::default()
+                                        .submit(Box::new(move || drop(sender)));
+                                }
+                            });
+                        }
+                    });
+                    // Disconnects once every job has run
+                    drop(sender);
+                    assert!(receiver.recv().is_err());
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    criterion,
+    reading_ufos,
+    writing_ufos,
+    writing_phases,
+    dropping_concurrently
+);
 criterion_main!(criterion);
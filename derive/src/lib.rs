@@ -0,0 +1,67 @@
+//! Derive macro for [`close_already`](https://docs.rs/close_already)'s
+//! `FastCloseable` trait
+//!
+//! Use this through `close_already`'s `derive` feature, rather than
+//! depending on it directly
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, DeriveInput, Error};
+
+/// Implements `FastCloseable` for a file wrapper type
+///
+/// The type must be `Send`, `'static`, and not `Clone`, which is checked at
+/// compile time. It's up to you to make sure it isn't reference counted
+#[proc_macro_derive(FastCloseable)]
+pub fn derive_fast_closeable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(lifetime) = input.generics.lifetimes().next() {
+        return Err(Error::new_spanned(
+            lifetime,
+            "FastCloseable types must be 'static, so can't borrow",
+        ));
+    }
+
+    let name = &input.ident;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let ty: syn::Type = parse_quote!(#name #ty_generics);
+    input
+        .generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote!(#ty: ::core::marker::Send + 'static));
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+
+    // If the type implements Clone, both impls of AmbiguousIfClone apply, so
+    // the inferred parameter can't be resolved and compilation fails
+    let not_clone = quote! {
+        const _: () = {
+            trait AmbiguousIfClone<A> {
+                fn check() {}
+            }
+            impl<T: ?::core::marker::Sized> AmbiguousIfClone<()> for T {}
+            struct IsClone;
+            impl<T> AmbiguousIfClone<IsClone> for T
+            where
+                T: ?::core::marker::Sized + ::core::clone::Clone,
+            {
+            }
+            #[allow(dead_code)]
+            fn fast_closeable_must_not_be_clone #impl_generics ()
+            #where_clause
+            {
+                <#ty as AmbiguousIfClone<_>>::check();
+            }
+        };
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::close_already::FastCloseable for #ty #where_clause {}
+        #not_clone
+    })
+}
@@ -0,0 +1,118 @@
+//! Tracking of the closures spawned by the `tokio` backend, so they can be
+//! awaited
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+/// The closures spawned by the `tokio` backend that haven't finished, and the
+/// tasks waiting for them to
+static RUNNING: Mutex<Running> = Mutex::new(Running {
+    count: 0,
+    wakers: Vec::new(),
+});
+
+struct Running {
+    count: usize,
+    wakers: Vec<Waker>,
+}
+
+fn lock() -> MutexGuard<'static, Running> {
+    RUNNING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns a future that resolves once every handle closure spawned by the
+/// `tokio` backend has finished
+///
+/// This is the async equivalent of [`drain`](crate::drain), for including
+/// file closes in a graceful shutdown without blocking the runtime. Closures
+/// spawned while waiting are waited for too. Handles closed inline, or by
+/// another backend or [`Closer`](crate::Closer), aren't tracked, so on
+/// non-Windows platforms this resolves immediately
+///
+/// ```no_run
+/// # async fn example() {
+/// // At the end of the server's graceful shutdown
+/// close_already::all_closed().await;
+/// # }
+/// ```
+pub fn all_closed() -> impl Future<Output = ()> + Send + 'static {
+    AllClosed
+}
+
+/// The future returned by [`all_closed`]
+struct AllClosed;
+
+impl Future for AllClosed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut running = lock();
+        if running.count == 0 {
+            return Poll::Ready(());
+        }
+        if !running
+            .wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            running.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Records a closure as spawned until it's dropped, whether or not it ran,
+/// waking the tasks waiting in [`all_closed`] if it was the last one
+pub(crate) struct Spawned(());
+
+impl Spawned {
+    pub(crate) fn new() -> Self {
+        lock().count += 1;
+        Spawned(())
+    }
+}
+
+impl Drop for Spawned {
+    fn drop(&mut self) {
+        let mut running = lock();
+        running.count -= 1;
+        if running.count == 0 {
+            let wakers = mem::take(&mut running.wakers);
+            drop(running);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_spawned_closures() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        struct Handle;
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                thread::sleep(Duration::from_millis(10));
+                CLOSED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        crate::backend::submit_tokio(Handle).unwrap();
+        all_closed().await;
+        assert!(CLOSED.load(Ordering::SeqCst), "resolved too early");
+    }
+}
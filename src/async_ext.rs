@@ -0,0 +1,269 @@
+//! Async IO helper methods on [`FastClose`], so users don't need to import
+//! their runtime's extension traits
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+// async-std and smol both use the futures IO traits
+#[cfg(not(feature = "backend-tokio"))]
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "backend-tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{FastClose, FastCloseable};
+
+/// Async IO helpers for [`FastClose`], implemented using the async backend's
+/// own IO traits
+///
+/// These are equivalent to the methods of the same name in your runtime's
+/// `AsyncReadExt`/`AsyncWriteExt` traits (or `ReadExt`/`WriteExt` for
+/// `async-std`), so you only need to import this trait to use `FastClose`
+/// idiomatically
+///
+/// If `backend-tokio` is enabled alongside another async backend, `tokio`'s
+/// IO traits are used
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use close_already::{FastCloseAsyncExt, FastCloseable};
+/// # #[cfg(feature = "backend-tokio")]
+/// use tokio::fs::File;
+/// # #[cfg(all(feature = "backend-smol", not(feature = "backend-tokio")))]
+/// # use smol::fs::File;
+/// # #[cfg(all(
+/// #     feature = "backend-async-std",
+/// #     not(any(feature = "backend-smol", feature = "backend-tokio")),
+/// # ))]
+/// # use async_std::fs::File;
+///
+/// let mut file = File::create("hello.txt").await?.fast_close();
+/// file.write_all(b"Hello, world!").await?;
+/// file.flush_and_close().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait FastCloseAsyncExt: Sized {
+    /// Reads all bytes until EOF, appending them to `buf`
+    ///
+    /// On success, the total number of bytes read is returned
+    fn read_to_end<'a>(
+        &'a mut self,
+        buf: &'a mut Vec<u8>,
+    ) -> impl Future<Output = io::Result<usize>> + Send + 'a
+    where
+        Self: AsyncRead + Unpin + Send;
+
+    /// Reads all bytes until EOF, appending them to `buf`, which must be
+    /// valid UTF-8
+    ///
+    /// On success, the total number of bytes read is returned
+    fn read_to_string<'a>(
+        &'a mut self,
+        buf: &'a mut String,
+    ) -> impl Future<Output = io::Result<usize>> + Send + 'a
+    where
+        Self: AsyncRead + Unpin + Send;
+
+    /// Writes an entire buffer
+    fn write_all<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+    ) -> impl Future<Output = io::Result<()>> + Send + 'a
+    where
+        Self: AsyncWrite + Unpin + Send;
+
+    /// Flushes and shuts down the writer, returning any errors, and then
+    /// offloads closing the handle to the backend
+    ///
+    /// Use this instead of dropping a writer when you need to know that all
+    /// the data written made it out of any buffers
+    fn flush_and_close(self) -> impl Future<Output = io::Result<()>> + Send
+    where
+        Self: AsyncWrite + Unpin + Send;
+
+    /// Submits the handle to the backend to be closed, like dropping it
+    /// would, returning a future that resolves once it's actually been
+    /// closed
+    ///
+    /// Use this when the file needs to be closed before continuing (e.g.
+    /// before renaming or removing it), without blocking the executor.
+    /// Only the future waits for the handle to be closed, so it's fine to
+    /// drop it instead of awaiting it
+    fn close_async(self) -> impl Future<Output = ()> + Send + 'static;
+}
+
+impl<H: FastCloseable + 'static> FastCloseAsyncExt for FastClose<H> {
+    fn read_to_end<'a>(
+        &'a mut self,
+        buf: &'a mut Vec<u8>,
+    ) -> impl Future<Output = io::Result<usize>> + Send + 'a
+    where
+        Self: AsyncRead + Unpin + Send,
+    {
+        AsyncReadExt::read_to_end(self, buf)
+    }
+
+    fn read_to_string<'a>(
+        &'a mut self,
+        buf: &'a mut String,
+    ) -> impl Future<Output = io::Result<usize>> + Send + 'a
+    where
+        Self: AsyncRead + Unpin + Send,
+    {
+        AsyncReadExt::read_to_string(self, buf)
+    }
+
+    fn write_all<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+    ) -> impl Future<Output = io::Result<()>> + Send + 'a
+    where
+        Self: AsyncWrite + Unpin + Send,
+    {
+        AsyncWriteExt::write_all(self, buf)
+    }
+
+    #[cfg(feature = "backend-tokio")]
+    async fn flush_and_close(mut self) -> io::Result<()>
+    where
+        Self: AsyncWrite + Unpin + Send,
+    {
+        AsyncWriteExt::flush(&mut self).await?;
+        AsyncWriteExt::shutdown(&mut self).await
+    }
+
+    #[cfg(not(feature = "backend-tokio"))]
+    async fn flush_and_close(mut self) -> io::Result<()>
+    where
+        Self: AsyncWrite + Unpin + Send,
+    {
+        // Closing flushes first
+        std::future::poll_fn(|cx| Pin::new(&mut self).poll_close(cx)).await
+    }
+
+    fn close_async(self) -> impl Future<Output = ()> + Send + 'static {
+        let signal = Arc::new(Signal::default());
+        let notify_signal = Arc::clone(&signal);
+        drop(self.map(|handle| Notify {
+            handle: Some(handle),
+            signal: notify_signal,
+        }));
+        Closed(signal)
+    }
+}
+
+/// Whether a handle has been closed, shared between [`Notify`] and
+/// [`Closed`]
+#[derive(Default)]
+struct Signal(Mutex<SignalState>);
+
+#[derive(Default)]
+struct SignalState {
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl Signal {
+    fn lock(&self) -> MutexGuard<'_, SignalState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A handle that signals when it's been dropped
+struct Notify<H> {
+    handle: Option<H>,
+    signal: Arc<Signal>,
+}
+
+impl<H: FastCloseable> FastCloseable for Notify<H> {
+    fn before_close(&mut self) -> io::Result<()> {
+        self.handle.as_mut().map_or(Ok(()), H::before_close)
+    }
+
+    /// Closes the handle, then wakes the task waiting for it
+    fn close(mut self) -> io::Result<()> {
+        self.handle.take().map_or(Ok(()), H::close)
+    }
+}
+
+impl<H> Drop for Notify<H> {
+    /// Closes the handle, then wakes the task waiting for it
+    fn drop(&mut self) {
+        drop(self.handle.take());
+        let mut state = self.signal.lock();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The future returned by [`FastCloseAsyncExt::close_async`]
+struct Closed(Arc<Signal>);
+
+impl Future for Closed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.0.lock();
+        if state.closed {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    #[cfg(all(
+        feature = "backend-async-std",
+        not(any(feature = "backend-smol", feature = "backend-tokio")),
+    ))]
+    use async_std::fs::File;
+    #[cfg(all(feature = "backend-smol", not(feature = "backend-tokio")))]
+    use smol::fs::File;
+    #[cfg(feature = "backend-tokio")]
+    use tokio::fs::File;
+
+    use crate::{test_runtime::block_on, FastCloseAsyncExt, FastCloseable};
+
+    #[test]
+    fn write_then_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        block_on(async {
+            let mut file = File::create(&path).await.unwrap().fast_close();
+            file.write_all(b"hello")
+                .await
+                .expect("write should succeed");
+            file.flush_and_close().await.expect("close should succeed");
+
+            let mut file = File::open(&path).await.unwrap().fast_close();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)
+                .await
+                .expect("read should succeed");
+            assert_eq!(contents, "hello");
+        });
+    }
+
+    #[test]
+    fn close_then_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        block_on(async {
+            let mut file = File::create(&path).await.unwrap().fast_close();
+            file.write_all(b"hello")
+                .await
+                .expect("write should succeed");
+            file.close_async().await;
+            std::fs::remove_file(&path).expect("file should be closed");
+        });
+    }
+}
@@ -0,0 +1,24 @@
+//! Migration information for the deprecated `async-std` backend
+//!
+//! `async-std` is no longer maintained, so `backend-async-std` is
+//! deprecated in favour of `backend-smol`. To make migrating painless, the
+//! `async-std` backend now closes handles on `smol`'s blocking thread pool,
+//! while still implementing [`FastCloseable`](crate::FastCloseable) and the
+//! async IO traits for `async_std`'s types. Your code doesn't need to
+//! change until you're ready to move off `async-std` entirely
+//!
+//! To migrate, replace `async_std::fs::File` with `smol::fs::File` (or
+//! `async_fs::File`, which is the same type), and then switch to the
+//! `backend-smol` feature
+//!
+//! The constants in this module can be used to check the timeline
+//! programmatically, e.g. in a build script or test that should fail once
+//! migrating is required
+
+/// The version of `close_already` in which `backend-async-std` was
+/// deprecated
+pub const DEPRECATED_SINCE: &str = "0.4.0";
+
+/// The version of `close_already` in which `backend-async-std` will be
+/// removed
+pub const REMOVED_IN: &str = "0.5.0";
@@ -0,0 +1,142 @@
+//! Tracking of live [`FastClose`](crate::FastClose) instances, for detecting
+//! handle leaks
+//!
+//! With the `audit` feature enabled, every `FastClose` is recorded in a
+//! global table from when it's created until it's dropped (or unwrapped with
+//! [`into_inner`](crate::FastClose::into_inner)). Long-running services can
+//! periodically check [`outstanding`] or [`count_by_label`] to spot handles
+//! accumulating. Give handles a label with
+//! [`FastClose::with_label`](crate::FastClose::with_label) to tell them apart
+//!
+//! Recording every handle takes a lock on creation and drop, so this is
+//! intended for diagnosing leaks, rather than being left on permanently
+use std::{
+    any,
+    borrow::Cow,
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::Instant,
+};
+
+use crate::diagnostics::Origin;
+
+/// Assigns each handle a unique ID, in order of creation
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The live handles, by ID
+static LIVE: Mutex<BTreeMap<u64, Outstanding>> = Mutex::new(BTreeMap::new());
+
+/// A live `FastClose`, as returned by [`outstanding`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Outstanding {
+    /// The label given to the handle, if any
+    pub label: Option<Cow<'static, str>>,
+    /// The name of the handle's type
+    pub type_name: &'static str,
+    /// When the `FastClose` was created
+    pub created: Instant,
+    /// Where the `FastClose` was created
+    #[cfg(feature = "diagnostics")]
+    pub origin: Option<Origin>,
+}
+
+/// Lists every `FastClose` that hasn't yet been dropped, oldest first
+pub fn outstanding() -> Vec<Outstanding> {
+    live().values().cloned().collect()
+}
+
+/// Counts the `FastClose`s that haven't yet been dropped by their label, to
+/// find which are accumulating
+///
+/// ```
+/// for (label, count) in close_already::audit::count_by_label() {
+///     let label = label.as_deref().unwrap_or("unlabeled");
+///     println!("{label}: {count} handles open");
+/// }
+/// ```
+pub fn count_by_label() -> BTreeMap<Option<Cow<'static, str>>, usize> {
+    let mut counts = BTreeMap::new();
+    for handle in live().values() {
+        *counts.entry(handle.label.clone()).or_default() += 1;
+    }
+    counts
+}
+
+fn live() -> MutexGuard<'static, BTreeMap<u64, Outstanding>> {
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A `FastClose`'s entry in the table of live handles, which must be
+/// released when the handle is dropped or unwrapped
+#[derive(Debug)]
+pub(crate) struct Ticket(u64);
+
+impl Ticket {
+    /// Records a new handle of type `H`, created at `origin`
+    pub(crate) fn register<H: ?Sized>(origin: Option<Origin>) -> Ticket {
+        #[cfg(not(feature = "diagnostics"))]
+        let _ = origin;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        live().insert(id, Outstanding {
+            label: None,
+            type_name: any::type_name::<H>(),
+            created: Instant::now(),
+            #[cfg(feature = "diagnostics")]
+            origin,
+        });
+        Ticket(id)
+    }
+
+    /// Sets the handle's label
+    pub(crate) fn label(&self, label: Cow<'static, str>) {
+        if let Some(handle) = live().get_mut(&self.0) {
+            handle.label = Some(label);
+        }
+    }
+
+    /// Removes the handle from the table
+    pub(crate) fn release(&self) {
+        live().remove(&self.0);
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{fs::tests::in_runtime, FastClose};
+
+    #[test]
+    fn tracks_labeled_handles() {
+        in_runtime(|| {
+            let label = Some(Cow::Borrowed("audit test"));
+            let count = || count_by_label().get(&label).copied();
+            let file = File::open("Cargo.toml").unwrap();
+            let file = FastClose::with_label(file, "audit test");
+            assert_eq!(count(), Some(1));
+            assert!(outstanding().iter().any(|handle| {
+                handle.label == label
+                    && handle.type_name == any::type_name::<File>()
+            }));
+            #[cfg(feature = "diagnostics")]
+            assert!(outstanding().iter().any(|handle| {
+                handle.label == label
+                    && handle
+                        .origin
+                        .as_ref()
+                        .is_some_and(|origin| origin.location.file() == file!())
+            }));
+
+            let file = file.into_inner();
+            assert_eq!(count(), None, "unwrapped handles aren't live");
+            let file = FastClose::with_label(file, "audit test");
+            drop(file);
+            assert_eq!(count(), None, "dropped handles aren't live");
+        });
+    }
+}
@@ -0,0 +1,968 @@
+//! The backends that handle closures are offloaded to
+//!
+//! Each backend provides its own `submit_<name>` function, enabled by its
+//! feature flag, which takes ownership of a task and arranges for it to be
+//! dropped elsewhere. [`submit`] dispatches to whichever is active (see
+//! [`Backend`]). The rest of the crate goes through [`close_on`], which wraps
+//! handles in [`Finish`] to do the bookkeeping common to all backends, and
+//! sends them to either the global [`Closer`] or `submit`. These are only
+//! called on Windows, but are compiled everywhere
+#![cfg_attr(not(windows), allow(dead_code))]
+
+#[cfg(feature = "backend-thread-per-close")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "backend-threadpool")]
+use std::sync::Arc;
+#[cfg(any(
+    feature = "backend-thread",
+    feature = "backend-thread-per-close"
+))]
+use std::thread;
+use std::{
+    borrow::Cow,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::OnceLock,
+};
+#[cfg(feature = "backend-thread")]
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex, Once, PoisonError},
+};
+
+#[cfg(feature = "backend-threadpool")]
+use crate::pool::{Pool, PoolConfig};
+use crate::{
+    closer::Closer, diagnostics::Origin, stats::BackendStats, FastCloseable,
+};
+
+/// A lazily initialised [`Pool`] to send handle closures to
+#[cfg(feature = "backend-threadpool")]
+static CLOSER_POOL: OnceLock<Arc<Pool>> = OnceLock::new();
+
+/// Configures the `threadpool` backend's thread pool
+///
+/// Call this at startup, as the pool is created with the default
+/// [`PoolConfig`] when the first handle is dropped
+///
+/// # Errors
+///
+/// The pool can only be configured before it's created. If it already has
+/// been, `config` is returned back
+#[cfg(feature = "backend-threadpool")]
+pub fn configure(config: PoolConfig) -> Result<(), PoolConfig> {
+    let mut config = Some(config);
+    CLOSER_POOL.get_or_init(|| Pool::new(config.take().unwrap()));
+    match config {
+        None => Ok(()),
+        Some(config) => Err(config),
+    }
+}
+
+/// The thread pool, created with the default [`PoolConfig`] if it hasn't
+/// been [configured](configure)
+#[cfg(feature = "backend-threadpool")]
+fn closer_pool() -> &'static Arc<Pool> {
+    CLOSER_POOL.get_or_init(|| Pool::new(PoolConfig::default()))
+}
+
+/// Submits the task to a thread pool to be dropped
+#[cfg(feature = "backend-threadpool")]
+fn submit_threadpool<T: Send + 'static>(
+    task: T,
+    priority: Priority,
+) -> io::Result<()> {
+    closer_pool().execute(move || drop(task), priority);
+    Ok(())
+}
+
+/// The tasks waiting for the closer thread, with a queue per [`Priority`]
+#[cfg(feature = "backend-thread")]
+static CLOSER_QUEUES: Mutex<[VecDeque<Box<dyn Send>>; 3]> =
+    Mutex::new([VecDeque::new(), VecDeque::new(), VecDeque::new()]);
+
+/// Notified when a task is queued for the closer thread
+#[cfg(feature = "backend-thread")]
+static CLOSER_QUEUED: Condvar = Condvar::new();
+
+/// Spawns the closer thread the first time it's needed
+#[cfg(feature = "backend-thread")]
+static CLOSER_THREAD: Once = Once::new();
+
+/// Spawns the closer thread if it isn't running yet
+///
+/// It's only ever spawned once, so panics while dropping tasks are caught,
+/// rather than taking down the thread and stranding the tasks queued after
+/// them. Handles' panics are already reported by [`Finish`], so this only
+/// catches those of other tasks
+#[cfg(feature = "backend-thread")]
+fn closer_thread() {
+    CLOSER_THREAD.call_once(|| {
+        thread::Builder::new()
+            .name(String::from("close_already"))
+            .spawn(|| loop {
+                let mut queues = CLOSER_QUEUES
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let task = loop {
+                    match queues.iter_mut().find_map(VecDeque::pop_front) {
+                        Some(task) => break task,
+                        None => {
+                            queues = CLOSER_QUEUED
+                                .wait(queues)
+                                .unwrap_or_else(PoisonError::into_inner);
+                        },
+                    }
+                };
+                drop(queues);
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(task)));
+            })
+            .expect("failed to spawn close_already closer thread");
+    });
+}
+
+/// Sends the task to a single dedicated thread to be dropped, so tasks of
+/// the same priority are dropped in the order they were submitted
+#[cfg(feature = "backend-thread")]
+fn submit_thread<T: Send + 'static>(
+    task: T,
+    priority: Priority,
+) -> io::Result<()> {
+    closer_thread();
+    CLOSER_QUEUES.lock().unwrap_or_else(PoisonError::into_inner)
+        [priority as usize]
+        .push_back(Box::new(task));
+    CLOSER_QUEUED.notify_one();
+    Ok(())
+}
+
+/// The most threads `backend-thread-per-close` will run at once
+#[cfg(feature = "backend-thread-per-close")]
+const MAX_CLOSE_THREADS: usize = 16;
+
+/// The number of threads `backend-thread-per-close` is running
+#[cfg(feature = "backend-thread-per-close")]
+static CLOSE_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Spawns a thread to drop the task, or drops it inline if
+/// [`MAX_CLOSE_THREADS`] are already running
+///
+/// If spawning fails, the task is dropped inline, and the error returned
+#[cfg(feature = "backend-thread-per-close")]
+fn submit_thread_per_close<T: Send + 'static>(task: T) -> io::Result<()> {
+    /// Frees up the thread's slot when it finishes
+    struct Running;
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            CLOSE_THREADS.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    if CLOSE_THREADS.fetch_add(1, Ordering::SeqCst) >= MAX_CLOSE_THREADS {
+        drop(Running);
+        drop(task);
+        return Ok(());
+    }
+    let running = Running;
+    thread::Builder::new()
+        .name(String::from("close_already"))
+        .spawn(move || {
+            let _running = running;
+            drop(task);
+        })
+        .map(drop)
+}
+
+/// Spawns a task onto Bevy's `IoTaskPool` to drop the task
+///
+/// If the `IoTaskPool` hasn't been initialised yet (it's set up by Bevy's
+/// `TaskPoolPlugin`), the task is dropped inline
+#[cfg(feature = "backend-bevy-tasks")]
+fn submit_bevy_tasks<T: Send + 'static>(task: T) -> io::Result<()> {
+    if let Some(pool) = bevy_tasks::IoTaskPool::try_get() {
+        pool.spawn(async move { drop(task) }).detach();
+    }
+    Ok(())
+}
+
+/// Submits the task as a `blocking` task to be dropped
+#[cfg(feature = "backend-blocking")]
+fn submit_blocking<T: Send + 'static>(task: T) -> io::Result<()> {
+    blocking::unblock(move || drop(task)).detach();
+    Ok(())
+}
+
+/// Submits the task to the thread pool given to
+/// [`rayon::set_thread_pool`](crate::rayon::set_thread_pool), or `rayon`'s
+/// global thread pool, to be dropped. It's spawned in FIFO order if enabled
+/// with [`rayon::set_fifo`](crate::rayon::set_fifo)
+#[cfg(feature = "backend-rayon")]
+pub(crate) fn submit_rayon<T: Send + 'static>(task: T) -> io::Result<()> {
+    let job = move || drop(task);
+    let fifo = crate::rayon::fifo();
+    match crate::rayon::THREAD_POOL.get() {
+        Some(pool) if fifo => pool.spawn_fifo(job),
+        Some(pool) => pool.spawn(job),
+        None if fifo => rayon::spawn_fifo(job),
+        None => rayon::spawn(job),
+    }
+    Ok(())
+}
+
+/// Spawns a task onto `async-global-executor` to drop the task
+#[cfg(feature = "backend-async-global-executor")]
+fn submit_async_global_executor<T: Send + 'static>(task: T) -> io::Result<()> {
+    async_global_executor::spawn(async move { drop(task) }).detach();
+    Ok(())
+}
+
+/// Drops the task on `smol`'s blocking thread pool
+///
+/// `async-std` is unmaintained, so this uses `smol` to ease migrating away
+/// from it (see [`async_std`](crate::async_std))
+#[cfg(feature = "backend-async-std")]
+fn submit_async_std<T: Send + 'static>(task: T) -> io::Result<()> {
+    smol::unblock(move || drop(task)).detach();
+    Ok(())
+}
+
+/// Drops the task on `smol`'s blocking thread pool, or, if enabled with
+/// [`smol::set_spawn`](crate::smol::set_spawn), in a task spawned onto the
+/// executor given to [`smol::set_executor`](crate::smol::set_executor), or
+/// `smol`'s global executor if none was given
+#[cfg(feature = "backend-smol")]
+pub(crate) fn submit_smol<T: Send + 'static>(task: T) -> io::Result<()> {
+    if !crate::smol::spawn() {
+        smol::unblock(move || drop(task)).detach();
+        return Ok(());
+    }
+    let task = async move { drop(task) };
+    match crate::smol::EXECUTOR.get() {
+        Some(executor) => executor.spawn(task).detach(),
+        None => smol::spawn(task).detach(),
+    }
+    Ok(())
+}
+
+/// Submits the task to the spawner installed for the current thread (see
+/// [`spawn::set_local_spawner`](crate::spawn::set_local_spawner)), or the
+/// global one (see [`spawn::set_spawner`](crate::spawn::set_spawner)),
+/// dropping it inline if neither has been installed
+///
+/// If spawning fails, the task is dropped inline, and the error returned
+#[cfg(feature = "backend-spawn")]
+pub(crate) fn submit_spawn<T: Send + 'static>(task: T) -> io::Result<()> {
+    use futures_task::{FutureObj, LocalFutureObj};
+
+    use crate::spawn::{LOCAL_SPAWNER, SPAWNER};
+
+    let mut task = Some(task);
+    let local = LOCAL_SPAWNER.with_borrow(|local| {
+        let spawner = local.as_ref()?;
+        let task = task.take();
+        let future = LocalFutureObj::new(Box::new(async move { drop(task) }));
+        Some(spawner.spawn_local_obj(future))
+    });
+    if let Some(result) = local {
+        return result.map_err(io::Error::other);
+    }
+    match SPAWNER.get() {
+        Some(spawner) => {
+            let future = FutureObj::new(Box::new(async move { drop(task) }));
+            spawner.spawn_obj(future).map_err(io::Error::other)
+        },
+        // Dropped inline
+        None => Ok(()),
+    }
+}
+
+/// The runtime set by [`set_tokio_runtime`], if any
+#[cfg(feature = "backend-tokio")]
+static TOKIO_RUNTIME: OnceLock<tokio::runtime::Handle> = OnceLock::new();
+
+/// Sets the `tokio` runtime whose blocking thread pool handle closures are
+/// spawned onto
+///
+/// By default, closures are spawned onto the runtime of the thread dropping
+/// the handle, so handles can't be offloaded if they're dropped outside of
+/// one. Call this at startup with a handle to your runtime, and closures will
+/// always be spawned onto it, whichever thread drops the handle
+///
+/// This only affects handles dropped after it is called
+///
+/// ```no_run
+/// # async fn example() {
+/// // At the start of the application's async main
+/// let runtime = tokio::runtime::Handle::current();
+/// close_already::set_tokio_runtime(runtime).unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Only one runtime can be set. If one has already been set, `runtime` is
+/// returned back
+#[cfg(feature = "backend-tokio")]
+pub fn set_tokio_runtime(
+    runtime: tokio::runtime::Handle,
+) -> Result<(), tokio::runtime::Handle> {
+    TOKIO_RUNTIME.set(runtime)
+}
+
+/// Drops the task on the blocking thread pool of the runtime given to
+/// [`set_tokio_runtime`], or the current thread's runtime
+///
+/// Closing a handle blocks, so this uses `spawn_blocking` to keep it off the
+/// runtime's async worker threads.
+/// If neither runtime is available, the task is dropped inline, and an error
+/// returned. Spawned tasks are tracked for [`all_closed`](crate::all_closed())
+#[cfg(feature = "backend-tokio")]
+pub(crate) fn submit_tokio<T: Send + 'static>(task: T) -> io::Result<()> {
+    use tokio::runtime::Handle;
+
+    let runtime = match TOKIO_RUNTIME.get() {
+        Some(runtime) => runtime.clone(),
+        None => Handle::try_current().map_err(io::Error::other)?,
+    };
+    // Moved into the closure, so it's dropped even if the runtime shuts down
+    // before running it
+    let spawned = crate::all_closed::Spawned::new();
+    runtime.spawn_blocking(move || {
+        drop(task);
+        drop(spawned);
+    });
+    Ok(())
+}
+
+/// A backend that handle closures can be offloaded to, each enabled by its
+/// `backend-<name>` feature
+///
+/// Any number of backend features can be enabled at once (e.g. through
+/// feature unification in a workspace). The backend that's used is, in order
+/// of priority:
+/// 1. The one given to [`set_backend`]
+/// 2. The one named by the `CLOSE_ALREADY_BACKEND` environment variable (see
+///    [`name`](Backend::name)), if it's enabled
+/// 3. The first enabled backend, in the order they're declared here
+///
+/// The backend is chosen when the first handle is dropped, and can't be
+/// changed after that
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Closes handles synchronously on the thread dropping them, for
+    /// deterministic tests. This takes priority over every other backend
+    #[cfg(feature = "backend-sync")]
+    Sync,
+    /// `close_already`'s own thread pool
+    #[cfg(feature = "backend-threadpool")]
+    Threadpool,
+    /// A single dedicated thread, closing handles in order
+    #[cfg(feature = "backend-thread")]
+    Thread,
+    /// A new thread for each closure, up to a limit
+    #[cfg(feature = "backend-thread-per-close")]
+    ThreadPerClose,
+    /// `rayon`'s global thread pool, or the one given to
+    /// `rayon::set_thread_pool`
+    #[cfg(feature = "backend-rayon")]
+    Rayon,
+    /// `blocking`'s thread pool
+    #[cfg(feature = "backend-blocking")]
+    Blocking,
+    /// `tokio`'s blocking thread pool, either the one of the runtime given to
+    /// `set_tokio_runtime`, or the runtime entered by the thread dropping the
+    /// handle
+    #[cfg(feature = "backend-tokio")]
+    Tokio,
+    /// `smol`'s blocking thread pool, or its executor if enabled with
+    /// `smol::set_spawn`
+    #[cfg(feature = "backend-smol")]
+    Smol,
+    /// `async-global-executor`'s global executor
+    #[cfg(feature = "backend-async-global-executor")]
+    AsyncGlobalExecutor,
+    /// `smol`'s blocking thread pool, for the deprecated `async-std` backend
+    #[cfg(feature = "backend-async-std")]
+    AsyncStd,
+    /// Bevy's `IoTaskPool`, once it's been initialised
+    #[cfg(feature = "backend-bevy-tasks")]
+    BevyTasks,
+    /// The executor installed with `spawn::set_spawner`
+    #[cfg(feature = "backend-spawn")]
+    Spawn,
+    /// Closes handles inline, as if `close_already` wasn't being used.
+    /// This is used if no backend features are enabled
+    Inline,
+}
+
+impl Backend {
+    /// The enabled backends, in order of priority
+    pub const ENABLED: &'static [Backend] = &[
+        #[cfg(feature = "backend-sync")]
+        Backend::Sync,
+        #[cfg(feature = "backend-threadpool")]
+        Backend::Threadpool,
+        #[cfg(feature = "backend-thread")]
+        Backend::Thread,
+        #[cfg(feature = "backend-thread-per-close")]
+        Backend::ThreadPerClose,
+        #[cfg(feature = "backend-rayon")]
+        Backend::Rayon,
+        #[cfg(feature = "backend-blocking")]
+        Backend::Blocking,
+        #[cfg(feature = "backend-tokio")]
+        Backend::Tokio,
+        #[cfg(feature = "backend-smol")]
+        Backend::Smol,
+        #[cfg(feature = "backend-async-global-executor")]
+        Backend::AsyncGlobalExecutor,
+        #[cfg(feature = "backend-async-std")]
+        Backend::AsyncStd,
+        #[cfg(feature = "backend-bevy-tasks")]
+        Backend::BevyTasks,
+        #[cfg(feature = "backend-spawn")]
+        Backend::Spawn,
+        Backend::Inline,
+    ];
+
+    /// The backend that handle closures are submitted to, choosing it if
+    /// that hasn't happened yet
+    pub fn active() -> Backend {
+        *ACTIVE.get_or_init(|| {
+            std::env::var(BACKEND_VAR)
+                .ok()
+                .and_then(|name| Backend::from_name(&name))
+                .unwrap_or(Backend::ENABLED[0])
+        })
+    }
+
+    /// The backend's name, as in its `backend-<name>` feature, and as used
+    /// in the `CLOSE_ALREADY_BACKEND` environment variable. `Inline` is
+    /// named `inline`
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "backend-sync")]
+            Backend::Sync => "sync",
+            #[cfg(feature = "backend-threadpool")]
+            Backend::Threadpool => "threadpool",
+            #[cfg(feature = "backend-thread")]
+            Backend::Thread => "thread",
+            #[cfg(feature = "backend-thread-per-close")]
+            Backend::ThreadPerClose => "thread-per-close",
+            #[cfg(feature = "backend-rayon")]
+            Backend::Rayon => "rayon",
+            #[cfg(feature = "backend-blocking")]
+            Backend::Blocking => "blocking",
+            #[cfg(feature = "backend-tokio")]
+            Backend::Tokio => "tokio",
+            #[cfg(feature = "backend-smol")]
+            Backend::Smol => "smol",
+            #[cfg(feature = "backend-async-global-executor")]
+            Backend::AsyncGlobalExecutor => "async-global-executor",
+            #[cfg(feature = "backend-async-std")]
+            Backend::AsyncStd => "async-std",
+            #[cfg(feature = "backend-bevy-tasks")]
+            Backend::BevyTasks => "bevy-tasks",
+            #[cfg(feature = "backend-spawn")]
+            Backend::Spawn => "spawn",
+            Backend::Inline => "inline",
+        }
+    }
+
+    /// Finds the enabled backend with the given [`name`](Backend::name)
+    pub fn from_name(name: &str) -> Option<Backend> {
+        Backend::ENABLED
+            .iter()
+            .copied()
+            .find(|backend| backend.name() == name)
+    }
+}
+
+/// The environment variable naming the backend to use, if
+/// [`set_backend`] isn't called
+const BACKEND_VAR: &str = "CLOSE_ALREADY_BACKEND";
+
+/// The backend chosen by [`set_backend`] or [`Backend::active`]
+static ACTIVE: OnceLock<Backend> = OnceLock::new();
+
+/// Sets the backend that handle closures are submitted to, instead of
+/// choosing one automatically (see [`Backend`])
+///
+/// Call this at startup, before any handles are dropped
+///
+/// # Errors
+///
+/// The backend can only be chosen once. If it has already been chosen, the
+/// backend in use is returned
+pub fn set_backend(backend: Backend) -> Result<(), Backend> {
+    let mut set = false;
+    let active = *ACTIVE.get_or_init(|| {
+        set = true;
+        backend
+    });
+    match set {
+        true => Ok(()),
+        false => Err(active),
+    }
+}
+
+/// Starts up the [active](Backend::active) backend's threads ahead of time,
+/// so the first handles dropped don't have to wait for them
+///
+/// For the `threadpool` backend, this creates the pool if it hasn't been
+/// already, and makes sure up to `workers` of its worker threads are running
+/// (e.g. if they've exited after being idle, see `PoolConfig::idle_timeout`).
+/// The `thread` backend's thread, and `rayon`'s global thread pool (unless
+/// another has been set), are started regardless of `workers`. Other backends'
+/// threads are managed by their runtimes, so aren't affected
+///
+/// This chooses the backend if it hasn't been already, so call it after
+/// [`set_backend`]
+#[cfg_attr(not(feature = "backend-threadpool"), allow(unused_variables))]
+pub fn warm_up(workers: usize) {
+    match Backend::active() {
+        #[cfg(feature = "backend-threadpool")]
+        Backend::Threadpool => closer_pool().warm_up(workers),
+        #[cfg(feature = "backend-thread")]
+        Backend::Thread => closer_thread(),
+        #[cfg(feature = "backend-rayon")]
+        Backend::Rayon if crate::rayon::THREAD_POOL.get().is_none() => {
+            rayon::current_num_threads();
+        },
+        _ => {},
+    }
+}
+
+/// How urgently a handle should be closed, relative to the others waiting
+/// to be closed
+///
+/// Use this to close handles that are holding up something else (e.g. a
+/// file that's about to be renamed) before ones that are just being cleaned
+/// up. Only the `threadpool` and `thread` backends queue handles by
+/// priority, other backends and [`Closer`]s treat every handle the same
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// Closed before any other handles waiting to be closed
+    High,
+    /// The priority of handles that haven't been given one
+    #[default]
+    Normal,
+    /// Only closed once there are no other handles waiting to be closed
+    Low,
+}
+
+/// Submits the task to the [active](Backend::active) backend to be dropped,
+/// ahead of or behind others in its queue depending on its `priority`
+#[cfg_attr(
+    not(any(feature = "backend-threadpool", feature = "backend-thread")),
+    allow(unused_variables)
+)]
+pub(crate) fn submit<T: Send + 'static>(
+    task: T,
+    priority: Priority,
+) -> io::Result<()> {
+    match Backend::active() {
+        #[cfg(feature = "backend-threadpool")]
+        Backend::Threadpool => submit_threadpool(task, priority),
+        #[cfg(feature = "backend-thread")]
+        Backend::Thread => submit_thread(task, priority),
+        #[cfg(feature = "backend-thread-per-close")]
+        Backend::ThreadPerClose => submit_thread_per_close(task),
+        #[cfg(feature = "backend-rayon")]
+        Backend::Rayon => submit_rayon(task),
+        #[cfg(feature = "backend-blocking")]
+        Backend::Blocking => submit_blocking(task),
+        #[cfg(feature = "backend-tokio")]
+        Backend::Tokio => submit_tokio(task),
+        #[cfg(feature = "backend-smol")]
+        Backend::Smol => submit_smol(task),
+        #[cfg(feature = "backend-async-global-executor")]
+        Backend::AsyncGlobalExecutor => submit_async_global_executor(task),
+        #[cfg(feature = "backend-async-std")]
+        Backend::AsyncStd => submit_async_std(task),
+        #[cfg(feature = "backend-bevy-tasks")]
+        Backend::BevyTasks => submit_bevy_tasks(task),
+        #[cfg(feature = "backend-spawn")]
+        Backend::Spawn => submit_spawn(task),
+        #[cfg(feature = "backend-sync")]
+        Backend::Sync => {
+            drop(task);
+            Ok(())
+        },
+        Backend::Inline => {
+            drop(task);
+            Ok(())
+        },
+    }
+}
+
+/// Gets details of the [active](Backend::active) backend's threads and
+/// queue
+pub(crate) fn stats() -> BackendStats {
+    let backend = Backend::active();
+    let (threads, queued) = match backend {
+        #[cfg(feature = "backend-threadpool")]
+        Backend::Threadpool => {
+            CLOSER_POOL.get().map_or((Some(0), Some(0)), |pool| {
+                (Some(pool.workers()), Some(pool.queue_len()))
+            })
+        },
+        #[cfg(feature = "backend-thread")]
+        Backend::Thread => {
+            let queued = CLOSER_QUEUES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .map(VecDeque::len)
+                .sum();
+            (
+                Some(usize::from(CLOSER_THREAD.is_completed())),
+                Some(queued),
+            )
+        },
+        #[cfg(feature = "backend-thread-per-close")]
+        Backend::ThreadPerClose => {
+            (Some(CLOSE_THREADS.load(Ordering::Relaxed)), None)
+        },
+        _ => (None, None),
+    };
+    BackendStats {
+        name: backend.name(),
+        threads,
+        queued,
+    }
+}
+
+/// Submits the handle to the global [`Closer`] if one has been set, or the
+/// active backend otherwise, to handle its closure
+#[cfg(test)]
+pub(crate) fn close<H: FastCloseable + 'static>(handle: H) {
+    close_on(None, Priority::Normal, None, None, handle);
+}
+
+/// Submits the handle to `closer`, falling back to the global [`Closer`] if
+/// one has been set, or the active backend otherwise, if it's `None`. The
+/// handle is tracked as part of the current thread's [`scope`](crate::scope()),
+/// if any, and `label`led in reports about it, along with its `origin`
+pub(crate) fn close_on<H: FastCloseable + 'static>(
+    closer: Option<&dyn Closer>,
+    priority: Priority,
+    label: Option<Cow<'static, str>>,
+    origin: Option<Origin>,
+    handle: H,
+) {
+    crate::pending::submitted();
+    #[cfg(all(windows, feature = "etw"))]
+    crate::etw::submitted::<H>(label.as_deref());
+    dispatch(closer, priority, label, origin, handle);
+}
+
+/// Sends a handle to the given, or global, [`Closer`], or the active backend
+/// (via the [`batch`](crate::batch)er, if enabled, unless it's urgent)
+fn dispatch<H: FastCloseable + 'static>(
+    closer: Option<&dyn Closer>,
+    priority: Priority,
+    label: Option<Cow<'static, str>>,
+    origin: Option<Origin>,
+    handle: H,
+) {
+    let closer = closer.or_else(crate::closer::global);
+    match closer {
+        Some(closer) => {
+            let submitted = label.clone();
+            let finish = Finish::new(handle, label, origin, true);
+            closer.submit_labeled(
+                submitted.as_deref(),
+                Box::new(move || drop(finish)),
+            );
+        },
+        None if priority != Priority::High && crate::batch::enabled() => {
+            let finish = Finish::new(handle, label, origin, false);
+            crate::batch::push(Box::new(finish));
+        },
+        None => {
+            let finish = Finish::new(handle, label.clone(), origin, false);
+            if let Err(why) = submit(finish, priority) {
+                crate::error::report_handle::<H>(label, why);
+            }
+        },
+    }
+}
+
+/// Submits jobs to the [active](Backend::active) backend
+///
+/// This is what's used when no global closer has been set with
+/// [`set_global_closer`](crate::set_global_closer). Use it to fall back to
+/// the built-in backend from your own [`Closer`]
+#[derive(Debug, Default, Clone, Copy)]
+#[non_exhaustive]
+pub struct DefaultCloser;
+
+impl Closer for DefaultCloser {
+    fn submit(&self, job: Box<dyn FnOnce() + Send>) {
+        if let Err(why) = submit(RunOnDrop(Some(job)), Priority::Normal) {
+            crate::error::report_handle::<dyn FnOnce() + Send>(None, why);
+        }
+    }
+}
+
+/// A job that's run when it's dropped, so that backends only need to drop
+/// what they're given
+struct RunOnDrop(Option<Box<dyn FnOnce() + Send>>);
+
+impl Drop for RunOnDrop {
+    fn drop(&mut self) {
+        if let Some(job) = self.0.take() {
+            job();
+        }
+    }
+}
+
+/// A handle that's been submitted to be closed, which, when dropped, closes
+/// it and records its closure. It's timed if enabled (see
+/// [`bench_support`](crate::bench_support)), needed for
+/// [`set_close_deadline`](crate::set_close_deadline) or the `etw`,
+/// `histogram`, `metrics`, and `tracing` features, or there are subscribers
+/// to the `events` stream
+///
+/// As the bookkeeping is done on drop, it happens even if a backend or
+/// [`Closer`] drops the job without running it. Errors closing the handle
+/// are sent to the [error sink](crate::set_error_sink), and panics are caught
+/// and reported, so the bookkeeping is always done
+struct Finish<H: FastCloseable + 'static> {
+    handle: Option<H>,
+    /// The handle's label, if it has one
+    label: Option<Cow<'static, str>>,
+    /// The handle's entry in the [`watchdog`](crate::watchdog), removed once
+    /// it's been closed and this is dropped
+    _watch: Option<crate::watchdog::Watch>,
+    /// Counts the handle as part of the [`scope`](crate::scope()) it was
+    /// dropped in, if any, until it's been closed and this is dropped
+    _scope: Option<crate::group::Counted<()>>,
+    /// The span the handle is closed in
+    #[cfg(feature = "tracing")]
+    span: ::tracing::Span,
+}
+
+impl<H: FastCloseable + 'static> Finish<H> {
+    /// Wraps a handle that's about to be submitted to a [`Closer`], if
+    /// `closer`, or the active backend, tracking it as part of the current
+    /// thread's [`scope`](crate::scope()), if any
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn new(
+        handle: H,
+        label: Option<Cow<'static, str>>,
+        origin: Option<Origin>,
+        closer: bool,
+    ) -> Self {
+        Finish {
+            handle: Some(handle),
+            #[cfg(feature = "tracing")]
+            span: crate::tracing::submitted::<H>(label.as_deref(), closer),
+            _watch: crate::watchdog::watch::<H>(label.as_ref(), origin),
+            _scope: crate::scope::current().map(|scope| scope.track(())),
+            label,
+        }
+    }
+}
+
+impl<H: FastCloseable + 'static> Drop for Finish<H> {
+    fn drop(&mut self) {
+        let started = crate::bench_support::begin();
+        let deadline_started = crate::deadline::start();
+        #[cfg(feature = "events")]
+        let event_started = crate::events::start();
+        #[cfg(any(
+            all(windows, feature = "etw"),
+            feature = "histogram",
+            feature = "metrics",
+            feature = "tracing",
+        ))]
+        let close_started = std::time::Instant::now();
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        let handle = self.handle.take();
+        let label = self.label.take();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(handle) = handle {
+                crate::error::close(handle, label.as_ref());
+            }
+        }))
+        .map_err(|payload| {
+            crate::error::report_panic::<H>(label.clone(), payload)
+        });
+        #[cfg(feature = "tracing")]
+        crate::tracing::closed(close_started.elapsed(), result.is_err());
+        #[cfg(all(windows, feature = "etw"))]
+        crate::etw::closed::<H>(
+            label.as_deref(),
+            close_started.elapsed(),
+            result.is_err(),
+        );
+        #[cfg(feature = "events")]
+        crate::events::publish::<H>(event_started, label, result);
+        #[cfg(not(feature = "events"))]
+        let _ = label;
+        #[cfg(not(feature = "events"))]
+        let _ = result;
+        #[cfg(feature = "histogram")]
+        crate::stats::record_duration(close_started.elapsed());
+        crate::deadline::closed(deadline_started);
+        crate::bench_support::closed(started);
+        crate::pending::closed();
+        #[cfg(feature = "metrics")]
+        crate::metrics::closed(close_started.elapsed());
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    #[test]
+    #[cfg(feature = "backend-thread")]
+    fn thread_closes_in_order() {
+        use std::sync::{mpsc, Mutex};
+
+        use super::{submit_thread as submit, Priority};
+
+        static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+        struct Handle(usize);
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                ORDER.lock().unwrap().push(self.0);
+            }
+        }
+
+        for n in 0..100 {
+            submit(Handle(n), Priority::Normal).unwrap();
+        }
+        // The channel disconnects once the closer thread drops the sender
+        let (sender, receiver) = mpsc::channel::<()>();
+        submit(sender, Priority::Normal).unwrap();
+        assert!(receiver.recv().is_err());
+        assert_eq!(*ORDER.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "backend-thread")]
+    fn thread_survives_panicking_task() {
+        use std::sync::mpsc;
+
+        use super::{submit_thread as submit, Priority};
+
+        struct Panics;
+
+        impl Drop for Panics {
+            fn drop(&mut self) {
+                panic!("expected panic");
+            }
+        }
+
+        submit(Panics, Priority::Normal).unwrap();
+        let (sender, receiver) = mpsc::channel::<()>();
+        submit(sender, Priority::Normal).unwrap();
+        assert!(receiver.recv().is_err(), "task should be dropped");
+    }
+
+    #[test]
+    #[cfg(feature = "backend-sync")]
+    fn sync_closes_before_returning() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use super::{close, Backend};
+
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        struct Handle;
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                CLOSED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        impl crate::FastCloseable for Handle {}
+
+        assert_eq!(Backend::active(), Backend::Sync);
+        close(Handle);
+        assert!(CLOSED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[cfg(feature = "backend-thread-per-close")]
+    fn thread_per_close_frees_slots() {
+        use std::sync::{atomic::Ordering, mpsc};
+
+        use super::{submit_thread_per_close as submit, CLOSE_THREADS};
+
+        // The channels disconnect once the close threads drop the senders
+        let receivers = (0..4)
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel::<()>();
+                submit(sender).unwrap();
+                receiver
+            })
+            .collect::<Vec<_>>();
+        for receiver in receivers {
+            assert!(receiver.recv().is_err());
+        }
+        while CLOSE_THREADS.load(Ordering::SeqCst) > 0 {
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "backend-bevy-tasks")]
+    fn bevy_tasks_closes_on_io_pool() {
+        use std::sync::mpsc;
+
+        use bevy_tasks::{IoTaskPool, TaskPool};
+
+        use super::submit_bevy_tasks as submit;
+
+        IoTaskPool::get_or_init(TaskPool::new);
+        // The channel disconnects once the pool drops the sender
+        let (sender, receiver) = mpsc::channel::<()>();
+        submit(sender).unwrap();
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn backend_names_round_trip() {
+        use super::Backend;
+
+        for backend in Backend::ENABLED {
+            assert_eq!(Backend::from_name(backend.name()), Some(*backend));
+        }
+        assert_eq!(Backend::from_name("inline"), Some(Backend::Inline));
+        assert_eq!(Backend::from_name("nonsense"), None);
+    }
+
+    #[test]
+    fn finish_catches_panics() {
+        struct Panics;
+
+        impl Drop for Panics {
+            fn drop(&mut self) {
+                panic!("expected panic");
+            }
+        }
+
+        impl crate::FastCloseable for Panics {}
+
+        let _reporting = crate::error::tests::lock_reporting();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        crate::set_panic_handler(move |panic| {
+            let _ = sender.send(panic);
+        });
+        crate::pending::submitted();
+        drop(super::Finish::new(Panics, None, None, false));
+        crate::clear_panic_handler();
+
+        let panic = receiver.try_recv().expect("panic should be reported");
+        assert_eq!(panic.message.as_deref(), Some("expected panic"));
+    }
+}
@@ -0,0 +1,255 @@
+//! Coalescing dropped handles into batches, so the backend runs one job per
+//! batch rather than per handle
+use std::{
+    mem,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::Priority;
+
+/// Whether batching is enabled, checked before locking the batcher
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The batcher used by all dropped handles
+static BATCHER: Batcher = Batcher::new();
+
+/// How dropped handles are batched together, given to [`set_batching`]
+///
+/// ```no_run
+/// use std::{num::NonZeroUsize, time::Duration};
+///
+/// use close_already::Batching;
+///
+/// let batching = Batching::new(NonZeroUsize::new(64).unwrap())
+///     .max_delay(Duration::from_millis(2));
+/// close_already::set_batching(Some(batching));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Batching {
+    max_size: NonZeroUsize,
+    max_delay: Duration,
+}
+
+impl Batching {
+    /// Batches up to `max_size` handles together, waiting at most 1ms for a
+    /// batch to fill up
+    pub fn new(max_size: NonZeroUsize) -> Self {
+        Batching {
+            max_size,
+            max_delay: Duration::from_millis(1),
+        }
+    }
+
+    /// Sets how long a batch can wait to fill up before it's submitted
+    /// anyway, bounding how long a handle can be held before it's closed
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+}
+
+/// Enables batching dropped handles together, or disables it if `None`
+///
+/// Normally, each handle dropped is submitted to the backend as its own job.
+/// When dropping lots of handles to small files, the cost of scheduling the
+/// jobs can rival that of closing the handles. With batching enabled,
+/// handles are collected into batches, which are submitted to the backend
+/// once they're full, or when the oldest handle in them has waited for the
+/// batch's maximum delay. A background thread is started to submit batches
+/// that don't fill up in time
+///
+/// Handles sent to a [`Closer`](crate::Closer), or with a
+/// [`Priority::High`], aren't batched. Use
+/// [`FastCloseGroup`](crate::FastCloseGroup) to close a known set of handles
+/// together without any delay
+///
+/// This can be changed at any time. Disabling batching submits any partial
+/// batch straight away
+pub fn set_batching(batching: Option<Batching>) {
+    BATCHER.set(batching);
+    ENABLED.store(batching.is_some(), Ordering::SeqCst);
+}
+
+/// Whether handles should be [`push`]ed to be batched
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Adds a handle, boxed to be dropped by the backend, to the current batch
+pub(crate) fn push(job: Box<dyn Send>) {
+    BATCHER.push(job);
+}
+
+/// Collects jobs into batches, with a thread to submit batches that have
+/// waited for too long
+struct Batcher {
+    state: Mutex<State>,
+    /// Notified when a batch is started, or batching is changed
+    changed: Condvar,
+}
+
+struct State {
+    batching: Option<Batching>,
+    jobs: Vec<Box<dyn Send>>,
+    /// When the first job in the current batch was pushed, if there is one
+    started: Option<Instant>,
+    /// Whether the thread submitting overdue batches has been spawned
+    flusher: bool,
+}
+
+impl Batcher {
+    const fn new() -> Self {
+        Batcher {
+            state: Mutex::new(State {
+                batching: None,
+                jobs: Vec::new(),
+                started: None,
+                flusher: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set(&self, batching: Option<Batching>) {
+        self.lock().batching = batching;
+        self.changed.notify_all();
+    }
+
+    fn push(&'static self, job: Box<dyn Send>) {
+        let mut state = self.lock();
+        let Some(batching) = state.batching else {
+            // Batching was disabled after the caller checked
+            drop(state);
+            submit(vec![job]);
+            return;
+        };
+        state.jobs.push(job);
+        if state.jobs.len() >= batching.max_size.get() {
+            let batch = state.take();
+            drop(state);
+            submit(batch);
+        } else if state.started.is_none() {
+            state.started = Some(Instant::now());
+            if !mem::replace(&mut state.flusher, true) {
+                thread::Builder::new()
+                    .name(String::from("close_already-batcher"))
+                    .spawn(move || self.flush_overdue())
+                    .expect("failed to spawn close_already batcher thread");
+            }
+            drop(state);
+            self.changed.notify_all();
+        }
+    }
+
+    /// Submits batches once they've waited for the maximum delay, forever
+    fn flush_overdue(&self) {
+        let mut state = self.lock();
+        loop {
+            let Some(started) = state.started else {
+                state = self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            };
+            let max_delay = state
+                .batching
+                .map_or(Duration::ZERO, |batching| batching.max_delay);
+            match max_delay.checked_sub(started.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => {
+                    state = self
+                        .changed
+                        .wait_timeout(state, remaining)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                },
+                _ => {
+                    let batch = state.take();
+                    drop(state);
+                    submit(batch);
+                    state = self.lock();
+                },
+            }
+        }
+    }
+}
+
+impl State {
+    /// Takes the current batch, ready to start a new one
+    fn take(&mut self) -> Vec<Box<dyn Send>> {
+        self.started = None;
+        mem::take(&mut self.jobs)
+    }
+}
+
+/// Submits a batch to the backend, which drops every job in it together
+fn submit(batch: Vec<Box<dyn Send>>) {
+    #[cfg(feature = "log")]
+    crate::log::batch(batch.len());
+    if let Err(why) = crate::backend::submit(batch, Priority::Normal) {
+        crate::error::report_handle::<Vec<Box<dyn Send>>>(None, why);
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{
+        sync::mpsc,
+        thread::{self, ThreadId},
+    };
+
+    use super::*;
+
+    /// Sends the thread it's dropped on
+    struct Handle(mpsc::Sender<ThreadId>);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            self.0.send(thread::current().id()).unwrap();
+        }
+    }
+
+    fn batcher(batching: Batching) -> &'static Batcher {
+        let batcher: &'static Batcher = Box::leak(Box::new(Batcher::new()));
+        batcher.set(Some(batching));
+        batcher
+    }
+
+    #[test]
+    fn submits_full_batches_together() {
+        let two = NonZeroUsize::new(2).unwrap();
+        let batcher =
+            batcher(Batching::new(two).max_delay(Duration::from_secs(3600)));
+        let (sender, receiver) = mpsc::channel();
+        batcher.push(Box::new(Handle(sender.clone())));
+        assert!(receiver.try_recv().is_err(), "batch isn't full yet");
+        batcher.push(Box::new(Handle(sender)));
+        assert_eq!(
+            receiver.recv().unwrap(),
+            receiver.recv().unwrap(),
+            "batch should be dropped together"
+        );
+    }
+
+    #[test]
+    fn submits_overdue_batches() {
+        let ten = NonZeroUsize::new(10).unwrap();
+        let batcher =
+            batcher(Batching::new(ten).max_delay(Duration::from_millis(5)));
+        let (sender, receiver) = mpsc::channel();
+        batcher.push(Box::new(Handle(sender)));
+        receiver
+            .recv()
+            .expect("batch should be submitted after delay");
+    }
+}
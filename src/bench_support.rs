@@ -0,0 +1,221 @@
+//! Timing hooks for benchmarking `close_already`
+//!
+//! Closures happen in the background, so a benchmark timing your code can
+//! only see how long it took to hand handles off to the backend, not how long
+//! the backend then took to close them. When enabled, these hooks record both,
+//! which lets a regression be pinned on submission or closing specifically
+//!
+//! Timings are only recorded on Windows, as handles are closed inline
+//! elsewhere
+//!
+//! For totals, use [`enable`] and [`timings`]:
+//!
+//! ```no_run
+//! use close_already::{bench_support, fs};
+//!
+//! bench_support::enable(true);
+//! fs::write("hello.txt", b"Hello, world!").unwrap();
+//! let timings = bench_support::timings();
+//! println!(
+//!     "{} handles took {:?} to close",
+//!     timings.closed, timings.close_time
+//! );
+//! ```
+//!
+//! To also get the time each handle took to close, such as to measure how
+//! much `close_already` helps your own workload from your own benchmarks,
+//! use [`start`] and [`stop`]:
+//!
+//! ```no_run
+//! use std::time::{Duration, Instant};
+//!
+//! use close_already::{bench_support, fs};
+//!
+//! # fn my_workload() {}
+//! bench_support::start();
+//! let started = Instant::now();
+//! my_workload();
+//! let elapsed = started.elapsed();
+//! // Closures happen in the background, so wait for them to finish
+//! while !bench_support::timings().is_idle() {
+//!     std::thread::yield_now();
+//! }
+//! let recording = bench_support::stop();
+//! let slowest = recording.close_times.iter().max();
+//! println!("took {elapsed:?}, the slowest closure took {slowest:?}");
+//! ```
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// Whether timings are being recorded
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether individual closure times are being recorded, by [`start`]
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+/// The individual closure times recorded since [`start`]
+static CLOSE_TIMES: Mutex<Vec<Duration>> = Mutex::new(Vec::new());
+
+static SUBMITTED: AtomicU64 = AtomicU64::new(0);
+static SUBMIT_NANOS: AtomicU64 = AtomicU64::new(0);
+static CLOSED: AtomicU64 = AtomicU64::new(0);
+static CLOSE_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Totals of the time spent submitting and closing handles, since timings
+/// were last [`reset`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Timings {
+    /// The number of handles submitted to the backend
+    pub submitted: u64,
+    /// The total time spent submitting handles to the backend, i.e. the time
+    /// spent dropping `FastClose`s
+    pub submit_time: Duration,
+    /// The number of handles the backend has closed
+    pub closed: u64,
+    /// The total time the backend spent closing handles
+    pub close_time: Duration,
+}
+
+impl Timings {
+    /// Whether every handle submitted has been closed
+    pub fn is_idle(&self) -> bool {
+        self.submitted == self.closed
+    }
+}
+
+/// Starts or stops recording timings
+///
+/// Recording is disabled by default. Disabling it doesn't reset the timings
+/// recorded so far
+pub fn enable(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Gets the timings recorded since the last [`reset`]
+pub fn timings() -> Timings {
+    Timings {
+        submitted: SUBMITTED.load(Ordering::Relaxed),
+        submit_time: Duration::from_nanos(SUBMIT_NANOS.load(Ordering::Relaxed)),
+        closed: CLOSED.load(Ordering::Relaxed),
+        close_time: Duration::from_nanos(CLOSE_NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Sets all the timings back to zero
+///
+/// Handles that are still being closed when this is called will be counted
+/// as closed but not submitted
+pub fn reset() {
+    for total in [&SUBMITTED, &SUBMIT_NANOS, &CLOSED, &CLOSE_NANOS] {
+        total.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Timings recorded between [`start`] and [`stop`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Recording {
+    /// The totals recorded
+    pub timings: Timings,
+    /// How long each handle took to close, in the order they finished
+    pub close_times: Vec<Duration>,
+}
+
+/// Resets the timings and starts recording them, including how long each
+/// individual handle takes to close
+pub fn start() {
+    reset();
+    close_times().clear();
+    RECORDING.store(true, Ordering::Relaxed);
+    enable(true);
+}
+
+/// Stops recording timings, returning everything recorded since [`start`]
+///
+/// This doesn't wait for handles to finish closing, so any still being closed
+/// in the background won't be included. Wait for [`Timings::is_idle`] first
+/// if you want them to be
+pub fn stop() -> Recording {
+    enable(false);
+    RECORDING.store(false, Ordering::Relaxed);
+    Recording {
+        timings: timings(),
+        close_times: mem::take(&mut *close_times()),
+    }
+}
+
+/// Locks the individual closure times, ignoring poisoning as they can't be
+/// left inconsistent
+fn close_times() -> MutexGuard<'static, Vec<Duration>> {
+    CLOSE_TIMES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Gets the time an operation started, if timings are being recorded
+#[inline]
+pub(crate) fn begin() -> Option<Instant> {
+    ENABLED.load(Ordering::Relaxed).then(Instant::now)
+}
+
+/// Records a handle as having been submitted, if timings are being recorded
+#[inline]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn submitted(started: Option<Instant>) {
+    if let Some(started) = started {
+        record(&SUBMITTED, &SUBMIT_NANOS, started);
+    }
+}
+
+/// Records a handle as having been closed, if timings are being recorded
+#[inline]
+pub(crate) fn closed(started: Option<Instant>) {
+    if let Some(started) = started {
+        let elapsed = record(&CLOSED, &CLOSE_NANOS, started);
+        if RECORDING.load(Ordering::Relaxed) {
+            close_times().push(elapsed);
+        }
+    }
+}
+
+/// Adds to a count and total time, returning the time elapsed
+fn record(count: &AtomicU64, nanos: &AtomicU64, started: Instant) -> Duration {
+    let elapsed = started.elapsed();
+    count.fetch_add(1, Ordering::Relaxed);
+    nanos.fetch_add(
+        elapsed.as_nanos().try_into().unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+    elapsed
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+
+    // Timings are global, so these are tested together to avoid interference
+    #[test]
+    fn records_when_enabled() {
+        enable(true);
+        let started = begin();
+        assert!(started.is_some());
+        submitted(started);
+        closed(begin());
+        let recorded = timings();
+        assert!(recorded.submitted >= 1);
+        assert!(recorded.closed >= 1);
+
+        start();
+        closed(begin());
+        closed(begin());
+        let recording = stop();
+        assert!(recording.timings.closed >= 2);
+        assert!(recording.close_times.len() >= 2);
+        assert!(begin().is_none(), "stop should disable timings");
+    }
+}
@@ -0,0 +1,15 @@
+//! Configuration for the `blocking` backend
+use std::num::NonZeroUsize;
+
+/// Sets the maximum number of threads in `blocking`'s thread pool
+///
+/// By default, `blocking` uses up to 500 threads, or the value of the
+/// `BLOCKING_MAX_THREADS` environment variable if it's set when the pool is
+/// first used. This can be called at any time, with the new limit taking
+/// effect immediately (values above 10,000 are capped by `blocking`)
+///
+/// Note that the thread pool is shared with everything else in your program
+/// that uses `blocking` (e.g. `smol::unblock`)
+pub fn configure(max_threads: NonZeroUsize) {
+    ::blocking::set_max_blocking_threads(max_threads);
+}
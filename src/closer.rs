@@ -0,0 +1,100 @@
+//! Installing your own executor to close handles on, at runtime
+use std::sync::OnceLock;
+
+/// The closer set by [`set_global_closer`], if any
+static GLOBAL: OnceLock<Box<dyn Closer>> = OnceLock::new();
+
+/// Something that can run the jobs that close handles, such as an executor or
+/// thread pool
+///
+/// By default, handles are closed on the backend chosen by feature flag.
+/// Applications can instead install their own closer at startup with
+/// [`set_global_closer`], so libraries using `close_already` don't need to
+/// pick a runtime for them. The built-in backend is available as
+/// [`DefaultCloser`](crate::DefaultCloser)
+///
+/// ```
+/// use close_already::Closer;
+///
+/// /// Closes handles on a new thread each
+/// struct ThreadPerClose;
+///
+/// impl Closer for ThreadPerClose {
+///     fn submit(&self, job: Box<dyn FnOnce() + Send>) {
+///         std::thread::spawn(job);
+///     }
+/// }
+///
+/// # let _ =
+/// close_already::set_global_closer(ThreadPerClose);
+/// ```
+pub trait Closer: Send + Sync {
+    /// Arranges for `job` to be run, usually on another thread
+    ///
+    /// Running the job closes the handle. If the job is dropped without
+    /// being run (e.g. because the executor is shutting down), the handle is
+    /// closed wherever it's dropped, so it's never leaked
+    fn submit(&self, job: Box<dyn FnOnce() + Send>);
+
+    /// Arranges for `job` to be run, like [`submit`](Closer::submit), for a
+    /// handle with the given label (see
+    /// [`FastClose::with_label`](crate::FastClose::with_label))
+    ///
+    /// This is what's called for every handle, so closers can use the label,
+    /// e.g. to name the task. By default, it ignores the label and calls
+    /// [`submit`](Closer::submit)
+    fn submit_labeled(
+        &self,
+        label: Option<&str>,
+        job: Box<dyn FnOnce() + Send>,
+    ) {
+        let _ = label;
+        self.submit(job);
+    }
+}
+
+/// Sets the closer that handle closures are submitted to, instead of the
+/// backend chosen by feature flag
+///
+/// This only affects handles dropped after it is called
+///
+/// # Errors
+///
+/// Only one closer can be set. If one has already been set, `closer` is
+/// returned back
+pub fn set_global_closer<C: Closer + 'static>(closer: C) -> Result<(), C> {
+    let mut closer = Some(closer);
+    GLOBAL.get_or_init(|| Box::new(closer.take().unwrap()));
+    match closer {
+        None => Ok(()),
+        Some(closer) => Err(closer),
+    }
+}
+
+/// The closer set by [`set_global_closer`], if any
+///
+/// This is generic over the lifetime so it can be used as a fallback for
+/// shorter-lived closers
+pub(crate) fn global<'a>() -> Option<&'a dyn Closer> {
+    GLOBAL.get().map(Box::as_ref)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    // Tokio's tasks can't be spawned outside of a runtime, and the smol
+    // tests install an executor that isn't run in the background
+    #[test]
+    #[cfg(not(any(feature = "backend-smol", feature = "backend-tokio")))]
+    fn default_closer_runs_jobs() {
+        use std::{sync::mpsc, time::Duration};
+
+        use super::Closer;
+        use crate::DefaultCloser;
+
+        let (sender, receiver) = mpsc::channel();
+        DefaultCloser.submit(Box::new(move || sender.send(()).unwrap()));
+        receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("job should be run");
+    }
+}
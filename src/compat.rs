@@ -0,0 +1,188 @@
+//! Cross-platform equivalents of platform-specific extension traits, so code
+//! using [`FastClose`] doesn't need to be conditionally compiled for each OS
+#[cfg(windows)]
+use std::os::windows::{
+    fs::FileExt as PlatformFileExt,
+    io::{FromRawHandle, IntoRawHandle},
+};
+#[cfg(unix)]
+use std::os::{fd::IntoRawFd, unix::fs::FileExt as PlatformFileExt};
+use std::{
+    fs::File,
+    io,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{FastClose, FastCloseable};
+
+/// Positional IO, implemented using Unix's
+/// [`FileExt`](https://doc.rust-lang.org/std/os/unix/fs/trait.FileExt.html)
+/// or Windows'
+/// [`FileExt`](https://doc.rust-lang.org/std/os/windows/fs/trait.FileExt.html)
+///
+/// # Platform-specific behaviour
+///
+/// On Windows, reading or writing at an offset also moves the file's cursor
+/// to the end of the read/write, whereas on Unix the cursor is left where it
+/// was. Don't mix positional IO with cursor-based IO if you rely on either
+pub trait FileExt {
+    /// Reads a number of bytes starting from a given offset, returning the
+    /// number of bytes read
+    ///
+    /// Reading beyond the end of the file will always return 0 bytes read
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Writes a number of bytes starting from a given offset, returning the
+    /// number of bytes written
+    ///
+    /// Writing beyond the end of the file will extend it
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+}
+
+impl FileExt for File {
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        PlatformFileExt::read_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        PlatformFileExt::seek_read(self, buf, offset)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        PlatformFileExt::write_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        PlatformFileExt::seek_write(self, buf, offset)
+    }
+}
+
+impl<H> FileExt for FastClose<H>
+where
+    H: FileExt + FastCloseable + 'static,
+{
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        FileExt::read_at(&**self, buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        FileExt::write_at(&**self, buf, offset)
+    }
+}
+
+/// Makes any type that owns an OS handle [`FastCloseable`], without needing
+/// an impl for each type
+///
+/// Third-party handle wrappers can be closed by `close_already` so long as
+/// they implement
+/// [`IntoRawHandle`](https://doc.rust-lang.org/std/os/windows/io/trait.IntoRawHandle.html)
+/// on Windows, or [`IntoRawFd`](https://doc.rust-lang.org/std/os/fd/trait.IntoRawFd.html)
+/// on Unix. A blanket implementation of `FastCloseable` for these types isn't
+/// possible, as it would overlap with the implementations for `File` and the
+/// other types `close_already` supports. Wrapping in `OwnsHandle` instead
+/// opts a type in explicitly
+///
+/// The wrapper must own its handle (rather than e.g. sharing it with
+/// clones), as with any other `FastCloseable` type
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// use std::{fs::File, io::Write};
+///
+/// use close_already::{compat::OwnsHandle, FastClose};
+///
+/// let file = File::create(std::env::temp_dir().join("owns_handle.txt"))?;
+/// let mut file = FastClose::new(OwnsHandle(file));
+/// file.write_all(b"hello")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct OwnsHandle<T>(pub T);
+
+#[cfg(windows)]
+impl<T: IntoRawHandle + Send> FastCloseable for OwnsHandle<T> {
+    /// Returns any error from `CloseHandle`
+    fn close(self) -> io::Result<()> {
+        // SAFETY: the wrapper has given up ownership of the handle, so it's
+        // only owned (and closed) by the new File
+        unsafe { File::from_raw_handle(self.0.into_raw_handle()) }.close()
+    }
+}
+
+#[cfg(unix)]
+impl<T: IntoRawFd + Send> FastCloseable for OwnsHandle<T> {}
+
+impl<T> Deref for OwnsHandle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for OwnsHandle<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: io::Read> io::Read for OwnsHandle<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<T: io::Write> io::Write for OwnsHandle<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<T: io::Seek> io::Seek for OwnsHandle<T> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::io::{Read, Seek, Write};
+
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn positional_io() {
+        in_runtime(|| {
+            let file = tempfile::tempfile().unwrap().fast_close();
+            assert_eq!(file.write_at(b"world", 6).unwrap(), 5);
+            assert_eq!(file.write_at(b"hello ", 0).unwrap(), 6);
+            let mut buf = [0; 5];
+            assert_eq!(file.read_at(&mut buf, 6).unwrap(), 5);
+            assert_eq!(&buf, b"world");
+        });
+    }
+
+    #[test]
+    fn owns_handle() {
+        in_runtime(|| {
+            let mut file =
+                FastClose::new(OwnsHandle(tempfile::tempfile().unwrap()));
+            file.write_all(b"hello").unwrap();
+            file.rewind().unwrap();
+            let mut buf = String::new();
+            file.read_to_string(&mut buf).unwrap();
+            assert_eq!(buf, "hello");
+            file.close().unwrap();
+        });
+    }
+}
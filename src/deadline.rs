@@ -0,0 +1,92 @@
+//! Bounding how long handles can wait in the backend's queue
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The deadline in nanoseconds, or `u64::MAX` if there isn't one
+static DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// A moving average of how long the backend takes to close a handle, in
+/// nanoseconds
+static AVERAGE_CLOSE: AtomicU64 = AtomicU64::new(0);
+
+/// Sets a deadline for handles to be closed within, or removes it if `None`
+///
+/// When dropped, a handle is normally submitted to the backend however long
+/// its queue is. With a deadline set, if the queue is estimated to take
+/// longer than the deadline to get through, the handle is instead closed
+/// inline, bounding how long any handle can remain open after being dropped.
+/// This is for applications that need files to be closed within a certain
+/// time, at the cost of dropping being slow when the backend is overloaded
+///
+/// The estimate is rough: it assumes the backend closes a handle per CPU
+/// core at a time, taking about as long as the handles it's recently closed
+pub fn set_close_deadline(deadline: Option<Duration>) {
+    let nanos = deadline.map_or(u64::MAX, |deadline| {
+        deadline.as_nanos().try_into().unwrap_or(u64::MAX - 1)
+    });
+    DEADLINE.store(nanos, Ordering::Relaxed);
+}
+
+/// Whether the backend's queue, with `pending` handles in it, is estimated
+/// to take longer than the deadline to get through
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn exceeded(pending: usize) -> bool {
+    let deadline = DEADLINE.load(Ordering::Relaxed);
+    if deadline == u64::MAX {
+        return false;
+    }
+    let pending = pending as u64;
+    let average = AVERAGE_CLOSE.load(Ordering::Relaxed);
+    let estimate = pending.saturating_mul(average) / parallelism() as u64;
+    estimate > deadline
+}
+
+/// The number of handles the backend is assumed to close at once
+fn parallelism() -> usize {
+    static PARALLELISM: OnceLock<usize> = OnceLock::new();
+    *PARALLELISM.get_or_init(|| {
+        thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    })
+}
+
+/// Gets the time a closure started, if a deadline is set
+pub(crate) fn start() -> Option<Instant> {
+    (DEADLINE.load(Ordering::Relaxed) != u64::MAX).then(Instant::now)
+}
+
+/// Updates the average closure time if the closure was timed
+pub(crate) fn closed(started: Option<Instant>) {
+    if let Some(started) = started {
+        let sample: u64 =
+            started.elapsed().as_nanos().try_into().unwrap_or(u64::MAX);
+        let average = AVERAGE_CLOSE.load(Ordering::Relaxed);
+        let average = match average {
+            0 => sample,
+            _ => average - average / 8 + sample / 8,
+        };
+        AVERAGE_CLOSE.store(average, Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exceeded_with_long_queue() {
+        let queued = 20 * parallelism();
+        assert!(!exceeded(queued), "there's no deadline by default");
+        set_close_deadline(Some(Duration::from_millis(10)));
+        AVERAGE_CLOSE.store(1_000_000, Ordering::Relaxed);
+        assert!(!exceeded(0), "the queue is empty");
+        assert!(exceeded(queued), "20ms of closures are queued");
+        set_close_deadline(None);
+    }
+}
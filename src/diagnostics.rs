@@ -0,0 +1,120 @@
+//! Recording where handles were created, with the `diagnostics` feature
+#[cfg(windows)]
+use std::borrow::Cow;
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    fmt,
+    panic::Location,
+    sync::Arc,
+};
+
+/// Where a [`FastClose`](crate::FastClose) was created, recorded with the
+/// `diagnostics` feature enabled
+///
+/// This is included in the [`watchdog`](crate::watchdog)'s reports, the
+/// `audit` table, and the handles listed by
+/// [`assert_no_pending_closes!`](crate::assert_no_pending_closes), to find
+/// where slow or leaked handles come from. A backtrace is also captured if
+/// enabled by the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment
+/// variables (see [`Backtrace::capture`]), which makes creating handles much
+/// slower
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Origin {
+    /// The source location the handle was created at
+    pub location: &'static Location<'static>,
+    /// The backtrace from when the handle was created, if enabled
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Origin {
+    /// Captures where the caller was called from, if the `diagnostics`
+    /// feature is enabled
+    #[inline]
+    #[track_caller]
+    #[cfg_attr(not(any(windows, feature = "audit")), allow(dead_code))]
+    pub(crate) fn capture() -> Option<Origin> {
+        if !cfg!(feature = "diagnostics") {
+            return None;
+        }
+        let backtrace = Backtrace::capture();
+        Some(Origin {
+            location: Location::caller(),
+            backtrace: (backtrace.status() == BacktraceStatus::Captured)
+                .then(|| Arc::new(backtrace)),
+        })
+    }
+}
+
+impl fmt::Display for Origin {
+    /// Writes the location, followed by the backtrace, if there is one, with
+    /// the alternate flag (`{:#}`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.location)?;
+        match &self.backtrace {
+            Some(backtrace) if f.alternate() => write!(f, "\n{backtrace}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What a `FastClose` keeps about its handle for reports about its closure,
+/// with the `diagnostics` feature enabled: where it was created, and any
+/// label noted for it (e.g. the path it was opened from by the `fs` module)
+///
+/// Without the feature, nothing is kept, so this is zero-sized
+#[cfg(windows)]
+#[derive(Default)]
+pub(crate) struct Notes {
+    #[cfg(feature = "diagnostics")]
+    origin: Option<Origin>,
+    #[cfg(feature = "diagnostics")]
+    label: Option<Cow<'static, str>>,
+}
+
+#[cfg(windows)]
+impl Notes {
+    /// Notes where a handle was created
+    pub(crate) fn new(origin: Option<Origin>) -> Notes {
+        #[cfg(not(feature = "diagnostics"))]
+        let _ = origin;
+        Notes {
+            #[cfg(feature = "diagnostics")]
+            origin,
+            #[cfg(feature = "diagnostics")]
+            label: None,
+        }
+    }
+
+    /// Notes the handle's label
+    pub(crate) fn set_label(&mut self, label: Cow<'static, str>) {
+        #[cfg(feature = "diagnostics")]
+        {
+            self.label = Some(label);
+        }
+        #[cfg(not(feature = "diagnostics"))]
+        let _ = label;
+    }
+
+    /// Takes the label and origin noted, if any
+    pub(crate) fn take(
+        &mut self,
+    ) -> (Option<Cow<'static, str>>, Option<Origin>) {
+        #[cfg(feature = "diagnostics")]
+        return (self.label.take(), self.origin.take());
+        #[cfg(not(feature = "diagnostics"))]
+        (None, None)
+    }
+}
+
+#[cfg(all(test, not(miri), feature = "diagnostics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_caller() {
+        let origin = Origin::capture().unwrap();
+        assert_eq!(origin.location.file(), file!());
+        assert_eq!(origin.to_string().split(':').next(), Some(file!()));
+    }
+}
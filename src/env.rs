@@ -0,0 +1,107 @@
+//! Inspection of the environment `close_already` is running in
+//!
+//! The cost of closing a file on Windows is dominated by anti-virus
+//! real-time protection (e.g. Microsoft Defender) scanning the file as the
+//! handle is closed. Locations excluded from scanning, such as Dev Drives,
+//! close files nearly as quickly as other operating systems do, in which case
+//! offloading closes is of little benefit. This module provides best-effort
+//! ways of finding out which situation you're in
+use std::{
+    fs::OpenOptions,
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// How many probe files [`scan_exposure`] writes
+const PROBES: usize = 5;
+
+/// Close latencies at or above this are assumed to be caused by a scanner
+const SCANNED_THRESHOLD: Duration = Duration::from_micros(500);
+
+/// Distinguishes probe files created by the same process
+static PROBE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The findings of [`scan_exposure`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Exposure {
+    /// The median time taken to close a freshly written probe file
+    pub close_latency: Duration,
+    /// Whether real-time protection appears to be scanning files written to
+    /// the location
+    pub likely_scanned: bool,
+}
+
+/// Reports whether real-time protection appears to be active for a path
+///
+/// This writes a handful of small probe files to `path` (or to its parent
+/// directory, if `path` is a file), timing how long each takes to close
+/// synchronously. The probe files are removed afterwards
+///
+/// This is best-effort: the result is a heuristic based on latency alone, so
+/// it can be skewed by a heavily loaded disk. Use it to decide on strategies
+/// (e.g. not bothering to offload closes inside an excluded Dev Drive) or to
+/// warn users, not for anything security-sensitive
+///
+/// # Errors
+///
+/// Returns an error if a probe file can't be created, written, or removed
+pub fn scan_exposure(path: impl AsRef<Path>) -> io::Result<Exposure> {
+    fn inner(path: &Path) -> io::Result<Exposure> {
+        let dir = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(path)
+        };
+        let mut latencies = Vec::with_capacity(PROBES);
+        for _ in 0..PROBES {
+            latencies.push(probe(dir)?);
+        }
+        latencies.sort_unstable();
+        let close_latency = latencies[PROBES / 2];
+        Ok(Exposure {
+            close_latency,
+            likely_scanned: close_latency >= SCANNED_THRESHOLD,
+        })
+    }
+    inner(path.as_ref())
+}
+
+/// Writes a single probe file to `dir`, returning how long it took to close
+fn probe(dir: &Path) -> io::Result<Duration> {
+    let path = probe_path(dir);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // Scanners may skip empty files, so give them something to look at
+    let result = file.write_all(&[0xCA; 4096]);
+    let start = Instant::now();
+    drop(file);
+    let elapsed = start.elapsed();
+    std::fs::remove_file(&path)?;
+    result.map(|_| elapsed)
+}
+
+/// Generates a probe file name unlikely to collide with anything else
+fn probe_path(dir: &Path) -> PathBuf {
+    let n = PROBE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!(".close_already-probe-{}-{n}", process::id()))
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_files_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        scan_exposure(dir.path()).expect("scan should succeed");
+        let leftovers = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(leftovers, 0, "probe files should be cleaned up");
+    }
+}
@@ -0,0 +1,306 @@
+//! Reporting of errors encountered in the background, which can't be
+//! returned to the code that caused them
+use std::{
+    any::{self, Any},
+    borrow::Cow,
+    error, fmt, io,
+    path::Path,
+    sync::{Arc, PoisonError, RwLock},
+    time::SystemTime,
+};
+
+use crate::FastCloseable;
+
+/// A function that errors are sent to
+type Sink = Arc<dyn Fn(CloseError) + Send + Sync>;
+
+/// The sink set by [`set_error_sink`], if any
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// A function that panics are sent to
+type PanicHandler = Arc<dyn Fn(ClosePanic) + Send + Sync>;
+
+/// The handler set by [`set_panic_handler`], if any
+static PANIC_HANDLER: RwLock<Option<PanicHandler>> = RwLock::new(None);
+
+/// An error encountered while closing or cleaning up after a handle, which
+/// has been sent to the sink set by [`set_error_sink`]
+///
+/// This includes:
+/// - Errors closing handles, as returned by [`FastCloseable::close`] (on
+///   Windows, where `close_already` closes them)
+/// - Errors from handles' [`before_close`](FastCloseable::before_close) hooks,
+///   such as flushing buffered writes
+/// - Backends failing to accept a handle (it's closed inline instead)
+/// - Temporary files created by the [`fs`](crate::fs) module that couldn't be
+///   removed
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CloseError {
+    /// A description of the handle or file the error relates to. As handles
+    /// aren't required to be `Debug`, this is their type name, or the path
+    /// for files
+    pub handle_debug: String,
+    /// The label given to the handle, if any (see
+    /// [`FastClose::with_label`](crate::FastClose::with_label))
+    pub label: Option<Cow<'static, str>>,
+    /// The underlying error
+    pub source: io::Error,
+    /// When the error happened
+    pub when: SystemTime,
+}
+
+impl fmt::Display for CloseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to close ")?;
+        describe(f, &self.handle_debug, self.label.as_deref())?;
+        write!(f, ": {}", self.source)
+    }
+}
+
+impl error::Error for CloseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Sets a function to send errors encountered in the background to,
+/// replacing any set before
+///
+/// By default, these errors are ignored, as there's nowhere to return them
+/// to. The sink may be called from any thread, including the backend's, so
+/// it should return quickly. To receive errors as a channel instead, send
+/// them from the sink:
+///
+/// ```
+/// use std::sync::mpsc;
+///
+/// let (sender, receiver) = mpsc::channel();
+/// close_already::set_error_sink(move |error| {
+///     let _ = sender.send(error);
+/// });
+/// # drop(receiver);
+/// ```
+pub fn set_error_sink(sink: impl Fn(CloseError) + Send + Sync + 'static) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) =
+        Some(Arc::new(sink));
+}
+
+/// Removes the sink set by [`set_error_sink`], so errors are ignored again
+pub fn clear_error_sink() {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// A panic caught while closing a handle in the background, which has been
+/// sent to the handler set by [`set_panic_handler`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ClosePanic {
+    /// The type name of the handle that panicked while being closed
+    pub handle_debug: String,
+    /// The label given to the handle, if any (see
+    /// [`FastClose::with_label`](crate::FastClose::with_label))
+    pub label: Option<Cow<'static, str>>,
+    /// The panic's message, if it had one
+    pub message: Option<String>,
+    /// When the panic happened
+    pub when: SystemTime,
+}
+
+impl fmt::Display for ClosePanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.message.as_deref().unwrap_or("Box<dyn Any>");
+        write!(f, "panicked closing ")?;
+        describe(f, &self.handle_debug, self.label.as_deref())?;
+        write!(f, ": {message}")
+    }
+}
+
+/// Writes the handle's label, followed by its description in brackets, or
+/// just its description if it doesn't have a label
+pub(crate) fn describe(
+    f: &mut fmt::Formatter<'_>,
+    handle_debug: &str,
+    label: Option<&str>,
+) -> fmt::Result {
+    match label {
+        Some(label) => write!(f, "{label} ({handle_debug})"),
+        None => write!(f, "{handle_debug}"),
+    }
+}
+
+/// Sets a function to send panics caught while closing handles in the
+/// background to, replacing any set before
+///
+/// If a handle's destructor panics on one of the backend's threads, the
+/// panic is caught, so that it can't take the thread down with it, or stop
+/// [`drain`](crate::drain) from seeing the handle as closed. The panic is
+/// still printed by the panic hook, but is otherwise ignored, unless a
+/// handler is set to log it. Like the error sink, the handler may be called
+/// from any thread
+pub fn set_panic_handler(handler: impl Fn(ClosePanic) + Send + Sync + 'static) {
+    *PANIC_HANDLER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(handler));
+}
+
+/// Removes the handler set by [`set_panic_handler`], so panics are ignored
+/// again
+pub fn clear_panic_handler() {
+    *PANIC_HANDLER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Reports a panic caught while closing a handle of type `H`, returning it
+pub(crate) fn report_panic<H: ?Sized>(
+    label: Option<Cow<'static, str>>,
+    payload: Box<dyn Any + Send>,
+) -> ClosePanic {
+    crate::stats::error();
+    let message = match payload.downcast::<String>() {
+        Ok(message) => Some(*message),
+        Err(payload) => payload.downcast_ref::<&str>().map(|&s| s.into()),
+    };
+    let panic = ClosePanic {
+        handle_debug: any::type_name::<H>().to_owned(),
+        label,
+        message,
+        when: SystemTime::now(),
+    };
+    #[cfg(feature = "log")]
+    crate::log::panic(&panic);
+    let handler = PANIC_HANDLER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(handler) = handler {
+        handler(panic.clone());
+    }
+    panic
+}
+
+/// Closes a handle, after running its
+/// [`before_close`](FastCloseable::before_close) hook, reporting any error
+pub(crate) fn close<H: FastCloseable>(
+    handle: H,
+    label: Option<&Cow<'static, str>>,
+) {
+    if let Err(why) = crate::close_handle(handle) {
+        report_handle::<H>(label.cloned(), why);
+    }
+}
+
+/// Reports an error relating to a handle of type `H`
+pub(crate) fn report_handle<H: ?Sized>(
+    label: Option<Cow<'static, str>>,
+    source: io::Error,
+) {
+    report(any::type_name::<H>().to_owned(), label, source);
+}
+
+/// Reports an error cleaning up the file at `path`. Files that are already
+/// gone aren't reported
+pub(crate) fn report_cleanup(path: &Path, result: io::Result<()>) {
+    match result {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => {
+            report(path.display().to_string(), None, why);
+        },
+        _ => {},
+    }
+}
+
+/// Sends an error to the sink, if there is one
+fn report(
+    handle_debug: String,
+    label: Option<Cow<'static, str>>,
+    source: io::Error,
+) {
+    crate::stats::error();
+    let error = CloseError {
+        handle_debug,
+        label,
+        source,
+        when: SystemTime::now(),
+    };
+    #[cfg(feature = "log")]
+    crate::log::error(&error);
+    let sink = SINK.read().unwrap_or_else(PoisonError::into_inner).clone();
+    if let Some(sink) = sink {
+        sink(error);
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+pub(crate) mod tests {
+    use std::sync::{mpsc, Mutex, MutexGuard};
+
+    use super::*;
+
+    /// Held by tests that report errors or panics, or set the sink or
+    /// handler they're reported to, so they don't see each other's reports
+    static REPORTING: Mutex<()> = Mutex::new(());
+
+    /// Locks the error sink and panic handler for the rest of the test
+    pub(crate) fn lock_reporting() -> MutexGuard<'static, ()> {
+        REPORTING.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[test]
+    fn sink_receives_errors() {
+        struct Failing;
+
+        impl FastCloseable for Failing {
+            fn before_close(&mut self) -> io::Result<()> {
+                Err(io::Error::other("unflushed"))
+            }
+
+            fn close(self) -> io::Result<()> {
+                Err(io::Error::other("oh no"))
+            }
+        }
+
+        let _reporting = lock_reporting();
+        let (sender, receiver) = mpsc::channel();
+        set_error_sink(move |error| {
+            let _ = sender.send(error);
+        });
+        report_cleanup(Path::new("gone"), Err(io::ErrorKind::NotFound.into()));
+        report_handle::<std::fs::File>(None, io::Error::other("oh no"));
+        report_handle::<std::fs::File>(
+            Some("log.txt".into()),
+            io::Error::other("oh no"),
+        );
+        close(Failing, Some(&"failing".into()));
+        clear_error_sink();
+
+        let error = receiver.try_recv().expect("error should be reported");
+        assert_eq!(error.handle_debug, "std::fs::File");
+        assert_eq!(error.to_string(), "failed to close std::fs::File: oh no");
+        let error = receiver.try_recv().expect("error should be reported");
+        assert_eq!(
+            error.to_string(),
+            "failed to close log.txt (std::fs::File): oh no"
+        );
+        let error = receiver.try_recv().expect("error should be reported");
+        assert!(error.to_string().starts_with("failed to close failing ("));
+        assert!(error.to_string().ends_with("Failing): unflushed"));
+        assert!(receiver.try_recv().is_err(), "NotFound isn't reported");
+    }
+
+    #[test]
+    fn handler_receives_panics() {
+        let _reporting = lock_reporting();
+        let (sender, receiver) = mpsc::channel();
+        set_panic_handler(move |panic| {
+            let _ = sender.send(panic);
+        });
+        let payload = std::panic::catch_unwind(|| panic!("oh {}", "no"));
+        report_panic::<std::fs::File>(None, payload.unwrap_err());
+        clear_panic_handler();
+
+        let panic = receiver.try_recv().expect("panic should be reported");
+        assert_eq!(panic.message.as_deref(), Some("oh no"));
+        assert_eq!(panic.to_string(), "panicked closing std::fs::File: oh no");
+    }
+}
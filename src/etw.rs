@@ -0,0 +1,213 @@
+//! An Event Tracing for Windows (ETW) provider, for correlating closures
+//! with other system activity in Windows Performance Analyzer
+//!
+//! With the `etw` feature enabled, TraceLogging events are written by the
+//! `CloseAlready` provider, whose GUID, derived from its name, is
+//! `354bcd8a-2d7f-57e4-73c4-f7eeff2beab6`. Record them with e.g.
+//! `wpr -start GeneralProfile` and a profile including `*CloseAlready`, or
+//! `tracelog`. The events are:
+//! - `HandleSubmitted`, when a handle is submitted to be closed in the
+//!   background, with its type (`Handle`), its label (`Label`, empty if it
+//!   doesn't have one), and the number of handles waiting to be closed
+//!   (`QueueDepth`)
+//! - `HandleClosed`, when the handle has been closed, with its type and label,
+//!   how long it took in nanoseconds (`DurationNs`), and whether it panicked
+//!
+//! Events are only built and written while a trace session has the provider
+//! enabled
+use std::{any, ptr, sync::OnceLock, time::Duration};
+
+use windows_sys::{
+    core::GUID,
+    Win32::System::Diagnostics::Etw::{
+        EventEnabled, EventProviderSetTraits, EventRegister,
+        EventSetInformation, EventWriteTransfer, EVENT_DATA_DESCRIPTOR,
+        EVENT_DATA_DESCRIPTOR_0, EVENT_DATA_DESCRIPTOR_0_0,
+        EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA,
+        EVENT_DATA_DESCRIPTOR_TYPE_NONE,
+        EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA, EVENT_DESCRIPTOR,
+    },
+};
+
+/// The provider's name
+const NAME: &str = "CloseAlready";
+
+/// The provider's GUID, derived from its name as is conventional for
+/// TraceLogging providers, so that tools can find it by name
+const GUID: GUID = GUID::from_u128(0x354bcd8a_2d7f_57e4_73c4_f7eeff2beab6);
+
+/// The descriptor used for every event, at the informational level on the
+/// TraceLogging channel
+const EVENT: EVENT_DESCRIPTOR = EVENT_DESCRIPTOR {
+    Id: 0,
+    Version: 0,
+    Channel: 11,
+    Level: 4,
+    Opcode: 0,
+    Task: 0,
+    Keyword: 0,
+};
+
+/// TraceLogging's field type for nul-terminated 8-bit strings
+const IN_ANSI_STRING: u8 = 2;
+
+/// TraceLogging's field type for `u64`s
+const IN_UINT64: u8 = 10;
+
+/// TraceLogging's field type for 32-bit booleans
+const IN_BOOL32: u8 = 13;
+
+/// The registered provider, or `None` if registration failed
+static PROVIDER: OnceLock<Option<Provider>> = OnceLock::new();
+
+struct Provider {
+    handle: u64,
+    metadata: Vec<u8>,
+    submitted: Vec<u8>,
+    closed: Vec<u8>,
+}
+
+impl Provider {
+    /// Registers the provider, for the lifetime of the process
+    fn register() -> Option<Provider> {
+        let mut handle = 0;
+        // SAFETY: the GUID and handle are valid for the duration of the
+        // call, and there's no callback
+        let error =
+            unsafe { EventRegister(&GUID, None, ptr::null(), &mut handle) };
+        if error != 0 {
+            return None;
+        }
+        let provider = Provider {
+            handle,
+            metadata: metadata(NAME.bytes().chain([0])),
+            submitted: event_metadata("HandleSubmitted", &[
+                ("Handle", IN_ANSI_STRING),
+                ("Label", IN_ANSI_STRING),
+                ("QueueDepth", IN_UINT64),
+            ]),
+            closed: event_metadata("HandleClosed", &[
+                ("Handle", IN_ANSI_STRING),
+                ("Label", IN_ANSI_STRING),
+                ("DurationNs", IN_UINT64),
+                ("Panicked", IN_BOOL32),
+            ]),
+        };
+        // SAFETY: the provider's traits are valid for the duration of the
+        // call, and are copied by it
+        unsafe {
+            EventSetInformation(
+                handle,
+                EventProviderSetTraits,
+                provider.metadata.as_ptr().cast(),
+                provider.metadata.len() as u32,
+            )
+        };
+        Some(provider)
+    }
+
+    /// Gets the provider if a session is listening to it
+    fn enabled() -> Option<&'static Provider> {
+        let provider = PROVIDER.get_or_init(Provider::register).as_ref()?;
+        // SAFETY: the handle is registered, and the descriptor is valid
+        let enabled = unsafe { EventEnabled(provider.handle, &EVENT) };
+        (enabled != 0).then_some(provider)
+    }
+
+    /// Writes an event with the given metadata, and fields' data
+    fn write(&self, metadata: &[u8], fields: &[&[u8]]) {
+        let descriptors = [
+            descriptor(
+                &self.metadata,
+                EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA,
+            ),
+            descriptor(metadata, EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA),
+        ]
+        .into_iter()
+        .chain(
+            fields
+                .iter()
+                .map(|data| descriptor(data, EVENT_DATA_DESCRIPTOR_TYPE_NONE)),
+        )
+        .collect::<Vec<_>>();
+        // SAFETY: the handle is registered, and the descriptors point to
+        // data that outlives the call
+        unsafe {
+            EventWriteTransfer(
+                self.handle,
+                &EVENT,
+                ptr::null(),
+                ptr::null(),
+                descriptors.len() as u32,
+                descriptors.as_ptr(),
+            )
+        };
+    }
+}
+
+/// Builds TraceLogging metadata, which is prefixed by its size
+fn metadata(contents: impl IntoIterator<Item = u8>) -> Vec<u8> {
+    let mut metadata = vec![0, 0];
+    metadata.extend(contents);
+    let size = metadata.len() as u16;
+    metadata[..2].copy_from_slice(&size.to_le_bytes());
+    metadata
+}
+
+/// Builds an event's metadata: no tags, then its name, then each field's
+/// name and type
+fn event_metadata(name: &str, fields: &[(&str, u8)]) -> Vec<u8> {
+    let mut contents = vec![0];
+    contents.extend(name.bytes().chain([0]));
+    for &(field, in_type) in fields {
+        contents.extend(field.bytes().chain([0, in_type]));
+    }
+    metadata(contents)
+}
+
+fn descriptor(data: &[u8], kind: u32) -> EVENT_DATA_DESCRIPTOR {
+    EVENT_DATA_DESCRIPTOR {
+        Ptr: data.as_ptr() as u64,
+        Size: data.len() as u32,
+        Anonymous: EVENT_DATA_DESCRIPTOR_0 {
+            Anonymous: EVENT_DATA_DESCRIPTOR_0_0 {
+                Type: kind as u8,
+                Reserved1: 0,
+                Reserved2: 0,
+            },
+        },
+    }
+}
+
+/// Writes a `HandleSubmitted` event for a handle of type `H`
+pub(crate) fn submitted<H: ?Sized>(label: Option<&str>) {
+    if let Some(provider) = Provider::enabled() {
+        let depth = (crate::pending::count() as u64).to_le_bytes();
+        provider.write(&provider.submitted, &[
+            any::type_name::<H>().as_bytes(),
+            b"\0",
+            label.unwrap_or_default().as_bytes(),
+            b"\0",
+            &depth,
+        ]);
+    }
+}
+
+/// Writes a `HandleClosed` event for a handle of type `H`
+pub(crate) fn closed<H: ?Sized>(
+    label: Option<&str>,
+    duration: Duration,
+    panicked: bool,
+) {
+    if let Some(provider) = Provider::enabled() {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        provider.write(&provider.closed, &[
+            any::type_name::<H>().as_bytes(),
+            b"\0",
+            label.unwrap_or_default().as_bytes(),
+            b"\0",
+            &nanos.to_le_bytes(),
+            &i32::from(panicked).to_le_bytes(),
+        ]);
+    }
+}
@@ -0,0 +1,220 @@
+//! A stream of events for handles closed in the background, for monitoring
+//!
+//! Each subscriber to [`subscribe`] receives a [`CloseEvent`] for every
+//! handle the backend (or a [`Closer`](crate::Closer)) closes from then on,
+//! with how long it took and whether it panicked. Handles are only timed
+//! while there are subscribers, so this costs nothing when unused
+//!
+//! ```no_run
+//! # async fn example() {
+//! use futures_lite::StreamExt;
+//!
+//! let mut events = close_already::events::subscribe();
+//! while let Some(event) = events.next().await {
+//!     println!("closed {} in {:?}", event.handle_debug, event.duration);
+//! }
+//! # }
+//! ```
+use std::{
+    any,
+    borrow::Cow,
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, Weak,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures_core::Stream;
+
+use crate::ClosePanic;
+
+/// How many events a subscriber can fall behind by before the oldest are
+/// dropped
+const CAPACITY: usize = 1024;
+
+/// Every subscriber's channel, including those that have been dropped but
+/// not yet removed
+static SUBSCRIBERS: Mutex<Vec<Weak<Channel>>> = Mutex::new(Vec::new());
+
+/// The number of live subscribers, checked before timing a handle
+static SUBSCRIBED: AtomicUsize = AtomicUsize::new(0);
+
+/// A handle having been closed in the background, received from
+/// [`CloseEvents`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CloseEvent {
+    /// The type name of the handle that was closed
+    pub handle_debug: String,
+    /// The label given to the handle, if any (see
+    /// [`FastClose::with_label`](crate::FastClose::with_label))
+    pub label: Option<Cow<'static, str>>,
+    /// How long closing the handle took
+    pub duration: Duration,
+    /// Whether the handle was closed, or panicked while being closed
+    pub result: Result<(), ClosePanic>,
+    /// When the handle finished closing
+    pub when: SystemTime,
+}
+
+/// Subscribes to events for every handle closed in the background from now
+/// on
+///
+/// Events are buffered for each subscriber. If one falls more than 1024
+/// events behind, the oldest are dropped, and counted by
+/// [`CloseEvents::missed`], so a slow consumer can't hold handles up
+pub fn subscribe() -> CloseEvents {
+    let channel = Arc::new(Channel::default());
+    let mut subscribers = subscribers();
+    subscribers.retain(|subscriber| subscriber.strong_count() > 0);
+    subscribers.push(Arc::downgrade(&channel));
+    SUBSCRIBED.fetch_add(1, Ordering::SeqCst);
+    CloseEvents(channel)
+}
+
+/// A [`Stream`] of [`CloseEvent`]s, created by [`subscribe`]
+///
+/// The stream never ends. Drop it to unsubscribe
+pub struct CloseEvents(Arc<Channel>);
+
+impl CloseEvents {
+    /// The number of events dropped because this subscriber fell too far
+    /// behind
+    pub fn missed(&self) -> u64 {
+        self.0.lock().missed
+    }
+}
+
+impl Stream for CloseEvents {
+    type Item = CloseEvent;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<CloseEvent>> {
+        let mut state = self.0.lock();
+        match state.events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl Drop for CloseEvents {
+    fn drop(&mut self) {
+        SUBSCRIBED.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for CloseEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.lock();
+        f.debug_struct("CloseEvents")
+            .field("buffered", &state.events.len())
+            .field("missed", &state.missed)
+            .finish()
+    }
+}
+
+/// A subscriber's buffered events, shared with publishers
+#[derive(Default)]
+struct Channel(Mutex<ChannelState>);
+
+#[derive(Default)]
+struct ChannelState {
+    events: VecDeque<CloseEvent>,
+    missed: u64,
+    waker: Option<Waker>,
+}
+
+impl Channel {
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn send(&self, event: CloseEvent) {
+        let mut state = self.lock();
+        if state.events.len() >= CAPACITY {
+            state.events.pop_front();
+            state.missed += 1;
+        }
+        state.events.push_back(event);
+        if let Some(waker) = state.waker.take() {
+            drop(state);
+            waker.wake();
+        }
+    }
+}
+
+fn subscribers() -> MutexGuard<'static, Vec<Weak<Channel>>> {
+    SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Starts timing a handle's closure, if there are any subscribers
+#[inline]
+pub(crate) fn start() -> Option<Instant> {
+    (SUBSCRIBED.load(Ordering::Relaxed) > 0).then(Instant::now)
+}
+
+/// Sends an event for a handle of type `H` to every subscriber, if it was
+/// timed
+pub(crate) fn publish<H: ?Sized>(
+    started: Option<Instant>,
+    label: Option<Cow<'static, str>>,
+    result: Result<(), ClosePanic>,
+) {
+    let Some(started) = started else { return };
+    let event = CloseEvent {
+        handle_debug: any::type_name::<H>().to_owned(),
+        label,
+        duration: started.elapsed(),
+        result,
+        when: SystemTime::now(),
+    };
+    let subscribers = subscribers()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<_>>();
+    for subscriber in subscribers {
+        subscriber.send(event.clone());
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::future;
+
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn receives_close_events() {
+        struct Handle;
+
+        impl crate::FastCloseable for Handle {}
+
+        let mut events = subscribe();
+        in_runtime(|| crate::backend::close(Handle));
+        let event = futures_executor::block_on(async {
+            loop {
+                let event =
+                    future::poll_fn(|cx| Pin::new(&mut events).poll_next(cx))
+                        .await
+                        .unwrap();
+                if event.handle_debug == any::type_name::<Handle>() {
+                    break event;
+                }
+            }
+        });
+        assert!(event.result.is_ok());
+        assert_eq!(events.missed(), 0);
+    }
+}
@@ -5,14 +5,41 @@
 //! `close_already`-using versions. The functions have identical signatures to
 //! make drop-in replacing possible; and the implementation is as close to std
 //! as possible (differences noted in documentation for each method)
+//!
+//! [`File`] and [`OpenOptions`] likewise replace [`std::fs::File`] and
+//! [`std::fs::OpenOptions`], for code that opens files itself
 use std::{
-    fs::{File, OpenOptions},
     io,
     io::{Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
 };
 
-use crate::FastCloseable;
+use crate::{error::report_cleanup, paths::Tracked, FastClose, FastCloseable};
+
+mod dir_size;
+mod file;
+#[cfg(all(
+    windows,
+    any(
+        feature = "backend-async-std",
+        feature = "backend-smol",
+        feature = "backend-tokio",
+    ),
+))]
+pub(crate) mod ioring;
+mod open_options;
+mod read_dir;
+mod transaction;
+
+pub use dir_size::{dir_size, DirStats, ExtensionStats};
+pub use file::File;
+pub use open_options::OpenOptions;
+pub use read_dir::{read_dir, DirEntry, ReadDir};
+pub use transaction::Transaction;
 
 /// Copies the contents of one file to another.
 /// This function will also copy the permission bits of the original file to
@@ -33,13 +60,16 @@ use crate::FastCloseable;
 /// After the copy is completed, the permission bits are set
 pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
     fn inner(from_path: &Path, to_path: &Path) -> io::Result<u64> {
-        let mut from = File::open(from_path)?.fast_close();
-        let mut to = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(to_path)?
-            .fast_close();
+        let from = reopen_safely(from_path, || std::fs::File::open(from_path))?;
+        let mut from = labeled(Tracked::new(from, from_path), from_path);
+        let to = reopen_safely(to_path, || {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(to_path)
+        })?;
+        let mut to = labeled(Tracked::new(to, to_path), to_path);
         let copied = io::copy(&mut from, &mut to)?;
         std::fs::set_permissions(to_path, from.metadata()?.permissions())?;
         Ok(copied)
@@ -49,7 +79,7 @@ pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
 
 /// Read the entire contents of a file into a bytes vector
 ///
-/// This is a convenience function for using [`File::open`] and
+/// This is a convenience function for using [`std::fs::File::open`] and
 /// [`read_to_end`](Read::read_to_end) with fewer imports and without an
 /// intermediate variable
 ///
@@ -61,7 +91,8 @@ pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
 /// identical
 pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
     fn inner(path: &Path) -> io::Result<Vec<u8>> {
-        let mut file = File::open(path)?.fast_close();
+        let file = reopen_safely(path, || std::fs::File::open(path))?;
+        let mut file = labeled(Tracked::new(file, path), path);
         let size = file.metadata().map(|m| m.len() as usize).ok();
         let mut bytes = Vec::with_capacity(size.unwrap_or(0));
         file.read_to_end(&mut bytes)?;
@@ -72,7 +103,7 @@ pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
 
 /// Read the entire contents of a file into a string.
 ///
-/// This is a convenience function for using [`File::open`] and
+/// This is a convenience function for using [`std::fs::File::open`] and
 /// [`read_to_string`](Read::read_to_string) with fewer imports and
 /// without an intermediate variable
 ///
@@ -84,7 +115,7 @@ pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
 /// identical
 pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
     fn inner(path: &Path) -> io::Result<String> {
-        let mut file = File::open(path)?;
+        let mut file = reopen_safely(path, || std::fs::File::open(path))?;
         let size = file.metadata().map(|m| m.len() as usize).ok();
         let mut string = String::with_capacity(size.unwrap_or(0));
         file.read_to_string(&mut string)?;
@@ -93,6 +124,153 @@ pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
     inner(path.as_ref())
 }
 
+/// Rename a file or directory to a new name, replacing the original file if
+/// `to` already exists
+///
+/// This will not work if the new name is on a different mount point, unless
+/// `from` is a file (see below)
+///
+/// # `close_already` differences
+///
+/// On Windows, transient sharing violations (commonly caused by anti-virus
+/// scanners, or a handle to the file that's still being closed in the
+/// background) are retried a few times with a backoff before giving up.
+/// If `from` is a file and `to` is on a different volume, the file is copied
+/// using [`copy()`] and then `from` is removed, as the standard library's
+/// `rename` would just fail
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    fn inner(from: &Path, to: &Path) -> io::Result<()> {
+        match retry_transient(|| std::fs::rename(from, to)) {
+            Err(why)
+                if why.kind() == io::ErrorKind::CrossesDevices
+                    && from.is_file() =>
+            {
+                copy(from, to)?;
+                remove_file(from)
+            },
+            result => result,
+        }
+    }
+    inner(from.as_ref(), to.as_ref())
+}
+
+/// Removes a file from the filesystem
+///
+/// Note that there is no guarantee that the file is immediately deleted (e.g.
+/// depending on platform, other open file descriptors may prevent immediate
+/// removal)
+///
+/// # `close_already` differences
+///
+/// On Windows, the file is deleted with POSIX semantics where the filesystem
+/// supports them (NTFS on Windows 10 1809 or later), meaning its name is freed
+/// immediately, even if other handles to it (such as ones still waiting to be
+/// closed in the background) remain open. Otherwise, a regular delete is
+/// used.
+/// Transient sharing violations are retried a few times with a backoff before
+/// giving up
+pub fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+    fn inner(path: &Path) -> io::Result<()> {
+        #[cfg(windows)]
+        {
+            retry_transient(|| windows::remove_file(path))
+        }
+        #[cfg(not(windows))]
+        {
+            std::fs::remove_file(path)
+        }
+    }
+    inner(path.as_ref())
+}
+
+/// Removes a file from the filesystem immediately, even if it's read-only or
+/// other handles to it are still open
+///
+/// This is not part of the standard library
+///
+/// # Platform-specific behaviour
+///
+/// On Windows, the file is deleted with POSIX semantics, ignoring its
+/// read-only attribute. Its name is freed immediately, even while other
+/// handles to it (including ones waiting to be closed in the background) are
+/// still open, so a new file can be created in its place straight away.
+/// Unlike [`remove_file()`], there's no fallback to a regular delete: if the
+/// filesystem doesn't support POSIX semantics (they need NTFS on Windows 10
+/// 1809 or later), an error of kind [`io::ErrorKind::Unsupported`] is
+/// returned.
+/// Transient sharing violations are retried a few times with a backoff before
+/// giving up.
+/// On other platforms, this is the same as [`std::fs::remove_file`], which
+/// already behaves this way
+pub fn remove_file_posix(path: impl AsRef<Path>) -> io::Result<()> {
+    fn inner(path: &Path) -> io::Result<()> {
+        #[cfg(windows)]
+        {
+            retry_transient(|| windows::remove_file_posix(path))
+        }
+        #[cfg(not(windows))]
+        {
+            std::fs::remove_file(path)
+        }
+    }
+    inner(path.as_ref())
+}
+
+/// Replaces one file with another, optionally keeping a backup of the
+/// original
+///
+/// `replaced` takes on the contents of `replacement`, and `replacement` no
+/// longer exists afterwards. If `backup` is given, the original contents of
+/// `replaced` are moved there, overwriting anything already at that path.
+/// If `replaced` doesn't exist, `replacement` is simply renamed to it, and no
+/// backup is made
+///
+/// This is not part of the standard library
+///
+/// # Platform-specific behaviour
+///
+/// On Windows, this uses [`ReplaceFileW`](https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-replacefilew),
+/// which preserves the attributes, ACLs, and alternate data streams of
+/// `replaced`, and retries transient sharing violations a few times with a
+/// backoff before giving up.
+/// On other platforms, the backup is made by hard linking (or copying, if
+/// that fails), and `replacement` is then renamed over `replaced`
+pub fn replace(
+    replaced: impl AsRef<Path>,
+    replacement: impl AsRef<Path>,
+    backup: Option<&Path>,
+) -> io::Result<()> {
+    fn inner(
+        replaced: &Path,
+        replacement: &Path,
+        backup: Option<&Path>,
+    ) -> io::Result<()> {
+        if !replaced.exists() {
+            return rename(replacement, replaced);
+        }
+        #[cfg(windows)]
+        {
+            retry_transient(|| windows::replace(replaced, replacement, backup))
+        }
+        #[cfg(not(windows))]
+        {
+            if let Some(backup) = backup {
+                match std::fs::remove_file(backup) {
+                    Err(why) if why.kind() != io::ErrorKind::NotFound => {
+                        return Err(why)
+                    },
+                    _ => {},
+                }
+                if std::fs::hard_link(replaced, backup).is_err() {
+                    copy(replaced, backup)?;
+                }
+            }
+            std::fs::rename(replacement, replaced)
+        }
+    }
+    inner(replaced.as_ref(), replacement.as_ref(), backup)
+}
+
 /// Write a slice as the entire contents of a file
 ///
 /// This function will create a file if it does not exist,
@@ -101,7 +279,7 @@ pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
 /// Depending on the platform, this function may fail if the
 /// full directory path does not exist
 ///
-/// This is a convenience function for using [`File::create`] and
+/// This is a convenience function for using [`std::fs::File::create`] and
 /// [`write_all`](Write::write_all) with fewer imports
 ///
 /// # `close_already` differences
@@ -112,7 +290,527 @@ pub fn write(
     contents: impl AsRef<[u8]>,
 ) -> io::Result<()> {
     fn inner(path: &Path, contents: &[u8]) -> io::Result<()> {
-        File::create(path)?.fast_close().write_all(contents)
+        let file = reopen_safely(path, || std::fs::File::create(path))?;
+        labeled(Tracked::new(file, path), path).write_all(contents)
     }
     inner(path.as_ref(), contents.as_ref())
 }
+
+/// Atomically write a slice as the entire contents of a file
+///
+/// The contents are first written to a temporary file in the same directory
+/// as `path`, which is synced to disk and then moved into place using
+/// [`replace()`], so readers of `path` will only ever see either the old or
+/// the new contents, even after a crash
+///
+/// This is not part of the standard library
+pub fn write_atomic(
+    path: impl AsRef<Path>,
+    contents: impl AsRef<[u8]>,
+) -> io::Result<()> {
+    fn inner(path: &Path, contents: &[u8]) -> io::Result<()> {
+        let temp_path = temp_sibling(path);
+        let result = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .and_then(|file| {
+                let mut file =
+                    labeled(Tracked::new(file, &temp_path), &temp_path);
+                file.write_all(contents)?;
+                // Without this, a crash after the rename can leave `path`
+                // empty, as the data may not have reached the disk yet
+                file.sync_all()
+            })
+            .and_then(|_| replace(path, &temp_path, None));
+        if result.is_err() {
+            report_cleanup(&temp_path, std::fs::remove_file(&temp_path));
+        }
+        result
+    }
+    inner(path.as_ref(), contents.as_ref())
+}
+
+/// Opens a file with the given options, returning a fast-closing handle
+///
+/// On Windows, transient sharing and lock violations are retried a few times
+/// with a backoff before giving up, the same as [`rename()`]. Files that have
+/// just been written are often briefly held open by anti-virus scanners or
+/// indexers, making an immediate reopen fail
+///
+/// This is not part of the standard library
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::fs::OpenOptions;
+///
+/// use close_already::fs;
+///
+/// fs::write("foo.txt", "Hello, world!")?;
+/// let file = fs::open_with_retry("foo.txt", OpenOptions::new().read(true))?;
+/// # drop(file);
+/// # Ok(())
+/// # }
+/// ```
+#[track_caller]
+pub fn open_with_retry(
+    path: impl AsRef<Path>,
+    options: &std::fs::OpenOptions,
+) -> io::Result<FastClose<std::fs::File>> {
+    #[track_caller]
+    fn inner(
+        path: &Path,
+        options: &std::fs::OpenOptions,
+    ) -> io::Result<FastClose<std::fs::File>> {
+        let file = retry_transient(|| options.open(path))?;
+        Ok(labeled(file, path))
+    }
+    inner(path.as_ref(), options)
+}
+
+/// Opens a file in read-only mode, returning a fast-closing handle
+///
+/// Equivalent to [`std::fs::File::open`] followed by
+/// [`fast_close`](FastCloseable::fast_close)
+///
+/// This is not part of the standard library
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::io::Read;
+///
+/// let mut file = close_already::fs::open_fast("foo.txt")?;
+/// let mut contents = String::new();
+/// file.read_to_string(&mut contents)?;
+/// # Ok(())
+/// # }
+/// ```
+#[track_caller]
+pub fn open_fast(
+    path: impl AsRef<Path>,
+) -> io::Result<FastClose<std::fs::File>> {
+    let path = path.as_ref();
+    let file = reopen_safely(path, || std::fs::File::open(path))?;
+    Ok(labeled(file, path))
+}
+
+/// Opens a file in write-only mode, creating it if it doesn't exist and
+/// truncating it if it does, returning a fast-closing handle
+///
+/// Equivalent to [`std::fs::File::create`] followed by
+/// [`fast_close`](FastCloseable::fast_close)
+///
+/// This is not part of the standard library
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::io::Write;
+///
+/// let mut file = close_already::fs::create_fast("foo.txt")?;
+/// file.write_all(b"Hello, world!")?;
+/// # Ok(())
+/// # }
+/// ```
+#[track_caller]
+pub fn create_fast(
+    path: impl AsRef<Path>,
+) -> io::Result<FastClose<std::fs::File>> {
+    let path = path.as_ref();
+    let file = reopen_safely(path, || std::fs::File::create(path))?;
+    Ok(labeled(file, path))
+}
+
+/// Extends [`std::fs::OpenOptions`] to open files straight into a [`FastClose`]
+///
+/// This is not part of the standard library
+pub trait OpenOptionsExt {
+    /// Opens a file at `path` with the options specified by `self`,
+    /// returning a fast-closing handle
+    ///
+    /// See [`std::fs::OpenOptions::open`]
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use std::fs::OpenOptions;
+    ///
+    /// use close_already::fs::OpenOptionsExt;
+    ///
+    /// let log = OpenOptions::new().append(true).open_fast("log.txt")?;
+    /// # drop(log);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn open_fast(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<FastClose<std::fs::File>>;
+}
+
+impl OpenOptionsExt for std::fs::OpenOptions {
+    #[track_caller]
+    fn open_fast(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<FastClose<std::fs::File>> {
+        let path = path.as_ref();
+        let file = reopen_safely(path, || self.open(path))?;
+        Ok(labeled(file, path))
+    }
+}
+
+/// Distinguishes temporary files created by the same process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Generates a path for a temporary file in the same directory as `path`, so
+/// that it can later be renamed over `path`
+fn temp_sibling(path: &Path) -> PathBuf {
+    let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}-{n}.tmp", process::id()));
+    path.with_file_name(name)
+}
+
+/// Wraps a handle opened from `path`, labeled with the path in the `audit`
+/// table and the `diagnostics` feature's reports (see
+/// [`FastClose::with_label`])
+#[track_caller]
+fn labeled<H: FastCloseable>(handle: H, path: &Path) -> FastClose<H> {
+    let mut fast_close = handle.fast_close();
+    // Only kept if audited, or on Windows with diagnostics enabled
+    if cfg!(any(
+        feature = "audit",
+        all(windows, feature = "diagnostics")
+    )) {
+        fast_close.note_label(path.display().to_string().into());
+    }
+    fast_close
+}
+
+/// How long [`reopen_safely`] waits for pending closures
+const REOPEN_WAIT: Duration = Duration::from_secs(1);
+
+/// Opens the file at `path` with `open`, and if it fails because the file is
+/// still open, and [reopen safety](crate::paths::set_reopen_safety) is
+/// enabled, waits for pending closures before trying again
+///
+/// With [ordering](crate::paths::set_ordering) enabled, only the closures of
+/// handles to `path` are waited for, if any are tracked. Otherwise, this
+/// waits for the backend's whole queue
+fn reopen_safely(
+    path: &Path,
+    open: impl Fn() -> io::Result<std::fs::File>,
+) -> io::Result<std::fs::File> {
+    match open() {
+        Err(why) if is_transient(&why) && crate::paths::reopen_safety() => {
+            if !crate::paths::wait_for_tracked_path(path, REOPEN_WAIT) {
+                crate::shutdown_timeout(REOPEN_WAIT);
+            }
+            open()
+        },
+        result => result,
+    }
+}
+
+/// How many times [`retry_transient`] will attempt an operation
+const RETRY_ATTEMPTS: u32 = 5;
+
+/// How long [`retry_transient`] waits after the first failure, doubling for
+/// each subsequent failure
+const RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Runs `op`, retrying it with a backoff if it fails with an error that's
+/// likely to resolve itself shortly (see [`is_transient`])
+fn retry_transient<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut backoff = RETRY_BACKOFF;
+    for _ in 1..RETRY_ATTEMPTS {
+        match op() {
+            Err(why) if is_transient(&why) => {
+                thread::sleep(backoff);
+                backoff *= 2;
+            },
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Whether an error is caused by another handle temporarily holding a file
+/// open in an incompatible way
+///
+/// Always false on non-Windows platforms
+fn is_transient(err: &io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{
+            ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION,
+        };
+
+        matches!(
+            err.raw_os_error().map(|code| code as u32),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+        )
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = err;
+        false
+    }
+}
+
+/// Windows-specific implementations of `fs` functions
+#[cfg(windows)]
+mod windows {
+    use std::{
+        ffi::{c_void, OsStr},
+        fs::{File, OpenOptions},
+        io, iter, mem,
+        os::windows::prelude::*,
+        path::Path,
+        ptr,
+    };
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_INVALID_PARAMETER, ERROR_NOT_SUPPORTED, HANDLE},
+        Storage::FileSystem::{
+            FileDispositionInfoEx, ReplaceFileW, SetFileInformationByHandle,
+            DELETE, FILE_DISPOSITION_FLAG_DELETE,
+            FILE_DISPOSITION_FLAG_IGNORE_READONLY_ATTRIBUTE,
+            FILE_DISPOSITION_FLAG_POSIX_SEMANTICS, FILE_DISPOSITION_INFO_EX,
+            FILE_DISPOSITION_INFO_EX_FLAGS, FILE_FLAG_OPEN_REPARSE_POINT,
+            FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+            REPLACEFILE_IGNORE_MERGE_ERRORS,
+        },
+    };
+
+    use crate::{paths::Tracked, FastClose};
+
+    /// Deletes a file using POSIX semantics, falling back to a regular delete
+    /// if the filesystem doesn't support them
+    pub(super) fn remove_file(path: &Path) -> io::Result<()> {
+        let file = open_for_delete(path)?;
+        match delete_posix(&file, 0) {
+            // Filesystem or Windows version doesn't support POSIX semantics
+            Err(why) if why.kind() == io::ErrorKind::Unsupported => {
+                drop(file);
+                std::fs::remove_file(path)
+            },
+            result => result,
+        }
+    }
+
+    /// Deletes a file using POSIX semantics, even if it's read-only
+    pub(super) fn remove_file_posix(path: &Path) -> io::Result<()> {
+        let file = open_for_delete(path)?;
+        delete_posix(&file, FILE_DISPOSITION_FLAG_IGNORE_READONLY_ATTRIBUTE)
+    }
+
+    /// Opens a file with only delete access, sharing it with any other
+    /// handles
+    fn open_for_delete(path: &Path) -> io::Result<FastClose<Tracked<File>>> {
+        let file = OpenOptions::new()
+            .access_mode(DELETE)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+            // Delete symlinks themselves, not their targets
+            .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT)
+            .open(path)?;
+        Ok(super::labeled(Tracked::new(file, path), path))
+    }
+
+    /// Marks an open file for deletion with POSIX semantics, and any extra
+    /// `flags`. Fails with [`io::ErrorKind::Unsupported`] if the filesystem
+    /// or Windows version doesn't support POSIX semantics
+    fn delete_posix(
+        file: &File,
+        flags: FILE_DISPOSITION_INFO_EX_FLAGS,
+    ) -> io::Result<()> {
+        let info = FILE_DISPOSITION_INFO_EX {
+            Flags: FILE_DISPOSITION_FLAG_DELETE
+                | FILE_DISPOSITION_FLAG_POSIX_SEMANTICS
+                | flags,
+        };
+        // SAFETY: the handle is valid for as long as `file` is alive, and the
+        // buffer is a FILE_DISPOSITION_INFO_EX of the size given
+        let succeeded = unsafe {
+            SetFileInformationByHandle(
+                file.as_raw_handle() as HANDLE,
+                FileDispositionInfoEx,
+                &info as *const FILE_DISPOSITION_INFO_EX as *const c_void,
+                mem::size_of::<FILE_DISPOSITION_INFO_EX>() as u32,
+            )
+        };
+        if succeeded != 0 {
+            return Ok(());
+        }
+        let why = io::Error::last_os_error();
+        match why.raw_os_error().map(|code| code as u32) {
+            Some(ERROR_INVALID_PARAMETER | ERROR_NOT_SUPPORTED) => {
+                Err(io::Error::new(io::ErrorKind::Unsupported, why))
+            },
+            _ => Err(why),
+        }
+    }
+
+    /// Replaces `replaced` with `replacement` using `ReplaceFileW`
+    pub(super) fn replace(
+        replaced: &Path,
+        replacement: &Path,
+        backup: Option<&Path>,
+    ) -> io::Result<()> {
+        let replaced = to_wide(replaced.as_os_str());
+        let replacement = to_wide(replacement.as_os_str());
+        let backup = backup.map(|backup| to_wide(backup.as_os_str()));
+        // SAFETY: all paths are null-terminated wide strings that outlive the
+        // call, and the backup path & reserved parameters may be null
+        let succeeded = unsafe {
+            ReplaceFileW(
+                replaced.as_ptr(),
+                replacement.as_ptr(),
+                backup
+                    .as_ref()
+                    .map_or(ptr::null(), |backup| backup.as_ptr()),
+                REPLACEFILE_IGNORE_MERGE_ERRORS,
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        if succeeded != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Converts a string to a null-terminated wide string for use with the
+    /// Windows API
+    fn to_wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(iter::once(0)).collect()
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Runs `test` inside a tokio runtime when using the tokio backend, as
+    /// dropping `FastClose` outside of one will cause a panic
+    pub(crate) fn in_runtime(test: impl FnOnce()) {
+        #[cfg(feature = "backend-tokio")]
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        #[cfg(feature = "backend-tokio")]
+        let _guard = runtime.enter();
+        test()
+    }
+
+    #[test]
+    fn rename_moves_file() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let from = dir.path().join("from");
+            let to = dir.path().join("to");
+            write(&from, b"hello").unwrap();
+            rename(&from, &to).expect("rename should succeed");
+            assert!(!from.exists(), "source should no longer exist");
+            assert_eq!(read(&to).unwrap(), b"hello");
+        });
+    }
+
+    #[test]
+    fn remove_file_while_open() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            let file = File::create(&path).unwrap();
+            remove_file(&path).expect("remove should succeed");
+            assert!(!path.exists(), "file should no longer exist");
+            drop(file);
+        });
+    }
+
+    #[test]
+    fn remove_read_only_file_while_open() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            let file = File::create(&path).unwrap();
+            let mut permissions = file.metadata().unwrap().permissions();
+            permissions.set_readonly(true);
+            std::fs::set_permissions(&path, permissions).unwrap();
+            remove_file_posix(&path).expect("remove should succeed");
+            assert!(!path.exists(), "file should no longer exist");
+            drop(file);
+        });
+    }
+
+    #[test]
+    fn replace_keeps_backup() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let replaced = dir.path().join("replaced");
+            let replacement = dir.path().join("replacement");
+            let backup = dir.path().join("backup");
+            write(&replaced, b"old").unwrap();
+            write(&replacement, b"new").unwrap();
+            replace(&replaced, &replacement, Some(&backup))
+                .expect("replace should succeed");
+            assert_eq!(read(&replaced).unwrap(), b"new");
+            assert_eq!(read(&backup).unwrap(), b"old");
+            assert!(!replacement.exists(), "replacement should be moved");
+        });
+    }
+
+    #[test]
+    fn open_and_create_fast() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("open_fast.txt");
+            create_fast(&path).unwrap().write_all(b"hello").unwrap();
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open_fast(&path)
+                .unwrap()
+                .write_all(b" world")
+                .unwrap();
+            let mut contents = String::new();
+            open_fast(&path)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, "hello world");
+        });
+    }
+
+    #[test]
+    fn open_with_retry_opens_file() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            write(&path, b"hello").unwrap();
+            let mut file =
+                open_with_retry(&path, std::fs::OpenOptions::new().read(true))
+                    .unwrap();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert_eq!(contents, b"hello");
+            let missing = open_with_retry(
+                dir.path().join("missing"),
+                std::fs::OpenOptions::new().read(true),
+            );
+            assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        });
+    }
+
+    #[test]
+    fn write_atomic_leaves_no_temp_files() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            write_atomic(&path, b"first").unwrap();
+            write_atomic(&path, b"second").unwrap();
+            assert_eq!(read(&path).unwrap(), b"second");
+            let entries = std::fs::read_dir(dir.path()).unwrap().count();
+            assert_eq!(entries, 1, "temporary files should not remain");
+        });
+    }
+}
@@ -0,0 +1,226 @@
+//! Parallel statistics for directory trees
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+};
+
+use super::read_dir;
+
+/// Computes the total size of the files in a directory tree, along with how
+/// many files there are and a breakdown by file extension
+///
+/// The tree is walked by one thread per CPU core, using [`read_dir()`], so
+/// each entry's metadata is retrieved as its directory is enumerated.
+/// Symbolic links aren't followed, and aren't counted as files. If any
+/// directory can't be read, the walk stops and the first error encountered is
+/// returned
+///
+/// This is not part of the standard library
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// let stats = close_already::fs::dir_size("target")?;
+/// println!("{} files, {} bytes", stats.files, stats.size);
+/// for (extension, by_extension) in &stats.by_extension {
+///     println!("{extension:?}: {} bytes", by_extension.size);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn dir_size(path: impl AsRef<Path>) -> io::Result<DirStats> {
+    let walk = Walk {
+        state: Mutex::new(WalkState {
+            dirs: vec![path.as_ref().to_owned()],
+            active: 0,
+            error: None,
+        }),
+        changed: Condvar::new(),
+    };
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let stats = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| scope.spawn(|| walk.work()))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("dir_size worker panicked"))
+            .fold(DirStats::default(), DirStats::merge)
+    });
+    let state = walk
+        .state
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner);
+    match state.error {
+        Some(why) => Err(why),
+        None => Ok(stats),
+    }
+}
+
+/// Statistics about the files in a directory tree, returned by [`dir_size`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DirStats {
+    /// The total size of all the files, in bytes
+    pub size: u64,
+    /// The number of files
+    pub files: u64,
+    /// The number of directories, not including the one walked
+    pub dirs: u64,
+    /// The size and number of files with each extension. Files without an
+    /// extension are under an empty string
+    pub by_extension: BTreeMap<OsString, ExtensionStats>,
+}
+
+/// Statistics about the files with one extension, in [`DirStats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExtensionStats {
+    /// The total size of the files, in bytes
+    pub size: u64,
+    /// The number of files
+    pub files: u64,
+}
+
+impl DirStats {
+    fn add_file(&mut self, path: &Path, size: u64) {
+        self.size += size;
+        self.files += 1;
+        let extension = path.extension().unwrap_or_default().to_owned();
+        let by_extension = self.by_extension.entry(extension).or_default();
+        by_extension.size += size;
+        by_extension.files += 1;
+    }
+
+    fn merge(mut self, other: DirStats) -> DirStats {
+        self.size += other.size;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        for (extension, stats) in other.by_extension {
+            let by_extension = self.by_extension.entry(extension).or_default();
+            by_extension.size += stats.size;
+            by_extension.files += stats.files;
+        }
+        self
+    }
+}
+
+/// The directories shared between the threads walking a tree
+struct Walk {
+    state: Mutex<WalkState>,
+    /// Notified when directories are queued or finished
+    changed: Condvar,
+}
+
+struct WalkState {
+    /// Directories waiting to be read
+    dirs: Vec<PathBuf>,
+    /// The number of directories being read, which may queue more
+    active: usize,
+    /// The first error encountered, which stops the walk
+    error: Option<io::Error>,
+}
+
+impl Walk {
+    /// Reads directories until there are none left, returning the
+    /// statistics for those this thread read
+    fn work(&self) -> DirStats {
+        let mut stats = DirStats::default();
+        while let Some(dir) = self.next_dir() {
+            let result = scan(&dir, &mut stats);
+            self.finish_dir(result);
+        }
+        stats
+    }
+
+    /// Waits for a directory to read, or returns `None` once the walk has
+    /// finished or failed
+    fn next_dir(&self) -> Option<PathBuf> {
+        let mut state = self.lock();
+        loop {
+            if state.error.is_some() {
+                return None;
+            }
+            if let Some(dir) = state.dirs.pop() {
+                state.active += 1;
+                return Some(dir);
+            }
+            if state.active == 0 {
+                return None;
+            }
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Queues the subdirectories found in a directory, or records why it
+    /// couldn't be read
+    fn finish_dir(&self, result: io::Result<Vec<PathBuf>>) {
+        let mut state = self.lock();
+        state.active -= 1;
+        match result {
+            Ok(subdirs) => state.dirs.extend(subdirs),
+            Err(why) => {
+                state.error.get_or_insert(why);
+            },
+        }
+        self.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WalkState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Adds the files in `dir` to `stats`, returning its subdirectories
+fn scan(dir: &Path, stats: &mut DirStats) -> io::Result<Vec<PathBuf>> {
+    let mut subdirs = Vec::new();
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            stats.dirs += 1;
+            subdirs.push(entry.into());
+        } else if metadata.is_file() {
+            stats.add_file(entry.as_ref(), metadata.len());
+        }
+    }
+    Ok(subdirs)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+    use crate::fs::{tests::in_runtime, write};
+
+    #[test]
+    fn totals_tree() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let nested = dir.path().join("a").join("b");
+            std::fs::create_dir_all(&nested).unwrap();
+            write(dir.path().join("one.txt"), "1").unwrap();
+            write(dir.path().join("a").join("two.txt"), "22").unwrap();
+            write(nested.join("three.rs"), "333").unwrap();
+            write(nested.join("README"), "4444").unwrap();
+
+            let stats = dir_size(dir.path()).unwrap();
+            assert_eq!(stats.size, 10);
+            assert_eq!(stats.files, 4);
+            assert_eq!(stats.dirs, 2);
+            let txt = stats.by_extension[&OsString::from("txt")];
+            assert_eq!((txt.size, txt.files), (3, 2));
+            let none = stats.by_extension[&OsString::new()];
+            assert_eq!((none.size, none.files), (4, 1));
+
+            let missing = dir_size(dir.path().join("missing"));
+            assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        });
+    }
+}
@@ -0,0 +1,328 @@
+//! A fast-closing replacement for [`std::fs::File`]
+use std::{
+    fmt::Arguments,
+    fs::{Metadata, Permissions},
+    io::{self, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::OpenOptions;
+use crate::{FastClose, FastCloseable};
+
+/// An open file, which is closed in the background when dropped
+///
+/// This has the same methods as [`std::fs::File`], so code can switch to it
+/// by changing the import. It wraps a `FastClose<std::fs::File>`, which can
+/// be converted to and from with [`From`], to use the rest of `FastClose`'s
+/// methods, or to give it settings with [`FastCloseWith`](crate::FastCloseWith)
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::io::Write;
+///
+/// use close_already::fs::File;
+///
+/// let mut file = File::create("foo.txt")?;
+/// file.write_all(b"Hello, world!")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # `close_already` differences
+///
+/// Files opened with [`open`](File::open), [`create`](File::create), and
+/// [`create_new`](File::create_new) are labeled with their path in the
+/// `audit` table and the `diagnostics` feature's reports (see
+/// [`FastClose::with_label`]). [`try_clone`](File::try_clone) returns another
+/// fast-closing `File`
+#[derive(Debug)]
+pub struct File(FastClose<std::fs::File>);
+
+impl File {
+    /// Attempts to open a file in read-only mode
+    ///
+    /// See [`std::fs::File::open`]
+    #[track_caller]
+    pub fn open(path: impl AsRef<Path>) -> io::Result<File> {
+        super::open_fast(path).map(File)
+    }
+
+    /// Opens a file in write-only mode, creating it if it doesn't exist and
+    /// truncating it if it does
+    ///
+    /// See [`std::fs::File::create`]
+    #[track_caller]
+    pub fn create(path: impl AsRef<Path>) -> io::Result<File> {
+        super::create_fast(path).map(File)
+    }
+
+    /// Creates a new file in read-write mode, failing if it already exists
+    ///
+    /// See [`std::fs::File::create_new`]
+    #[track_caller]
+    pub fn create_new(path: impl AsRef<Path>) -> io::Result<File> {
+        let path = path.as_ref();
+        let file = std::fs::File::create_new(path)?;
+        Ok(File(super::labeled(file, path)))
+    }
+
+    /// Returns a new set of options to open a file with, all set to `false`
+    ///
+    /// See [`std::fs::File::options`]. Files opened with them are returned as
+    /// a [`FastClose`], which can be converted into a `File`
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    /// Wraps a file from the standard library
+    #[track_caller]
+    pub fn from_std(file: std::fs::File) -> File {
+        File(file.fast_close())
+    }
+
+    /// Gets back the file from the standard library, which will no longer
+    /// be closed in the background
+    pub fn into_std(self) -> std::fs::File {
+        self.0.into_inner()
+    }
+
+    /// Queries metadata about the file
+    ///
+    /// See [`std::fs::File::metadata`]
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        self.0.metadata()
+    }
+
+    /// Truncates or extends the file to `size` bytes
+    ///
+    /// See [`std::fs::File::set_len`]
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        self.0.set_len(size)
+    }
+
+    /// Changes the permissions of the file
+    ///
+    /// See [`std::fs::File::set_permissions`]
+    pub fn set_permissions(&self, permissions: Permissions) -> io::Result<()> {
+        self.0.set_permissions(permissions)
+    }
+
+    /// Changes the file's last modification time
+    ///
+    /// See [`std::fs::File::set_modified`]
+    pub fn set_modified(&self, time: std::time::SystemTime) -> io::Result<()> {
+        self.0.set_modified(time)
+    }
+
+    /// Changes the file's timestamps
+    ///
+    /// See [`std::fs::File::set_times`]
+    pub fn set_times(&self, times: std::fs::FileTimes) -> io::Result<()> {
+        self.0.set_times(times)
+    }
+
+    /// Flushes the file's contents and metadata to disk
+    ///
+    /// See [`std::fs::File::sync_all`]
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.0.sync_all()
+    }
+
+    /// Flushes the file's contents to disk, but not necessarily its metadata
+    ///
+    /// See [`std::fs::File::sync_data`]
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.0.sync_data()
+    }
+
+    /// Creates another handle to the same file, which is also closed in the
+    /// background
+    ///
+    /// See [`std::fs::File::try_clone`]
+    pub fn try_clone(&self) -> io::Result<File> {
+        self.0.try_clone().map(File)
+    }
+}
+
+impl From<FastClose<std::fs::File>> for File {
+    fn from(file: FastClose<std::fs::File>) -> Self {
+        File(file)
+    }
+}
+
+impl From<File> for FastClose<std::fs::File> {
+    fn from(file: File) -> Self {
+        file.0
+    }
+}
+
+impl From<std::fs::File> for File {
+    #[track_caller]
+    fn from(file: std::fs::File) -> Self {
+        File::from_std(file)
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        self.0.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.0.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.0.read_to_string(buf)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.0.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+
+    fn write_fmt(&mut self, fmt: Arguments<'_>) -> io::Result<()> {
+        self.0.write_fmt(fmt)
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+// Like `std::fs::File`, files can be used through a shared reference
+impl Read for &File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.0).read(buf)
+    }
+
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        (&self.0).read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        (&self.0).read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        (&self.0).read_to_string(buf)
+    }
+}
+
+impl Write for &File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.0).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&self.0).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.0).flush()
+    }
+}
+
+impl Seek for &File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        (&self.0).seek(pos)
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsFd for File {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for File {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::fs::FileExt for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.0.read_at(buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.0.write_at(buf, offset)
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::io::AsHandle for File {
+    fn as_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+        self.0.as_handle()
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::io::AsRawHandle for File {
+    fn as_raw_handle(&self) -> std::os::windows::io::RawHandle {
+        self.0.as_raw_handle()
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::fs::FileExt for File {
+    fn seek_read(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.0.seek_read(buf, offset)
+    }
+
+    fn seek_write(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.0.seek_write(buf, offset)
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn mirrors_std_file() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            let mut file = File::create_new(&path).unwrap();
+            file.write_all(b"hello").unwrap();
+            file.sync_all().unwrap();
+            assert!(File::create_new(&path).is_err(), "file already exists");
+
+            let mut clone = file.try_clone().unwrap();
+            clone.rewind().unwrap();
+            let mut contents = String::new();
+            clone.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "hello");
+
+            file.set_len(2).unwrap();
+            assert_eq!(File::open(&path).unwrap().metadata().unwrap().len(), 2);
+        });
+    }
+}
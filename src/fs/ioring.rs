@@ -0,0 +1,356 @@
+//! Batched reads using the IoRing API, available from Windows 11
+//!
+//! The API is loaded at runtime, as its DLL doesn't exist on older versions
+//! of Windows, and linking to it would stop programs from starting there
+use std::{
+    fs::File,
+    io,
+    io::{Read, Seek, SeekFrom},
+    iter, mem,
+    os::windows::prelude::*,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use windows_sys::{
+    core::HRESULT,
+    Win32::{
+        Foundation::{ERROR_HANDLE_EOF, HANDLE, S_OK},
+        Storage::FileSystem::{
+            HIORING, IORING_BUFFER_REF, IORING_BUFFER_REF_0, IORING_CQE,
+            IORING_CREATE_ADVISORY_FLAGS_NONE, IORING_CREATE_FLAGS,
+            IORING_CREATE_REQUIRED_FLAGS_NONE, IORING_HANDLE_REF,
+            IORING_HANDLE_REF_0, IORING_REF_RAW, IORING_SQE_FLAGS,
+            IORING_VERSION, IORING_VERSION_1, IOSQE_FLAGS_NONE,
+        },
+        System::LibraryLoader::{
+            GetProcAddress, LoadLibraryExW, LOAD_LIBRARY_SEARCH_SYSTEM32,
+        },
+    },
+};
+
+use super::{labeled, reopen_safely};
+use crate::{paths::Tracked, FastClose};
+
+/// A file's path, and the result of reading it
+type Contents = (PathBuf, io::Result<Vec<u8>>);
+
+/// The most reads submitted to a ring at once
+const MAX_BATCH: usize = 64;
+
+/// Makes `SubmitIoRing` wait without a timeout
+const INFINITE: u32 = u32::MAX;
+
+/// A function as returned by `GetProcAddress`
+type Proc = unsafe extern "system" fn() -> isize;
+type CreateIoRing = unsafe extern "system" fn(
+    IORING_VERSION,
+    IORING_CREATE_FLAGS,
+    u32,
+    u32,
+    *mut HIORING,
+) -> HRESULT;
+type BuildIoRingReadFile = unsafe extern "system" fn(
+    HIORING,
+    IORING_HANDLE_REF,
+    IORING_BUFFER_REF,
+    u32,
+    u64,
+    usize,
+    IORING_SQE_FLAGS,
+) -> HRESULT;
+type SubmitIoRing =
+    unsafe extern "system" fn(HIORING, u32, u32, *mut u32) -> HRESULT;
+type PopIoRingCompletion =
+    unsafe extern "system" fn(HIORING, *mut IORING_CQE) -> HRESULT;
+type CloseIoRing = unsafe extern "system" fn(HIORING) -> HRESULT;
+
+/// The IoRing functions, loaded from the system's DLL
+struct Api {
+    create: CreateIoRing,
+    build_read: BuildIoRingReadFile,
+    submit: SubmitIoRing,
+    pop: PopIoRingCompletion,
+    close: CloseIoRing,
+}
+
+impl Api {
+    /// Gets the IoRing API, loading it the first time, or `None` if this
+    /// version of Windows doesn't support it
+    fn get() -> Option<&'static Api> {
+        static API: OnceLock<Option<Api>> = OnceLock::new();
+        API.get_or_init(Api::load).as_ref()
+    }
+
+    fn load() -> Option<Api> {
+        let name = "api-ms-win-core-ioring-l1-1-0.dll"
+            .encode_utf16()
+            .chain(iter::once(0))
+            .collect::<Vec<_>>();
+        // SAFETY: the name is a null-terminated wide string, and the
+        // reserved file handle must be null
+        let module = unsafe {
+            LoadLibraryExW(name.as_ptr(), 0, LOAD_LIBRARY_SEARCH_SYSTEM32)
+        };
+        if module == 0 {
+            return None;
+        }
+        macro_rules! function {
+            ($name:ident) => {{
+                // SAFETY: the module is loaded, and the name is a
+                // null-terminated string
+                let function = unsafe {
+                    GetProcAddress(
+                        module,
+                        concat!(stringify!($name), "\0").as_ptr(),
+                    )
+                }?;
+                // SAFETY: the function has the documented signature of the
+                // type it's named after
+                unsafe { mem::transmute::<Proc, $name>(function) }
+            }};
+        }
+        let api = Api {
+            create: function!(CreateIoRing),
+            build_read: function!(BuildIoRingReadFile),
+            submit: function!(SubmitIoRing),
+            pop: function!(PopIoRingCompletion),
+            close: function!(CloseIoRing),
+        };
+        // The DLL can exist without the kernel supporting reads
+        Ring::new(&api, 1).ok()?;
+        Some(api)
+    }
+}
+
+/// Reads the entire contents of each file in batches of up to `batch` files,
+/// submitting each batch's reads with a single system call
+///
+/// Gives `paths` back if IoRing isn't supported
+pub(crate) fn read_many(
+    paths: Vec<PathBuf>,
+    batch: usize,
+) -> Result<Vec<Contents>, Vec<PathBuf>> {
+    let Some(api) = Api::get() else {
+        return Err(paths);
+    };
+    let mut contents = Vec::with_capacity(paths.len());
+    let mut paths = paths.into_iter().peekable();
+    while paths.peek().is_some() {
+        let batch = paths
+            .by_ref()
+            .take(batch.clamp(1, MAX_BATCH))
+            .collect::<Vec<_>>();
+        contents.extend(read_batch(api, batch));
+    }
+    Ok(contents)
+}
+
+/// Reads a batch of files, queueing a read on a new ring for each one that
+/// opened. Those that can't be queued are read the same as
+/// [`fs::read`](super::read)
+fn read_batch(api: &Api, paths: Vec<PathBuf>) -> Vec<Contents> {
+    let mut files = paths
+        .iter()
+        .map(|path| Pending::open(path))
+        .collect::<Vec<_>>();
+    if let Ok(ring) = Ring::new(api, files.len() as u32) {
+        ring.read_into(&mut files);
+    }
+    paths
+        .into_iter()
+        .zip(files)
+        .map(|(path, file)| (path, file.finish()))
+        .collect()
+}
+
+/// A file being read as part of a batch
+struct Pending {
+    file: io::Result<FastClose<Tracked<File>>>,
+    /// Sized to one byte more than the file, so a short read shows the
+    /// whole file was read without another read to find the end of it
+    contents: Vec<u8>,
+    /// How many bytes the ring read into `contents`, once it's done so
+    read: Option<io::Result<usize>>,
+}
+
+impl Pending {
+    fn open(path: &Path) -> Pending {
+        let file = reopen_safely(path, || File::open(path))
+            .map(|file| labeled(Tracked::new(file, path), path));
+        let size = file
+            .as_ref()
+            .ok()
+            .and_then(|file| file.metadata().ok())
+            .map_or(0, |metadata| metadata.len() as usize);
+        Pending {
+            file,
+            contents: vec![0; size.saturating_add(1)],
+            read: None,
+        }
+    }
+
+    /// Finishes reading the file, reading it directly if the ring didn't or
+    /// if it's grown since it was opened
+    fn finish(self) -> io::Result<Vec<u8>> {
+        let mut file = self.file?;
+        let mut contents = self.contents;
+        match self.read {
+            Some(Ok(read)) if read < contents.len() => {
+                contents.truncate(read);
+                return Ok(contents);
+            },
+            Some(Ok(read)) => {
+                file.seek(SeekFrom::Start(read as u64))?;
+            },
+            Some(Err(why)) => return Err(why),
+            None => contents.clear(),
+        }
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+/// An IoRing, closed when dropped
+struct Ring<'a> {
+    api: &'a Api,
+    handle: HIORING,
+}
+
+impl<'a> Ring<'a> {
+    /// Creates a ring with room for `entries` operations
+    fn new(api: &'a Api, entries: u32) -> io::Result<Ring<'a>> {
+        let flags = IORING_CREATE_FLAGS {
+            Required: IORING_CREATE_REQUIRED_FLAGS_NONE,
+            Advisory: IORING_CREATE_ADVISORY_FLAGS_NONE,
+        };
+        let mut handle = 0;
+        // SAFETY: `handle` is a valid place for the ring's handle to be
+        // written to
+        check(unsafe {
+            (api.create)(IORING_VERSION_1, flags, entries, entries, &mut handle)
+        })?;
+        Ok(Ring { api, handle })
+    }
+
+    /// Reads every opened file into its buffer at once, recording how much
+    /// of each was read
+    fn read_into(&self, files: &mut [Pending]) {
+        let mut queued = Vec::with_capacity(files.len());
+        for (index, pending) in files.iter_mut().enumerate() {
+            let Ok(file) = &pending.file else { continue };
+            let Ok(len) = u32::try_from(pending.contents.len()) else {
+                continue;
+            };
+            let file = IORING_HANDLE_REF {
+                Kind: IORING_REF_RAW,
+                Handle: IORING_HANDLE_REF_0 {
+                    Handle: file.as_raw_handle() as HANDLE,
+                },
+            };
+            let buffer = IORING_BUFFER_REF {
+                Kind: IORING_REF_RAW,
+                Buffer: IORING_BUFFER_REF_0 {
+                    Address: pending.contents.as_mut_ptr().cast(),
+                },
+            };
+            // SAFETY: the handle and buffer aren't used or dropped until the
+            // read completes, or are leaked if it might not have
+            let built = unsafe {
+                (self.api.build_read)(
+                    self.handle,
+                    file,
+                    buffer,
+                    len,
+                    0,
+                    index,
+                    IOSQE_FLAGS_NONE,
+                )
+            };
+            if built >= 0 {
+                queued.push(index);
+            }
+        }
+        if queued.is_empty() {
+            return;
+        }
+
+        let mut submitted = 0;
+        // SAFETY: `submitted` is a valid place for the count to be written
+        let result = check(unsafe {
+            (self.api.submit)(
+                self.handle,
+                queued.len() as u32,
+                INFINITE,
+                &mut submitted,
+            )
+        });
+        // Nothing is in flight, so the files can be read directly instead
+        if result.is_err() && submitted == 0 {
+            return;
+        }
+
+        let mut completion = IORING_CQE {
+            UserData: 0,
+            ResultCode: S_OK,
+            Information: 0,
+        };
+        // SAFETY: `completion` is a valid place for a completion to be
+        // written to
+        while unsafe { (self.api.pop)(self.handle, &mut completion) } == S_OK {
+            if let Some(pending) = files.get_mut(completion.UserData) {
+                pending.read = Some(match check(completion.ResultCode) {
+                    Ok(()) => Ok(completion.Information),
+                    // Reading from the end of an empty file
+                    Err(why)
+                        if why.raw_os_error()
+                            == Some(ERROR_HANDLE_EOF as i32) =>
+                    {
+                        Ok(0)
+                    },
+                    Err(why) => Err(why),
+                });
+            }
+        }
+
+        for index in queued {
+            let pending = &mut files[index];
+            if pending.read.is_none() {
+                // The read may still be writing to the buffer, so it's
+                // leaked rather than freed from under it
+                mem::forget(mem::take(&mut pending.contents));
+                pending.read = Some(Err(match &result {
+                    Err(why) => copy_error(why),
+                    Ok(()) => io::Error::other("read didn't complete"),
+                }));
+            }
+        }
+    }
+}
+
+impl Drop for Ring<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the handle is a ring that hasn't been closed yet
+        unsafe { (self.api.close)(self.handle) };
+    }
+}
+
+/// Converts an `HRESULT` into a result, unwrapping Win32 error codes
+fn check(code: HRESULT) -> io::Result<()> {
+    if code >= 0 {
+        Ok(())
+    } else if code as u32 & 0xFFFF_0000 == 0x8007_0000 {
+        // Made by HRESULT_FROM_WIN32
+        Err(io::Error::from_raw_os_error(code & 0xFFFF))
+    } else {
+        Err(io::Error::from_raw_os_error(code))
+    }
+}
+
+/// Copies an error, as [`io::Error`] isn't `Clone`
+fn copy_error(why: &io::Error) -> io::Error {
+    why.raw_os_error().map_or_else(
+        || io::Error::new(why.kind(), why.to_string()),
+        io::Error::from_raw_os_error,
+    )
+}
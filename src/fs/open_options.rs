@@ -0,0 +1,205 @@
+//! A fast-closing replacement for [`std::fs::OpenOptions`]
+use std::{io, path::Path};
+
+use super::OpenOptionsExt as _;
+use crate::FastClose;
+
+/// Options for how a file is opened, opening it into a [`FastClose`]
+///
+/// This has the same methods as [`std::fs::OpenOptions`], as well as its
+/// platform-specific extensions (e.g. `share_mode` and `custom_flags` from
+/// `std::os::windows::fs::OpenOptionsExt` on Windows), so code can switch to
+/// it by changing the import
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use close_already::fs::OpenOptions;
+///
+/// let log = OpenOptions::new()
+///     .append(true)
+///     .create(true)
+///     .open("log.txt")?;
+/// # drop(log);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # `close_already` differences
+///
+/// [`open`](OpenOptions::open) returns a [`FastClose`], which can be
+/// converted into a [`File`](super::File). It's labeled with the file's path
+/// in the `audit` table and the `diagnostics` feature's reports (see
+/// [`FastClose::with_label`])
+#[derive(Debug, Clone)]
+pub struct OpenOptions(std::fs::OpenOptions);
+
+impl OpenOptions {
+    /// Creates a blank set of options, all set to `false`
+    ///
+    /// See [`std::fs::OpenOptions::new`]
+    pub fn new() -> OpenOptions {
+        OpenOptions(std::fs::OpenOptions::new())
+    }
+
+    /// Sets whether the file should be readable
+    ///
+    /// See [`std::fs::OpenOptions::read`]
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.0.read(read);
+        self
+    }
+
+    /// Sets whether the file should be writable
+    ///
+    /// See [`std::fs::OpenOptions::write`]
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.0.write(write);
+        self
+    }
+
+    /// Sets whether writes should be appended to the end of the file
+    ///
+    /// See [`std::fs::OpenOptions::append`]
+    pub fn append(&mut self, append: bool) -> &mut OpenOptions {
+        self.0.append(append);
+        self
+    }
+
+    /// Sets whether the file should be truncated if it exists
+    ///
+    /// See [`std::fs::OpenOptions::truncate`]
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.0.truncate(truncate);
+        self
+    }
+
+    /// Sets whether the file should be created if it doesn't exist
+    ///
+    /// See [`std::fs::OpenOptions::create`]
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.0.create(create);
+        self
+    }
+
+    /// Sets whether a new file must be created, failing if it already exists
+    ///
+    /// See [`std::fs::OpenOptions::create_new`]
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.0.create_new(create_new);
+        self
+    }
+
+    /// Opens a file at `path` with these options, returning a fast-closing
+    /// handle
+    ///
+    /// See [`std::fs::OpenOptions::open`]
+    #[track_caller]
+    pub fn open(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<FastClose<std::fs::File>> {
+        self.0.open_fast(path)
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        OpenOptions::new()
+    }
+}
+
+impl From<std::fs::OpenOptions> for OpenOptions {
+    fn from(options: std::fs::OpenOptions) -> Self {
+        OpenOptions(options)
+    }
+}
+
+impl From<OpenOptions> for std::fs::OpenOptions {
+    fn from(options: OpenOptions) -> Self {
+        options.0
+    }
+}
+
+impl AsRef<std::fs::OpenOptions> for OpenOptions {
+    fn as_ref(&self) -> &std::fs::OpenOptions {
+        &self.0
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::fs::OpenOptionsExt for OpenOptions {
+    fn mode(&mut self, mode: u32) -> &mut Self {
+        self.0.mode(mode);
+        self
+    }
+
+    fn custom_flags(&mut self, flags: i32) -> &mut Self {
+        self.0.custom_flags(flags);
+        self
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::fs::OpenOptionsExt for OpenOptions {
+    fn access_mode(&mut self, access: u32) -> &mut Self {
+        self.0.access_mode(access);
+        self
+    }
+
+    fn share_mode(&mut self, share: u32) -> &mut Self {
+        self.0.share_mode(share);
+        self
+    }
+
+    fn custom_flags(&mut self, flags: u32) -> &mut Self {
+        self.0.custom_flags(flags);
+        self
+    }
+
+    fn attributes(&mut self, attributes: u32) -> &mut Self {
+        self.0.attributes(attributes);
+        self
+    }
+
+    fn security_qos_flags(&mut self, flags: u32) -> &mut Self {
+        self.0.security_qos_flags(flags);
+        self
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn opens_fast_closing_files() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options.open(&path).unwrap().write_all(b"hello").unwrap();
+            assert!(options.open(&path).is_err(), "file already exists");
+
+            let mut contents = String::new();
+            OpenOptions::new()
+                .read(true)
+                .open(&path)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, "hello");
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = path.metadata().unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+        });
+    }
+}
@@ -0,0 +1,135 @@
+//! Directory listing with metadata retrieved during enumeration
+use std::{
+    ffi::OsString,
+    fs::{FileType, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Returns an iterator over the entries within a directory, with each entry's
+/// metadata already retrieved
+///
+/// The iterator will yield instances of [`io::Result`]`<`[`DirEntry`]`>`.
+/// New errors may be encountered after an iterator is initially constructed.
+/// Entries for the current and parent directories (typically `.` and `..`)
+/// are skipped
+///
+/// The order in which this iterator returns entries is platform and
+/// filesystem dependent. Use [`ReadDir::sorted`] to get them in order
+///
+/// # `close_already` differences
+///
+/// Each entry's metadata is retrieved as the directory is enumerated, so
+/// [`DirEntry::metadata`] never touches the filesystem. On Windows, this
+/// comes for free from the directory enumeration itself, so no file has to be
+/// opened to get its metadata. Elsewhere, this is equivalent to calling
+/// [`std::fs::DirEntry::metadata`] for every entry.
+/// Entries can be turned directly into paths, so can be passed straight to
+/// functions taking `impl Into<PathBuf>`, such as `fs_async::read_stream`
+pub fn read_dir(path: impl AsRef<Path>) -> io::Result<ReadDir> {
+    std::fs::read_dir(path).map(ReadDir)
+}
+
+/// Iterator over the entries in a directory, created by [`read_dir`]
+#[derive(Debug)]
+pub struct ReadDir(std::fs::ReadDir);
+
+impl ReadDir {
+    /// Collects all the entries, sorted by file name
+    ///
+    /// Fails if any entry couldn't be read
+    pub fn sorted(self) -> io::Result<Vec<DirEntry>> {
+        let mut entries = self.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_unstable_by(|a, b| a.file_name.cmp(&b.file_name));
+        Ok(entries)
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|entry| {
+            let entry = entry?;
+            Ok(DirEntry {
+                metadata: entry.metadata()?,
+                path: entry.path(),
+                file_name: entry.file_name(),
+            })
+        })
+    }
+}
+
+/// An entry returned by the [`ReadDir`] iterator, including its metadata
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    path: PathBuf,
+    file_name: OsString,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    /// Returns the full path to the file that this entry represents
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Returns the file name of this entry, without any leading path
+    /// component(s)
+    pub fn file_name(&self) -> OsString {
+        self.file_name.clone()
+    }
+
+    /// Returns the metadata for the file that this entry points at
+    ///
+    /// Unlike the standard library, this never fails, as the metadata was
+    /// retrieved when the entry was read. It returns a `Result` to be a
+    /// drop-in replacement
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        Ok(self.metadata.clone())
+    }
+
+    /// Returns the file type for the file that this entry points at
+    pub fn file_type(&self) -> io::Result<FileType> {
+        Ok(self.metadata.file_type())
+    }
+}
+
+impl AsRef<Path> for DirEntry {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl From<DirEntry> for PathBuf {
+    fn from(entry: DirEntry) -> Self {
+        entry.path
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+    use crate::fs::{tests::in_runtime, write};
+
+    #[test]
+    fn sorted_with_metadata() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            for name in ["c", "a", "b"] {
+                write(dir.path().join(name), name.repeat(3)).unwrap();
+            }
+            std::fs::create_dir(dir.path().join("d")).unwrap();
+
+            let entries = read_dir(dir.path()).unwrap().sorted().unwrap();
+            let names = entries
+                .iter()
+                .map(|entry| entry.file_name())
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["a", "b", "c", "d"]);
+            assert_eq!(entries[0].metadata().unwrap().len(), 3);
+            assert!(entries[3].file_type().unwrap().is_dir());
+            assert_eq!(PathBuf::from(entries[0].clone()), dir.path().join("a"));
+        });
+    }
+}
@@ -0,0 +1,218 @@
+//! All-or-nothing writes to multiple files
+use std::{
+    fs::OpenOptions,
+    io,
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use super::{labeled, remove_file, rename, replace, temp_sibling};
+use crate::{error::report_cleanup, paths::Tracked};
+
+/// A set of file writes that are applied all together, or not at all
+///
+/// Writes are staged to temporary files next to their destinations as soon as
+/// they're added, with the handles being closed in the background. Nothing
+/// at the destinations changes until [`commit`](Transaction::commit) is
+/// called, which moves every staged file into place. If any of those moves
+/// fail, the ones already made are undone, restoring the original files
+///
+/// This is useful for programs writing sets of interdependent files (e.g. a
+/// UFO font), which should never be left half-updated
+///
+/// Dropping a `Transaction` without committing it discards the staged writes
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use close_already::fs::Transaction;
+///
+/// let mut transaction = Transaction::new();
+/// transaction.write("font.ufo/metainfo.plist", b"...")?;
+/// transaction.write("font.ufo/fontinfo.plist", b"...")?;
+/// transaction.commit()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Transaction {
+    staged: Vec<Staged>,
+}
+
+/// A write that's waiting to be committed
+#[derive(Debug)]
+struct Staged {
+    target: PathBuf,
+    temp: PathBuf,
+}
+
+/// A staged write that has been moved into place, which may need undoing
+#[derive(Debug)]
+struct Applied {
+    target: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+impl Transaction {
+    /// Creates an empty transaction
+    pub fn new() -> Self {
+        Transaction::default()
+    }
+
+    /// Stages `contents` to be written to `path` when the transaction is
+    /// committed
+    ///
+    /// Staging another write to the same path replaces the previous one
+    pub fn write(
+        &mut self,
+        path: impl AsRef<Path>,
+        contents: impl AsRef<[u8]>,
+    ) -> io::Result<()> {
+        let target = path.as_ref().to_path_buf();
+        let temp = temp_sibling(&target);
+        let written = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .and_then(|file| {
+                labeled(Tracked::new(file, &temp), &temp)
+                    .write_all(contents.as_ref())
+            });
+        if let Err(why) = written {
+            report_cleanup(&temp, std::fs::remove_file(&temp));
+            return Err(why);
+        }
+        if let Some(previous) = self
+            .staged
+            .iter_mut()
+            .find(|staged| staged.target == target)
+        {
+            let previous = mem::replace(&mut previous.temp, temp);
+            report_cleanup(&previous, remove_file(&previous));
+        } else {
+            self.staged.push(Staged { target, temp });
+        }
+        Ok(())
+    }
+
+    /// The number of files staged to be written
+    pub fn len(&self) -> usize {
+        self.staged.len()
+    }
+
+    /// Whether there are no files staged to be written
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// Moves all staged writes into place
+    ///
+    /// If this fails part-way through, any files already moved into place
+    /// are restored to their original contents (or removed, if they didn't
+    /// exist before), and the error that caused the failure is returned
+    pub fn commit(mut self) -> io::Result<()> {
+        let staged = mem::take(&mut self.staged);
+        let mut applied = Vec::with_capacity(staged.len());
+        let mut staged = staged.into_iter();
+        for Staged { target, temp } in staged.by_ref() {
+            let backup = target.exists().then(|| temp_sibling(&target));
+            if let Err(why) = replace(&target, &temp, backup.as_deref()) {
+                report_cleanup(&temp, remove_file(&temp));
+                staged.for_each(|Staged { temp, .. }| {
+                    report_cleanup(&temp, remove_file(&temp));
+                });
+                Transaction::roll_back(applied);
+                return Err(why);
+            }
+            applied.push(Applied { target, backup });
+        }
+        for Applied { backup, .. } in applied {
+            if let Some(backup) = backup {
+                report_cleanup(&backup, remove_file(&backup));
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes applied writes, in reverse order. This is best-effort, as
+    /// there's nothing to be done if restoring a file fails
+    fn roll_back(applied: Vec<Applied>) {
+        for Applied { target, backup } in applied.into_iter().rev() {
+            let restored = match backup {
+                Some(backup) => rename(backup, &target),
+                None => remove_file(&target),
+            };
+            report_cleanup(&target, restored);
+        }
+    }
+}
+
+impl Drop for Transaction {
+    /// Removes the temporary files of any uncommitted writes
+    fn drop(&mut self) {
+        for Staged { temp, .. } in self.staged.drain(..) {
+            report_cleanup(&temp, remove_file(&temp));
+        }
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+    use crate::fs::{read, tests::in_runtime, write};
+
+    #[test]
+    fn commit_writes_all() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let existing = dir.path().join("existing");
+            let new = dir.path().join("new");
+            write(&existing, b"old").unwrap();
+
+            let mut transaction = Transaction::new();
+            transaction.write(&existing, b"updated").unwrap();
+            transaction.write(&new, b"created").unwrap();
+            assert_eq!(read(&existing).unwrap(), b"old");
+            transaction.commit().expect("commit should succeed");
+
+            assert_eq!(read(&existing).unwrap(), b"updated");
+            assert_eq!(read(&new).unwrap(), b"created");
+            let entries = std::fs::read_dir(dir.path()).unwrap().count();
+            assert_eq!(entries, 2, "temporary files should not remain");
+        });
+    }
+
+    #[test]
+    fn failed_commit_rolls_back() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let existing = dir.path().join("existing");
+            write(&existing, b"old").unwrap();
+
+            let mut transaction = Transaction::new();
+            transaction.write(&existing, b"updated").unwrap();
+            transaction.write(dir.path().join("new"), b"never").unwrap();
+            // Sabotage the second write
+            std::fs::remove_file(&transaction.staged[1].temp).unwrap();
+            transaction.commit().expect_err("commit should fail");
+
+            assert_eq!(read(&existing).unwrap(), b"old");
+            let entries = std::fs::read_dir(dir.path()).unwrap().count();
+            assert_eq!(entries, 1, "temporary files should not remain");
+        });
+    }
+
+    #[test]
+    fn drop_discards_staged() {
+        in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut transaction = Transaction::new();
+            transaction
+                .write(dir.path().join("file"), b"discarded")
+                .unwrap();
+            drop(transaction);
+            let entries = std::fs::read_dir(dir.path()).unwrap().count();
+            assert_eq!(entries, 0, "temporary files should not remain");
+        });
+    }
+}
@@ -0,0 +1,451 @@
+//! Asynchronous filesystem operations for the async backends
+//!
+//! These run the blocking work on the backend's blocking thread pool, with
+//! file closures being offloaded as usual, so they never block the async
+//! runtime's worker threads
+use std::{
+    collections::VecDeque,
+    future,
+    future::Future,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+/// A boxed future, as returned by [`unblock`]
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The most in-flight slots allocated up front, so a huge limit doesn't
+/// allocate (or overflow) before anything is in flight
+const MAX_PREALLOCATED: usize = 64;
+
+/// Reads the entire contents of many files, yielding them as a [`Stream`]
+///
+/// Up to `prefetch` files are read concurrently in the background, ahead of
+/// the consumer. The contents of each file are yielded alongside its path,
+/// in the same order as `paths`. A `prefetch` of zero is treated as one
+///
+/// ```no_run
+/// # async fn example() {
+/// use std::{future, pin::Pin};
+///
+/// use close_already::fs_async::read_stream;
+/// use futures_core::Stream;
+///
+/// let mut stream = read_stream(["a.txt", "b.txt", "c.txt"], 2);
+/// while let Some((path, contents)) =
+///     future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+/// {
+///     println!("{} is {} bytes", path.display(), contents.unwrap().len());
+/// }
+/// # }
+/// ```
+pub fn read_stream<I>(paths: I, prefetch: usize) -> ReadStream<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Into<PathBuf>,
+{
+    ReadStream {
+        paths: paths.into_iter(),
+        prefetch: prefetch.max(1),
+        in_flight: VecDeque::with_capacity(prefetch.clamp(1, MAX_PREALLOCATED)),
+    }
+}
+
+/// A [`Stream`] of file contents, created by [`read_stream`]
+#[must_use = "streams do nothing unless polled"]
+pub struct ReadStream<I> {
+    paths: I,
+    prefetch: usize,
+    in_flight: VecDeque<BoxFuture<(PathBuf, io::Result<Vec<u8>>)>>,
+}
+
+impl<I> Stream for ReadStream<I>
+where
+    I: Iterator + Unpin,
+    I::Item: Into<PathBuf>,
+{
+    type Item = (PathBuf, io::Result<Vec<u8>>);
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while this.in_flight.len() < this.prefetch {
+            let Some(path) = this.paths.next() else { break };
+            let path = path.into();
+            this.in_flight.push_back(unblock(move || {
+                let contents = crate::fs::read(&path);
+                (path, contents)
+            }));
+        }
+        let Some(front) = this.in_flight.front_mut() else {
+            return Poll::Ready(None);
+        };
+        let item = std::task::ready!(front.as_mut().poll(cx));
+        this.in_flight.pop_front();
+        Poll::Ready(Some(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.paths.size_hint();
+        let in_flight = self.in_flight.len();
+        (
+            lower.saturating_add(in_flight),
+            upper.and_then(|upper| upper.checked_add(in_flight)),
+        )
+    }
+}
+
+/// Reads the entire contents of many files at once
+///
+/// Each file's contents are returned alongside its path, in the same order
+/// as `paths`. Files are read through [`read_stream`], up to `concurrency` at
+/// a time, except on Windows 11 and later, where they're instead read in
+/// batches of up to `concurrency` using the IoRing API, submitting each
+/// batch's reads with a single system call. A `concurrency` of zero is
+/// treated as one
+///
+/// ```no_run
+/// # async fn example() {
+/// use close_already::fs_async::read_many;
+///
+/// for (path, contents) in read_many(["a.txt", "b.txt", "c.txt"], 2).await {
+///     println!("{} is {} bytes", path.display(), contents.unwrap().len());
+/// }
+/// # }
+/// ```
+pub async fn read_many<I>(
+    paths: I,
+    concurrency: usize,
+) -> Vec<(PathBuf, io::Result<Vec<u8>>)>
+where
+    I: IntoIterator,
+    I::Item: Into<PathBuf>,
+{
+    let paths = paths.into_iter().map(Into::into).collect::<Vec<PathBuf>>();
+    #[cfg(windows)]
+    let paths =
+        match unblock(move || crate::fs::ioring::read_many(paths, concurrency))
+            .await
+        {
+            Ok(contents) => return contents,
+            // IoRing isn't supported on this version of Windows
+            Err(paths) => paths,
+        };
+    let mut contents = Vec::with_capacity(paths.len());
+    let mut stream = read_stream(paths, concurrency);
+    while let Some(item) =
+        future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+    {
+        contents.push(item);
+    }
+    contents
+}
+
+/// Atomically write a slice as the entire contents of a file
+///
+/// This is the asynchronous equivalent of
+/// [`fs::write_atomic`](crate::fs::write_atomic): the contents are written to
+/// a temporary file next to `path`, which is then moved into place, all on
+/// the backend's blocking thread pool
+pub async fn write_atomic(
+    path: impl Into<PathBuf>,
+    contents: impl AsRef<[u8]> + Send + 'static,
+) -> io::Result<()> {
+    let path = path.into();
+    unblock(move || crate::fs::write_atomic(path, contents)).await
+}
+
+/// Writes many files with bounded concurrency, as a [`Sink`]
+///
+/// Each item sent to the sink is a path and the contents to write to it
+/// (e.g. `(PathBuf, bytes::Bytes)`), which is written as
+/// [`fs::write`](crate::fs::write) would. Up to `concurrency` files are
+/// written at once in the background; once that many are in flight, the sink
+/// applies backpressure until one completes. A `concurrency` of zero is
+/// treated as one
+///
+/// The first write error encountered is returned from the next call to
+/// `poll_ready`, `poll_flush`, or `poll_close`
+pub fn write_sink(concurrency: usize) -> WriteSink {
+    WriteSink {
+        concurrency: concurrency.max(1),
+        in_flight: VecDeque::with_capacity(
+            concurrency.clamp(1, MAX_PREALLOCATED),
+        ),
+    }
+}
+
+/// A [`Sink`] that writes files, created by [`write_sink`]
+#[must_use = "sinks do nothing unless polled"]
+pub struct WriteSink {
+    concurrency: usize,
+    in_flight: VecDeque<BoxFuture<io::Result<()>>>,
+}
+
+impl WriteSink {
+    /// Polls every in-flight write, removing those that have completed.
+    /// Returns the first error encountered, if any
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let mut result = Ok(());
+        self.in_flight
+            .retain_mut(|write| match write.as_mut().poll(cx) {
+                Poll::Ready(written) => {
+                    if result.is_ok() {
+                        result = written;
+                    }
+                    false
+                },
+                Poll::Pending => true,
+            });
+        result
+    }
+}
+
+impl<P, B> Sink<(P, B)> for WriteSink
+where
+    P: Into<PathBuf>,
+    B: AsRef<[u8]> + Send + 'static,
+{
+    type Error = io::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.poll_in_flight(cx)?;
+        if this.in_flight.len() < this.concurrency {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        (path, contents): (P, B),
+    ) -> Result<(), Self::Error> {
+        let path = path.into();
+        self.get_mut()
+            .in_flight
+            .push_back(unblock(move || crate::fs::write(path, contents)));
+        Ok(())
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.poll_in_flight(cx)?;
+        if this.in_flight.is_empty() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        <Self as Sink<(P, B)>>::poll_flush(self, cx)
+    }
+}
+
+/// Runs a blocking function on `smol`'s blocking thread pool
+///
+/// This is also used by the deprecated `async-std` backend. If `tokio` is
+/// enabled too, its blocking thread pool is used instead
+#[cfg(all(
+    any(feature = "backend-async-std", feature = "backend-smol"),
+    not(feature = "backend-tokio"),
+))]
+fn unblock<T, F>(f: F) -> BoxFuture<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Box::pin(smol::unblock(f))
+}
+
+/// Runs a blocking function on `tokio`'s blocking thread pool
+///
+/// Panics if called outside of a `tokio` runtime
+#[cfg(feature = "backend-tokio")]
+fn unblock<T, F>(f: F) -> BoxFuture<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let handle = tokio::task::spawn_blocking(f);
+    Box::pin(async move {
+        match handle.await {
+            Ok(output) => output,
+            Err(why) => std::panic::resume_unwind(why.into_panic()),
+        }
+    })
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::pin::Pin;
+
+    use futures_core::Stream;
+    use futures_sink::Sink;
+
+    use super::*;
+    use crate::test_runtime::block_on;
+
+    #[test]
+    fn read_stream_preserves_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..10)
+            .map(|n| {
+                let path = dir.path().join(n.to_string());
+                std::fs::write(&path, n.to_string()).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        block_on(async {
+            let mut stream = read_stream(paths.clone(), 3);
+            let mut yielded = Vec::new();
+            while let Some((path, contents)) =
+                future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+            {
+                let contents = contents.expect("read should succeed");
+                assert_eq!(
+                    path.file_name().unwrap().as_encoded_bytes(),
+                    contents
+                );
+                yielded.push(path);
+            }
+            assert_eq!(yielded, paths);
+        });
+    }
+
+    #[test]
+    fn read_stream_allows_unbounded_prefetch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"contents").unwrap();
+
+        block_on(async {
+            let mut stream = read_stream([path], usize::MAX);
+            let (_, contents) =
+                future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+                    .await
+                    .expect("stream should yield the file");
+            assert_eq!(contents.unwrap(), b"contents");
+        });
+    }
+
+    #[test]
+    fn read_many_preserves_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..10)
+            .map(|n| {
+                let path = dir.path().join(n.to_string());
+                std::fs::write(&path, n.to_string().repeat(n)).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        let missing = dir.path().join("missing");
+
+        let contents =
+            block_on(read_many(paths.iter().chain([&missing]).cloned(), 3));
+
+        assert_eq!(contents.len(), paths.len() + 1);
+        for ((path, contents), n) in contents.iter().zip(0..) {
+            if n == paths.len() {
+                assert_eq!(path, &missing);
+                let why = contents.as_ref().expect_err("file doesn't exist");
+                assert_eq!(why.kind(), io::ErrorKind::NotFound);
+            } else {
+                assert_eq!(path, &paths[n]);
+                assert_eq!(
+                    contents.as_ref().unwrap(),
+                    &n.to_string().repeat(n).into_bytes()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write_sink_writes_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..10)
+            .map(|n| dir.path().join(n.to_string()))
+            .collect::<Vec<_>>();
+
+        block_on(async {
+            let mut sink = write_sink(3);
+            for path in &paths {
+                future::poll_fn(|cx| {
+                    Sink::<(PathBuf, Vec<u8>)>::poll_ready(
+                        Pin::new(&mut sink),
+                        cx,
+                    )
+                })
+                .await
+                .expect("sink should be ready");
+                let contents = path.file_name().unwrap().as_encoded_bytes();
+                Pin::new(&mut sink)
+                    .start_send((path.clone(), contents.to_vec()))
+                    .unwrap();
+            }
+            future::poll_fn(|cx| {
+                Sink::<(PathBuf, Vec<u8>)>::poll_close(Pin::new(&mut sink), cx)
+            })
+            .await
+            .expect("writes should succeed");
+        });
+
+        for path in paths {
+            let contents = std::fs::read(&path).unwrap();
+            assert_eq!(path.file_name().unwrap().as_encoded_bytes(), contents);
+        }
+    }
+
+    #[test]
+    fn write_sink_allows_unbounded_concurrency() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+
+        block_on(async {
+            let mut sink = write_sink(usize::MAX);
+            Pin::new(&mut sink)
+                .start_send((path.clone(), b"contents".to_vec()))
+                .unwrap();
+            future::poll_fn(|cx| {
+                Sink::<(PathBuf, Vec<u8>)>::poll_close(Pin::new(&mut sink), cx)
+            })
+            .await
+            .expect("write should succeed");
+        });
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"contents");
+    }
+
+    #[test]
+    fn write_atomic_replaces_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"old").unwrap();
+
+        block_on(write_atomic(path.clone(), b"new"))
+            .expect("write should succeed");
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        let entries = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(entries, 1, "temporary files should not remain");
+    }
+}
@@ -0,0 +1,231 @@
+//! Closing many handles together in a few background jobs
+use std::{
+    fmt, io, mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::FastCloseable;
+
+/// The most handles closed by one background job, so that large groups are
+/// still spread across the backend's threads
+const CHUNK_SIZE: usize = 128;
+
+/// A collection of handles that are closed together, in as few background
+/// jobs as possible
+///
+/// Push handles into a group while processing, instead of wrapping each one
+/// in [`FastClose`](crate::FastClose). When the group is dropped, or
+/// [`drain`](FastCloseGroup::drain)ed, its handles are submitted to the
+/// backend in chunks, rather than one job per handle.
+/// [`wait`](FastCloseGroup::wait) can be used to block until they've all been
+/// closed
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::{fs::File, io::Write};
+///
+/// use close_already::FastCloseGroup;
+///
+/// let mut group = FastCloseGroup::new();
+/// for n in 0..1000 {
+///     let mut file = File::create(format!("{n}.txt"))?;
+///     file.write_all(b"Hello, world!")?;
+///     group.push(file);
+/// }
+/// // All 1000 files are closed in the background in a few jobs
+/// group.wait();
+/// # Ok(())
+/// # }
+/// ```
+pub struct FastCloseGroup<H: FastCloseable + 'static> {
+    handles: Vec<H>,
+    outstanding: Arc<Outstanding>,
+}
+
+/// The number of handles (or chunks of handles) submitted to the backend
+/// that haven't been closed yet, which can be waited on
+#[derive(Default)]
+pub(crate) struct Outstanding {
+    handles: Mutex<usize>,
+    closed: Condvar,
+}
+
+impl Outstanding {
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.handles.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Counts a handle as outstanding until the returned wrapper is dropped
+    pub(crate) fn track<H>(self: &Arc<Self>, handle: H) -> Counted<H> {
+        *self.lock() += 1;
+        Counted {
+            handle: Some(handle),
+            outstanding: Arc::clone(self),
+        }
+    }
+
+    /// The number of handles that haven't been closed yet
+    pub(crate) fn count(&self) -> usize {
+        *self.lock()
+    }
+
+    /// Blocks until every handle tracked has been closed
+    pub(crate) fn wait(&self) {
+        let mut handles = self.lock();
+        while *handles > 0 {
+            handles = self
+                .closed
+                .wait(handles)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// A handle that's counted as outstanding until it's been closed
+pub(crate) struct Counted<H> {
+    handle: Option<H>,
+    outstanding: Arc<Outstanding>,
+}
+
+impl<H: FastCloseable> FastCloseable for Counted<H> {
+    fn before_close(&mut self) -> io::Result<()> {
+        self.handle.as_mut().map_or(Ok(()), H::before_close)
+    }
+
+    /// Closes the handle, then marks it as closed
+    fn close(mut self) -> io::Result<()> {
+        self.handle.take().map_or(Ok(()), H::close)
+    }
+}
+
+impl<H> Drop for Counted<H> {
+    /// Closes the handle, then marks it as closed
+    fn drop(&mut self) {
+        drop(self.handle.take());
+        let mut handles = self.outstanding.lock();
+        *handles -= 1;
+        if *handles == 0 {
+            self.outstanding.closed.notify_all();
+        }
+    }
+}
+
+impl<H: FastCloseable + 'static> FastCloseGroup<H> {
+    /// Creates an empty group
+    pub fn new() -> Self {
+        FastCloseGroup {
+            handles: Vec::new(),
+            outstanding: Arc::default(),
+        }
+    }
+
+    /// Adds a handle to the group, to be closed when the group is drained or
+    /// dropped
+    pub fn push(&mut self, handle: H) {
+        self.handles.push(handle);
+    }
+
+    /// The number of handles in the group that haven't been submitted to be
+    /// closed yet
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Whether there are no handles in the group waiting to be submitted
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Submits all the group's handles to the backend to be closed, leaving
+    /// the group empty, and ready to be reused
+    pub fn drain(&mut self) {
+        let mut handles = mem::take(&mut self.handles);
+        while !handles.is_empty() {
+            let rest = handles.split_off(handles.len().min(CHUNK_SIZE));
+            let chunk = mem::replace(&mut handles, rest);
+            // Each chunk is submitted to the backend as one job
+            drop(self.outstanding.track(chunk).fast_close());
+        }
+    }
+
+    /// Submits all the group's handles to the backend to be closed, then
+    /// blocks until every handle the group has submitted has been closed
+    ///
+    /// When using an async backend, don't call this from a task on the same
+    /// runtime, as it may prevent the closures from running
+    pub fn wait(&mut self) {
+        self.drain();
+        self.outstanding.wait();
+    }
+}
+
+impl<H: FastCloseable + 'static> Default for FastCloseGroup<H> {
+    fn default() -> Self {
+        FastCloseGroup::new()
+    }
+}
+
+impl<H: FastCloseable + 'static> Extend<H> for FastCloseGroup<H> {
+    fn extend<I: IntoIterator<Item = H>>(&mut self, iter: I) {
+        self.handles.extend(iter);
+    }
+}
+
+impl<H: FastCloseable + 'static> fmt::Debug for FastCloseGroup<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FastCloseGroup")
+            .field("len", &self.handles.len())
+            .field("outstanding_chunks", &self.outstanding.count())
+            .finish()
+    }
+}
+
+impl<H: FastCloseable + 'static> Drop for FastCloseGroup<H> {
+    /// Submits any handles left in the group to the backend to be closed
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn drain_empties_group() {
+        in_runtime(|| {
+            let mut group = FastCloseGroup::new();
+            group.extend((0..300).map(|_| File::open("Cargo.toml").unwrap()));
+            assert_eq!(group.len(), 300);
+            group.drain();
+            assert!(group.is_empty());
+        });
+    }
+
+    // Tokio's tasks don't run while the test blocks its only thread
+    #[test]
+    #[cfg(not(feature = "backend-tokio"))]
+    fn wait_for_all_closed() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Handle;
+
+        impl FastCloseable for Handle {}
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                CLOSED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut group = FastCloseGroup::new();
+        group.extend((0..300).map(|_| Handle));
+        group.wait();
+        assert_eq!(CLOSED.load(Ordering::SeqCst), 300);
+    }
+}
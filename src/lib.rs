@@ -9,45 +9,175 @@ use std::{
     io,
     io::{IoSlice, IoSliceMut, SeekFrom},
     ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::mpsc,
 };
 
-mutually_exclusive_features::exactly_one_of! {
-    "backend-async-std",
-    "backend-blocking",
-    "backend-rayon",
-    "backend-smol",
-    "backend-threadpool",
-    "backend-tokio",
-}
-
+#[cfg(feature = "backend-tokio")]
+pub use all_closed::all_closed;
+#[cfg(any(
+    feature = "backend-async-std",
+    feature = "backend-smol",
+    feature = "backend-tokio",
+))]
+pub use async_ext::FastCloseAsyncExt;
+#[cfg(feature = "backend-threadpool")]
+pub use backend::configure;
+#[cfg(feature = "backend-tokio")]
+pub use backend::set_tokio_runtime;
+pub use backend::{set_backend, warm_up, Backend, DefaultCloser, Priority};
+pub use batch::{set_batching, Batching};
+/// Implements [`FastCloseable`] for a file wrapper type, checking that
+/// it's `Send`, `'static`, and not `Clone`
+///
+/// Requires the `derive` feature
+///
+/// ```
+/// use std::fs::File;
+///
+/// use close_already::{FastClose, FastCloseable};
+///
+/// #[derive(FastCloseable)]
+/// struct Log {
+///     file: File,
+///     lines: usize,
+/// }
+///
+/// let file = File::open("Cargo.toml")?;
+/// let log = FastClose::new(Log { file, lines: 0 });
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Types that are `Clone` are rejected
+///
+/// ```compile_fail
+/// #[derive(Clone, close_already::FastCloseable)]
+/// struct Shared(std::sync::Arc<std::fs::File>);
+/// ```
+#[cfg(feature = "derive")]
+pub use close_already_derive::FastCloseable;
+pub use closer::{set_global_closer, Closer};
+pub use deadline::set_close_deadline;
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::Origin;
+pub use error::{
+    clear_error_sink, clear_panic_handler, set_error_sink, set_panic_handler,
+    CloseError, ClosePanic,
+};
+pub use group::FastCloseGroup;
+pub use local::{poll_local, FastCloseLocal};
+#[doc(hidden)]
+pub use pending::pending_report as __pending_report;
+pub use pending::{
+    drain, pending, set_queue_capacity, shutdown_timeout, ExitGuard,
+    SubmitPolicy,
+};
+#[cfg(feature = "backend-threadpool")]
+pub use pool::PoolConfig;
+pub use scope::{scope, Scope};
+pub use settings::FastCloseWith;
+pub use shared::FastCloseArc;
+pub use stats::{stats, Stats, StatsSnapshot};
 #[cfg(not(windows))]
 pub use stub::FastClose;
 #[cfg(windows)]
 pub use windows::FastClose;
 
+#[cfg(feature = "backend-tokio")]
+mod all_closed;
+#[cfg(any(
+    feature = "backend-async-std",
+    feature = "backend-smol",
+    feature = "backend-tokio",
+))]
+mod async_ext;
+#[cfg(feature = "backend-async-std")]
+pub mod async_std;
+#[cfg(feature = "audit")]
+pub mod audit;
+mod backend;
+mod batch;
+pub mod bench_support;
+#[cfg(feature = "backend-blocking")]
+pub mod blocking;
+mod closer;
+#[cfg(any(unix, windows))]
+pub mod compat;
+mod deadline;
+mod diagnostics;
+pub mod env;
+mod error;
+#[cfg(all(windows, feature = "etw"))]
+mod etw;
+#[cfg(feature = "events")]
+pub mod events;
 pub mod fs;
+#[cfg(any(
+    feature = "backend-async-std",
+    feature = "backend-smol",
+    feature = "backend-tokio",
+))]
+pub mod fs_async;
+mod group;
+mod local;
+#[cfg(feature = "log")]
+pub mod log;
+#[cfg(feature = "metrics")]
+mod metrics;
+pub mod paths;
+mod pending;
+#[cfg(feature = "backend-threadpool")]
+mod pool;
+pub mod prelude;
+#[cfg(feature = "backend-rayon")]
+pub mod rayon;
+mod scope;
+mod settings;
+mod shared;
+#[cfg(feature = "backend-smol")]
+pub mod smol;
+#[cfg(feature = "backend-spawn")]
+pub mod spawn;
+pub mod stats;
+pub mod testing;
+#[cfg(feature = "backend-tokio")]
+pub mod tokio_io;
+#[cfg(feature = "tracing")]
+mod tracing;
+pub mod watchdog;
 
 /// The Windows implementation of [`FastClose`]
 #[cfg(windows)]
 mod windows {
-    #[cfg(feature = "backend-threadpool")]
-    use std::sync::OnceLock;
     use std::{
-        fmt, io, mem::ManuallyDrop, ops::Deref, os::windows::prelude::*,
+        borrow::Cow,
+        fmt, io,
+        mem::{self, ManuallyDrop},
+        ops::Deref,
+        os::windows::prelude::*,
     };
 
-    #[cfg(feature = "backend-threadpool")]
-    use threadpool::{Builder as ThreadPoolBuilder, ThreadPool};
-
-    /// A lazily initialised [`ThreadPool`] to send handle closures to
-    #[cfg(feature = "backend-threadpool")]
-    static CLOSER_POOL: OnceLock<ThreadPool> = OnceLock::new();
+    use crate::{
+        diagnostics::{Notes, Origin},
+        settings::Settings,
+        FastCloseable,
+    };
 
     /// A zero-sized wrapper that moves a file handle to a thread pool on drop
-    #[repr(transparent)]
-    pub struct FastClose<H: Send + 'static>(pub(super) ManuallyDrop<H>);
-
-    impl<H: Send + 'static> FastClose<H> {
+    ///
+    /// With the `audit` or `diagnostics` features enabled, this also holds
+    /// what they record about the handle, so is no longer zero-sized
+    #[cfg_attr(
+        not(any(feature = "audit", feature = "diagnostics")),
+        repr(transparent)
+    )]
+    pub struct FastClose<H: FastCloseable + 'static>(
+        pub(super) ManuallyDrop<H>,
+        Notes,
+        #[cfg(feature = "audit")] pub(super) crate::audit::Ticket,
+    );
+
+    impl<H: FastCloseable + 'static> FastClose<H> {
         /// Gets back the inner file type
         ///
         /// This means that `close_already` will no longer send the handle to a
@@ -56,108 +186,113 @@ mod windows {
             // Prevent destructor being called first, in case we get interrupted
             // somehow before the end of the method
             let mut wrapped = ManuallyDrop::new(self);
+            // Anything noted would otherwise be leaked with the wrapper
+            wrapped.1 = Notes::default();
+            #[cfg(feature = "audit")]
+            wrapped.2.release();
             // SAFETY: we are never going to access self.0 again because this
             // method takes ownership of self and we've already prevented its
             // destructor from being called
             unsafe { ManuallyDrop::take(&mut wrapped.0) }
         }
 
+        /// Records the handle's label in the `audit` table and with the
+        /// `diagnostics` feature, where enabled
+        pub(crate) fn note_label(&mut self, label: Cow<'static, str>) {
+            #[cfg(feature = "audit")]
+            self.2.label(label.clone());
+            self.1.set_label(label);
+        }
+
+        /// Wraps the inner handle in another type, keeping what's been noted
+        /// about it
+        pub(super) fn map<G: FastCloseable + 'static>(
+            self,
+            f: impl FnOnce(H) -> G,
+        ) -> FastClose<G> {
+            let mut wrapped = ManuallyDrop::new(self);
+            let notes = mem::take(&mut wrapped.1);
+            // SAFETY: the destructor has been prevented from running, so the
+            // ticket is only moved out of self once, here
+            #[cfg(feature = "audit")]
+            let ticket = unsafe { std::ptr::read(&wrapped.2) };
+            // SAFETY: as above, self.0 won't be accessed again
+            let handle = unsafe { ManuallyDrop::take(&mut wrapped.0) };
+            FastClose(
+                ManuallyDrop::new(f(handle)),
+                notes,
+                #[cfg(feature = "audit")]
+                ticket,
+            )
+        }
+
         // Private definition for FastCloseable to use
         /// Creates a new fast-closing file handle
         #[inline]
+        #[track_caller]
         pub(super) fn _new(handle: H) -> FastClose<H> {
-            FastClose(ManuallyDrop::new(handle))
+            let origin = Origin::capture();
+            #[cfg(feature = "audit")]
+            let ticket = crate::audit::Ticket::register::<H>(origin.clone());
+            FastClose(
+                ManuallyDrop::new(handle),
+                Notes::new(origin),
+                #[cfg(feature = "audit")]
+                ticket,
+            )
+        }
+
+        /// Submits the handle to be closed as set by `settings`, instead of
+        /// the defaults used on drop
+        pub(crate) fn submit_with(self, settings: Settings) {
+            let mut wrapped = ManuallyDrop::new(self);
+            // SAFETY: the destructor has been prevented from running, so
+            // this is the only time the handle is taken
+            unsafe { wrapped.submit(settings) };
         }
 
-        /// Gets the interal [`OwnedHandle`]
+        /// Submits the handle to be closed as set by `settings`
         ///
         /// # Safety
         ///
         /// `self.0` must never be accessed again.
         /// This method should only be called on drop
-        #[inline]
-        unsafe fn get_handle(&mut self) -> H {
+        unsafe fn submit(&mut self, settings: Settings) {
             // SAFETY: relies on self.0 never being accessed again
-            unsafe { ManuallyDrop::take(&mut self.0) }
+            let handle = unsafe { ManuallyDrop::take(&mut self.0) };
+            #[cfg(feature = "audit")]
+            self.2.release();
+            let (label, origin) = self.1.take();
+            let label = settings.label.or(label);
+            if settings.eager
+                || crate::deadline::exceeded(crate::pending::count())
+                || !crate::pending::admit()
+            {
+                crate::error::close(handle, label.as_ref());
+            } else {
+                let started = crate::bench_support::begin();
+                crate::backend::close_on(
+                    settings.closer.as_deref(),
+                    settings.priority,
+                    label,
+                    origin,
+                    handle,
+                );
+                crate::bench_support::submitted(started);
+            }
         }
     }
 
-    impl<H: Send + 'static> Drop for FastClose<H> {
-        /// Submits the file handle to a thread pool to handle its closure
-        ///
-        /// Note: on non-Windows targets, nothing is done, the handle is just
-        /// dropped normally
-        #[cfg(feature = "backend-threadpool")]
-        fn drop(&mut self) {
-            let closer_pool =
-                CLOSER_POOL.get_or_init(|| ThreadPoolBuilder::new().build());
-            // SAFETY: we're in Drop, so self.0 won't be accessed again
-            let handle = unsafe { self.get_handle() };
-            closer_pool.execute(move || drop(handle));
-        }
-
-        /// Submits the file handle as a `blocking` task to handle its
+    impl<H: FastCloseable + 'static> Drop for FastClose<H> {
+        /// Submits the file handle to your chosen backend to handle its
         /// closure
-        ///
-        /// Note: on non-Windows targets, nothing is done, the handle is just
-        /// dropped normally
-        #[cfg(feature = "backend-blocking")]
         fn drop(&mut self) {
             // SAFETY: we're in Drop, so self.0 won't be accessed again
-            let handle = unsafe { self.get_handle() };
-            blocking::unblock(move || drop(handle)).detach();
-        }
-
-        /// Submits the file handle to `rayon`'s thread pool to handle its
-        /// closure
-        ///
-        /// Note: on non-Windows targets, nothing is done, the handle is just
-        /// dropped normally
-        #[cfg(feature = "backend-rayon")]
-        fn drop(&mut self) {
-            // SAFETY: we're in Drop, so self.0 won't be accessed again
-            let handle = unsafe { self.get_handle() };
-            rayon::spawn(move || drop(handle));
-        }
-
-        /// Submits the file handle as a `async-std` task to handle its
-        /// closure
-        ///
-        /// Note: on non-Windows targets, nothing is done, the handle is just
-        /// dropped normally
-        #[cfg(feature = "backend-async-std")]
-        fn drop(&mut self) {
-            // SAFETY: we're in Drop, so self.0 won't be accessed again
-            let handle = unsafe { self.get_handle() };
-            async_std::task::spawn(async move { drop(handle) });
-        }
-
-        /// Submits the file handle as a `smol` task to handle its
-        /// closure
-        ///
-        /// Note: on non-Windows targets, nothing is done, the handle is just
-        /// dropped normally
-        #[cfg(feature = "backend-smol")]
-        fn drop(&mut self) {
-            // SAFETY: we're in Drop, so self.0 won't be accessed again
-            let handle = unsafe { self.get_handle() };
-            smol::spawn(async move { drop(handle) }).detach();
-        }
-
-        /// Submits the file handle as a `tokio` task to handle its
-        /// closure
-        ///
-        /// Note: on non-Windows targets, nothing is done, the handle is just
-        /// dropped normally
-        #[cfg(feature = "backend-tokio")]
-        fn drop(&mut self) {
-            // SAFETY: we're in Drop, so self.0 won't be accessed again
-            let handle = unsafe { self.get_handle() };
-            tokio::task::spawn(async move { drop(handle) });
+            unsafe { self.submit(Settings::default()) };
         }
     }
 
-    impl<H: Send + 'static> fmt::Debug for FastClose<H>
+    impl<H: FastCloseable + 'static> fmt::Debug for FastClose<H>
     where
         H: fmt::Debug,
     {
@@ -167,7 +302,7 @@ mod windows {
     }
 
     // Windows-only blanket impls
-    impl<H: Send + 'static> AsHandle for FastClose<H>
+    impl<H: FastCloseable + 'static> AsHandle for FastClose<H>
     where
         H: AsHandle,
     {
@@ -176,7 +311,27 @@ mod windows {
         }
     }
 
-    impl<H: Send + 'static> FileExt for FastClose<H>
+    impl<H: FastCloseable + 'static> AsRawHandle for FastClose<H>
+    where
+        H: AsRawHandle,
+    {
+        fn as_raw_handle(&self) -> RawHandle {
+            self.0.as_raw_handle()
+        }
+    }
+
+    impl<H: FastCloseable + 'static> IntoRawHandle for FastClose<H>
+    where
+        H: IntoRawHandle,
+    {
+        /// Consumes the wrapper without submitting the handle to be closed,
+        /// as the caller becomes responsible for closing it
+        fn into_raw_handle(self) -> RawHandle {
+            self.into_inner().into_raw_handle()
+        }
+    }
+
+    impl<H: FastCloseable + 'static> FileExt for FastClose<H>
     where
         H: FileExt,
     {
@@ -193,14 +348,22 @@ mod windows {
 /// The non-Windows stub implementation of [`FastClose`]
 #[cfg(not(windows))]
 mod stub {
-    use std::{mem::ManuallyDrop, ptr};
+    use std::{borrow::Cow, mem::ManuallyDrop, ptr};
+
+    use crate::{settings::Settings, FastCloseable};
 
     /// A zero-sized wrapper that moves a file handle to a thread pool on drop
-    #[repr(transparent)]
+    ///
+    /// With the `audit` feature enabled, this also holds the handle's entry
+    /// in the audit table, so is no longer zero-sized
+    #[cfg_attr(not(feature = "audit"), repr(transparent))]
     #[derive(Debug)]
-    pub struct FastClose<H: Send + 'static>(pub(super) H);
+    pub struct FastClose<H: FastCloseable + 'static>(
+        pub(super) H,
+        #[cfg(feature = "audit")] pub(super) crate::audit::Ticket,
+    );
 
-    impl<H: Send + 'static> FastClose<H> {
+    impl<H: FastCloseable + 'static> FastClose<H> {
         // https://discord.com/channels/442252698964721669/443150878111694848/1180556717243764829
         /// Gets back the inner file type
         ///
@@ -213,26 +376,92 @@ mod stub {
             // Prevent destructor being called first, in case we get interrupted
             // somehow before the end of the method
             let wrapped = ManuallyDrop::new(self);
+            #[cfg(feature = "audit")]
+            wrapped.1.release();
             let h_ptr: *const H = &wrapped.0;
             // SAFETY: we know h_ptr points to H still because the it was
             // wrapped in ManuallyDrop, preventing its destructor being run
             unsafe { ptr::read(h_ptr) }
         }
 
+        /// Records the handle's label in the `audit` table, where enabled
+        pub(crate) fn note_label(&mut self, label: Cow<'static, str>) {
+            #[cfg(feature = "audit")]
+            self.1.label(label);
+            #[cfg(not(feature = "audit"))]
+            let _ = label;
+        }
+
+        /// Closes the handle inline, as `settings` only apply on Windows
+        pub(crate) fn submit_with(self, settings: Settings) {
+            let _ = settings;
+            drop(self);
+        }
+
         // Private definition for FastCloseable to use
         /// Creates a new fast-closing file handle
         #[inline]
+        #[track_caller]
         pub(super) fn _new(handle: H) -> FastClose<H> {
-            FastClose(handle)
+            FastClose(
+                handle,
+                #[cfg(feature = "audit")]
+                crate::audit::Ticket::register::<H>(
+                    crate::diagnostics::Origin::capture(),
+                ),
+            )
+        }
+
+        /// Wraps the inner handle in another type
+        pub(super) fn map<G: FastCloseable + 'static>(
+            self,
+            f: impl FnOnce(H) -> G,
+        ) -> FastClose<G> {
+            let wrapped = ManuallyDrop::new(self);
+            // SAFETY: the destructor has been prevented from running, so the
+            // ticket is only moved out of self once, here
+            #[cfg(feature = "audit")]
+            let ticket = unsafe { ptr::read(&wrapped.1) };
+            // SAFETY: as above, for the handle
+            let handle = unsafe { ptr::read(&wrapped.0) };
+            FastClose(
+                f(handle),
+                #[cfg(feature = "audit")]
+                ticket,
+            )
         }
     }
 
-    impl<H: Send + 'static> Drop for FastClose<H> {
+    impl<H: FastCloseable + 'static> Drop for FastClose<H> {
         /// Submits the file handle to your chosen backend to handle its closure
         ///
         /// Note: on non-Windows targets, nothing is done, the handle is just
         /// dropped normally
-        fn drop(&mut self) {}
+        fn drop(&mut self) {
+            #[cfg(feature = "audit")]
+            self.1.release();
+        }
+    }
+
+    // Unix-only blanket impls, mirroring the Windows handle ones
+    #[cfg(unix)]
+    impl<H: FastCloseable + 'static> std::os::fd::AsFd for FastClose<H>
+    where
+        H: std::os::fd::AsFd,
+    {
+        fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+            self.0.as_fd()
+        }
+    }
+
+    #[cfg(unix)]
+    impl<H: FastCloseable + 'static> std::os::fd::AsRawFd for FastClose<H>
+    where
+        H: std::os::fd::AsRawFd,
+    {
+        fn as_raw_fd(&self) -> std::os::fd::RawFd {
+            self.0.as_raw_fd()
+        }
     }
 }
 
@@ -240,29 +469,126 @@ mod stub {
 impl<H: FastCloseable> FastClose<H> {
     /// Creates a new fast-closing file handle
     #[inline(always)]
+    #[track_caller]
     pub fn new(handle: H) -> Self {
         handle.fast_close()
     }
+
+    /// Closes the handle inline, returning any error encountered, instead of
+    /// sending it to the backend
+    ///
+    /// Dropping a `FastClose` (or the handle itself) ignores any error from
+    /// closing it, which is fine for most uses. Use this for the handles
+    /// where you need to know it closed cleanly before continuing. Flush any
+    /// buffered writers first (see
+    /// [`into_inner_flushed`](FastClose::into_inner_flushed)), unless their
+    /// [`before_close`](FastCloseable::before_close) hook does so, as only
+    /// the closure itself is checked otherwise
+    ///
+    /// Which errors can be returned depends on the handle's
+    /// [`FastCloseable::close`] implementation. For `File`, this is the
+    /// error from `CloseHandle` on Windows
+    pub fn close(self) -> io::Result<()> {
+        close_handle(self.into_inner())
+    }
+
+    /// Submits the handle to the backend to be closed, like dropping it
+    /// would, returning a channel that receives the result of closing it
+    ///
+    /// This is like [`close`](FastClose::close), without blocking until the
+    /// handle has been closed. The result can be checked later with
+    /// [`try_recv`](mpsc::Receiver::try_recv), or waited for with
+    /// [`recv`](mpsc::Receiver::recv). It's fine to drop the receiver if
+    /// you're no longer interested
+    ///
+    /// When using an async backend, don't block on the result from a task on
+    /// the same runtime, as it may prevent the closure from running
+    pub fn close_with_result(self) -> mpsc::Receiver<io::Result<()>> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        drop(self.map(|handle| CloseReporter {
+            handle: Some(handle),
+            sender,
+        }));
+        receiver
+    }
 }
 
 // Method impls for stub or non-stub
-impl<H: Send + 'static> FastClose<H> {
+impl<H: FastCloseable + 'static> FastClose<H> {
+    /// Flushes the inner writer, then gets it back, like
+    /// [`into_inner`](FastClose::into_inner)
+    ///
+    /// Use this instead of `into_inner` for buffered or encoding writers, so
+    /// that errors writing out their buffers aren't silently lost. If flushing
+    /// fails, the error is returned and the handle is closed as it would've
+    /// been on drop
+    pub fn into_inner_flushed(mut self) -> io::Result<H>
+    where
+        H: io::Write,
+    {
+        self.flush()?;
+        Ok(self.into_inner())
+    }
+
+    /// Pin projects from `self` to the inner file handle, for implementing
+    /// poll-based traits (such as `futures::AsyncBufRead`) on top of
+    /// `FastClose`
+    ///
+    /// `H` must be [`Unpin`], as the handle is moved to the backend when it's
+    /// dropped, so can't stay pinned in place. All the async runtimes' file
+    /// types are `Unpin`
+    ///
+    /// ```
+    /// use std::{
+    ///     io,
+    ///     pin::Pin,
+    ///     task::{Context, Poll},
+    /// };
+    ///
+    /// use close_already::{FastClose, FastCloseable};
+    ///
+    /// trait PollLen {
+    ///     fn poll_len(
+    ///         self: Pin<&mut Self>,
+    ///         cx: &mut Context<'_>,
+    ///     ) -> Poll<io::Result<u64>>;
+    /// }
+    ///
+    /// impl<H> PollLen for FastClose<H>
+    /// where
+    ///     H: PollLen + FastCloseable + Unpin + 'static,
+    /// {
+    ///     fn poll_len(
+    ///         self: Pin<&mut Self>,
+    ///         cx: &mut Context<'_>,
+    ///     ) -> Poll<io::Result<u64>> {
+    ///         self.pin_inner().poll_len(cx)
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn pin_inner(self: Pin<&mut Self>) -> Pin<&mut H>
+    where
+        H: Unpin,
+    {
+        Pin::new(self.get_mut().deref_mut())
+    }
+
     /// Pin projects from `self` to the inner file handle
     #[cfg(any(
+        feature = "backend-async-global-executor",
         feature = "backend-async-std",
         feature = "backend-smol",
         feature = "backend-tokio",
     ))]
     #[inline]
-    fn pin_project_to_inner(
-        self: std::pin::Pin<&mut Self>,
-    ) -> std::pin::Pin<&mut H> {
+    fn pin_project_to_inner(self: Pin<&mut Self>) -> Pin<&mut H> {
         // SAFETY: `self.0` is pinned when `self` is pinned
         unsafe { self.map_unchecked_mut(|fc| fc.deref_mut()) }
     }
 }
 
-impl<H: Send + 'static> Deref for FastClose<H> {
+impl<H: FastCloseable + 'static> Deref for FastClose<H> {
     type Target = H;
 
     fn deref(&self) -> &Self::Target {
@@ -270,7 +596,7 @@ impl<H: Send + 'static> Deref for FastClose<H> {
     }
 }
 
-impl<H: Send + 'static> DerefMut for FastClose<H> {
+impl<H: FastCloseable + 'static> DerefMut for FastClose<H> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
@@ -287,7 +613,7 @@ where
 
 impl<H> io::Read for FastClose<H>
 where
-    H: io::Read + Send + 'static,
+    H: io::Read + FastCloseable + 'static,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.0.read(buf)
@@ -320,9 +646,30 @@ where
     }
 }
 
+impl<H> io::BufRead for FastClose<H>
+where
+    H: io::BufRead + FastCloseable + 'static,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.0.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt)
+    }
+
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.0.read_until(byte, buf)
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.0.read_line(buf)
+    }
+}
+
 impl<H> io::Write for FastClose<H>
 where
-    H: io::Write + Send + 'static,
+    H: io::Write + FastCloseable + 'static,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.0.write(buf)
@@ -354,7 +701,7 @@ where
 
 impl<H> io::Seek for FastClose<H>
 where
-    H: io::Seek + Send + 'static,
+    H: io::Seek + FastCloseable + 'static,
 {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         self.0.seek(pos)
@@ -369,6 +716,74 @@ where
     }
 }
 
+// Shared references, for handle types that can be read from or written to
+// through a shared reference (like `&File`)
+impl<'a, H> io::Read for &'a FastClose<H>
+where
+    H: FastCloseable + 'static,
+    &'a H: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut handle: &'a H = self;
+        handle.read(buf)
+    }
+
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        let mut handle: &'a H = self;
+        handle.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let mut handle: &'a H = self;
+        handle.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut handle: &'a H = self;
+        handle.read_to_string(buf)
+    }
+}
+
+impl<'a, H> io::Write for &'a FastClose<H>
+where
+    H: FastCloseable + 'static,
+    &'a H: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut handle: &'a H = self;
+        handle.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut handle: &'a H = self;
+        handle.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut handle: &'a H = self;
+        handle.flush()
+    }
+}
+
+impl<'a, H> io::Seek for &'a FastClose<H>
+where
+    H: FastCloseable + 'static,
+    &'a H: io::Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut handle: &'a H = self;
+        handle.seek(pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        let mut handle: &'a H = self;
+        handle.stream_position()
+    }
+}
+
 /// Indicates compatibility with [`FastClose`], providing a convenience method
 /// for wrapping a type
 ///
@@ -380,8 +795,8 @@ where
 /// Phrased another way, you can implement `FastCloseable` on any file wrapper
 /// type that is:
 /// - Owned, not borrowed (`'static`)
-/// - Not reference counted (use [`FastClose`] **inside** of an `Arc`, not
-///   outside)
+/// - Not reference counted (use [`FastCloseArc`], or [`FastClose`] **inside**
+///   of an `Arc`, not outside)
 /// - `Send`
 /// - `!Clone`
 ///
@@ -389,9 +804,21 @@ where
 /// only public API for constructing a `FastClose` that doesn't rely on the
 /// `FastCloseable` trait (`FastClose::new` just calls `.fast_close()` on the
 /// parameter)
+///
+/// If closing the type can report an error, override `close()` to return it,
+/// otherwise the default implementation just drops `self`
+///
+/// Boxed readers and writers (`Box<dyn Read + Send>` and
+/// `Box<dyn Write + Send>`) are `FastCloseable`, so sinks chosen at runtime
+/// can still be closed in the background. Make sure whatever's in the box
+/// owns its handle
+///
+/// With the `derive` feature, `#[derive(FastCloseable)]` implements this,
+/// checking the requirements above that can be checked at compile time
 pub trait FastCloseable: Send {
     /// Wraps `self` in [`FastClose`]
     #[inline(always)]
+    #[track_caller]
     fn fast_close(self) -> FastClose<Self>
     where
         Self: Sized,
@@ -399,113 +826,226 @@ pub trait FastCloseable: Send {
         // Use internal constructor, because the public one calls .fast_close()
         FastClose::_new(self)
     }
+
+    /// Prepares `self` to be closed, returning any error encountered
+    ///
+    /// This runs just before [`close`](FastCloseable::close), wherever the
+    /// handle is closed, so is the place to flush buffered writes whose
+    /// errors would otherwise be lost. In the background, errors are sent to
+    /// the [error sink](crate::set_error_sink). Does nothing by default
+    fn before_close(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Closes `self` inline, returning any error encountered
+    ///
+    /// See [`FastClose::close`]
+    fn close(self) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        drop(self);
+        Ok(())
+    }
 }
 
-impl FastCloseable for std::fs::File {}
+impl FastCloseable for std::fs::File {
+    /// On Windows, returns any error from `CloseHandle`.
+    /// On other platforms, this can't fail
+    fn close(self) -> io::Result<()> {
+        #[cfg(windows)]
+        {
+            use std::os::windows::io::IntoRawHandle;
 
-/// Trait implementations for `async-std` types
-#[cfg(feature = "backend-async-std")]
-mod async_std_impls {
-    use std::{
-        pin::Pin,
-        task::{Context, Poll},
-    };
+            use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
 
-    use async_std::io::{
-        Read as AsyncRead, Seek as AsyncSeek, Write as AsyncWrite,
-    };
+            let handle = self.into_raw_handle();
+            // SAFETY: the file has given up ownership of the handle, so it's
+            // closed exactly once, here
+            if unsafe { CloseHandle(handle as HANDLE) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+        #[cfg(not(windows))]
+        {
+            drop(self);
+            Ok(())
+        }
+    }
+}
 
-    use super::*;
+impl FastClose<std::fs::File> {
+    /// Creates a new `FastClose<File>` instance that shares the same
+    /// underlying file handle as the existing one
+    ///
+    /// See [`File::try_clone`](std::fs::File::try_clone). The clone is
+    /// closed independently, so only its own closure is offloaded when it's
+    /// dropped
+    pub fn try_clone(&self) -> io::Result<Self> {
+        self.deref().try_clone().map(FastClose::new)
+    }
+}
 
-    impl FastCloseable for async_std::fs::File {}
+impl From<FastClose<std::fs::File>> for std::process::Stdio {
+    /// Unwraps the file without submitting it to be closed, as the child
+    /// process owns it afterwards
+    fn from(file: FastClose<std::fs::File>) -> Self {
+        file.into_inner().into()
+    }
+}
 
-    impl<H> AsyncRead for FastClose<H>
-    where
-        H: AsyncRead + Send + 'static,
-    {
-        fn poll_read(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            buf: &mut [u8],
-        ) -> Poll<io::Result<usize>> {
-            self.pin_project_to_inner().poll_read(cx, buf)
-        }
+/// A handle that's closed when dropped, sending the result to
+/// [`FastClose::close_with_result`]'s receiver
+struct CloseReporter<H: FastCloseable> {
+    handle: Option<H>,
+    sender: mpsc::SyncSender<io::Result<()>>,
+}
 
-        fn poll_read_vectored(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            bufs: &mut [IoSliceMut<'_>],
-        ) -> Poll<io::Result<usize>> {
-            self.pin_project_to_inner().poll_read_vectored(cx, bufs)
+impl<H: FastCloseable> FastCloseable for CloseReporter<H> {}
+
+impl<H: FastCloseable> Drop for CloseReporter<H> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            // The receiver may have been dropped, which is fine
+            let _ = self.sender.send(close_handle(handle));
         }
     }
+}
 
-    impl<H> AsyncSeek for FastClose<H>
-    where
-        H: AsyncSeek + Send + 'static,
-    {
-        fn poll_seek(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            pos: SeekFrom,
-        ) -> Poll<io::Result<u64>> {
-            self.pin_project_to_inner().poll_seek(cx, pos)
-        }
+/// Closes a handle after running its
+/// [`before_close`](FastCloseable::before_close) hook, returning the first
+/// error from either
+fn close_handle<H: FastCloseable>(mut handle: H) -> io::Result<()> {
+    let prepared = handle.before_close();
+    prepared.and(handle.close())
+}
+
+// Collections of handles are submitted to the backend as a single job, so
+// dropping lots of handles at once only costs one submission
+impl<H: FastCloseable> FastCloseable for Vec<H> {
+    /// Prepares every handle, returning the first error encountered
+    fn before_close(&mut self) -> io::Result<()> {
+        self.iter_mut()
+            .map(H::before_close)
+            .fold(Ok(()), Result::and)
     }
 
-    impl<H> AsyncWrite for FastClose<H>
-    where
-        H: AsyncWrite + Send + 'static,
-    {
-        fn poll_write(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            buf: &[u8],
-        ) -> Poll<io::Result<usize>> {
-            self.pin_project_to_inner().poll_write(cx, buf)
-        }
+    /// Closes every handle, returning the first error encountered
+    fn close(self) -> io::Result<()> {
+        self.into_iter().map(H::close).fold(Ok(()), Result::and)
+    }
+}
 
-        fn poll_write_vectored(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            bufs: &[IoSlice<'_>],
-        ) -> Poll<io::Result<usize>> {
-            self.pin_project_to_inner().poll_write_vectored(cx, bufs)
-        }
+impl<H: FastCloseable> FastCloseable for Box<[H]> {
+    /// Prepares every handle, returning the first error encountered
+    fn before_close(&mut self) -> io::Result<()> {
+        self.iter_mut()
+            .map(H::before_close)
+            .fold(Ok(()), Result::and)
+    }
 
-        fn poll_flush(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<io::Result<()>> {
-            self.pin_project_to_inner().poll_flush(cx)
-        }
+    /// Closes every handle, returning the first error encountered
+    fn close(self) -> io::Result<()> {
+        self.into_vec().close()
+    }
+}
 
-        fn poll_close(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<io::Result<()>> {
-            self.pin_project_to_inner().poll_close(cx)
-        }
+impl<H: FastCloseable, const N: usize> FastCloseable for [H; N] {
+    /// Prepares every handle, returning the first error encountered
+    fn before_close(&mut self) -> io::Result<()> {
+        self.iter_mut()
+            .map(H::before_close)
+            .fold(Ok(()), Result::and)
+    }
+
+    /// Closes every handle, returning the first error encountered
+    fn close(self) -> io::Result<()> {
+        self.into_iter().map(H::close).fold(Ok(()), Result::and)
+    }
+}
+
+// Standard IO adapters own their inner handles, so can be closed in the
+// background in their entirety
+impl<H: FastCloseable> FastCloseable for io::Take<H> {
+    fn before_close(&mut self) -> io::Result<()> {
+        self.get_mut().before_close()
+    }
+
+    fn close(self) -> io::Result<()> {
+        self.into_inner().close()
     }
 }
 
-/// Trait implementations for `smol` types
+impl<A: FastCloseable, B: FastCloseable> FastCloseable for io::Chain<A, B> {
+    /// Prepares both handles, returning the first error encountered
+    fn before_close(&mut self) -> io::Result<()> {
+        let (first, second) = self.get_mut();
+        first.before_close().and(second.before_close())
+    }
+
+    /// Closes both handles, returning the first error encountered
+    fn close(self) -> io::Result<()> {
+        let (first, second) = self.into_inner();
+        first.close().and(second.close())
+    }
+}
+
+impl<T: FastCloseable> FastCloseable for io::Cursor<T> {
+    fn before_close(&mut self) -> io::Result<()> {
+        self.get_mut().before_close()
+    }
+
+    fn close(self) -> io::Result<()> {
+        self.into_inner().close()
+    }
+}
+
+// Boxed readers and writers, for when the sink or source is chosen at
+// runtime. Only box types that own their handle, as with any other
+// FastCloseable type
+impl FastCloseable for Box<dyn io::Read + Send> {}
+
+impl FastCloseable for Box<dyn io::Write + Send> {
+    /// Flushes the writer, so errors writing out any buffer are reported
+    fn before_close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+#[cfg(feature = "backend-async-std")]
+impl FastCloseable for ::async_std::fs::File {}
+
 #[cfg(feature = "backend-smol")]
-mod smol_impls {
+impl FastCloseable for ::smol::fs::File {}
+
+// smol's File is a re-export of async-fs'
+#[cfg(all(
+    feature = "backend-async-global-executor",
+    not(feature = "backend-smol"),
+))]
+impl FastCloseable for async_fs::File {}
+
+/// Trait implementations for the `futures` IO traits, which `async-std`,
+/// `smol`, and `async-fs` all use
+#[cfg(any(
+    feature = "backend-async-global-executor",
+    feature = "backend-async-std",
+    feature = "backend-smol",
+))]
+mod futures_io_impls {
     use std::{
         pin::Pin,
         task::{Context, Poll},
     };
 
-    use smol::io::{AsyncRead, AsyncSeek, AsyncWrite};
+    use futures_lite::io::{AsyncRead, AsyncSeek, AsyncWrite};
 
     use super::*;
 
-    impl FastCloseable for smol::fs::File {}
-
     impl<H> AsyncRead for FastClose<H>
     where
-        H: AsyncRead + Send + 'static,
+        H: AsyncRead + FastCloseable + 'static,
     {
         fn poll_read(
             self: Pin<&mut Self>,
@@ -526,7 +1066,7 @@ mod smol_impls {
 
     impl<H> AsyncSeek for FastClose<H>
     where
-        H: AsyncSeek + Send + 'static,
+        H: AsyncSeek + FastCloseable + 'static,
     {
         fn poll_seek(
             self: Pin<&mut Self>,
@@ -539,7 +1079,7 @@ mod smol_impls {
 
     impl<H> AsyncWrite for FastClose<H>
     where
-        H: AsyncWrite + Send + 'static,
+        H: AsyncWrite + FastCloseable + 'static,
     {
         fn poll_write(
             self: Pin<&mut Self>,
@@ -588,9 +1128,19 @@ mod tokio_impls {
 
     impl FastCloseable for tokio::fs::File {}
 
+    impl FastClose<tokio::fs::File> {
+        /// Creates a new `FastClose<File>` instance that shares the same
+        /// underlying file handle as the existing one
+        ///
+        /// See [`tokio::fs::File::try_clone`]
+        pub async fn try_clone(&self) -> io::Result<Self> {
+            self.deref().try_clone().await.map(FastClose::new)
+        }
+    }
+
     impl<H> AsyncRead for FastClose<H>
     where
-        H: AsyncRead + Send + 'static,
+        H: AsyncRead + FastCloseable + 'static,
     {
         fn poll_read(
             self: Pin<&mut Self>,
@@ -603,7 +1153,7 @@ mod tokio_impls {
 
     impl<H> AsyncSeek for FastClose<H>
     where
-        H: AsyncSeek + Send + 'static,
+        H: AsyncSeek + FastCloseable + 'static,
     {
         fn start_seek(
             self: Pin<&mut Self>,
@@ -622,7 +1172,7 @@ mod tokio_impls {
 
     impl<H> AsyncWrite for FastClose<H>
     where
-        H: AsyncWrite + Send + 'static,
+        H: AsyncWrite + FastCloseable + 'static,
     {
         fn poll_write(
             self: Pin<&mut Self>,
@@ -662,11 +1212,25 @@ mod tokio_impls {
 
 #[cfg(all(test, not(miri)))]
 mod tests {
-    use std::{fs::File, mem::size_of};
+    #[cfg(not(any(
+        feature = "audit",
+        all(windows, feature = "diagnostics")
+    )))]
+    use std::mem::size_of;
+    use std::{
+        fs::File,
+        io,
+        io::{BufRead, BufReader, BufWriter, Cursor, Read, Write},
+    };
+
+    use crate::{FastClose, FastCloseable};
 
-    use crate::FastClose;
+    impl FastCloseable for BufWriter<Vec<u8>> {}
+
+    impl FastCloseable for BufReader<File> {}
 
     #[test]
+    #[cfg(not(any(feature = "audit", all(windows, feature = "diagnostics"))))]
     fn is_zst() {
         assert_eq!(
             size_of::<FastClose<File>>(),
@@ -675,6 +1239,227 @@ mod tests {
         );
     }
 
+    #[test]
+    fn collections() {
+        crate::fs::tests::in_runtime(|| {
+            let open = || File::open("Cargo.toml").unwrap();
+            let files = vec![open(), open()].fast_close();
+            assert_eq!(files.len(), 2);
+            let files = files.into_inner().into_boxed_slice().fast_close();
+            assert_eq!(files.len(), 2);
+            let files = [open(), open(), open()].fast_close();
+            assert_eq!(files.len(), 3);
+        });
+    }
+
+    #[test]
+    fn io_adapters() {
+        crate::fs::tests::in_runtime(|| {
+            let open = || File::open("Cargo.toml").unwrap();
+            let mut take = open().take(9).fast_close();
+            let mut contents = String::new();
+            take.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "[package]");
+
+            let mut chain = open().take(1).chain(open().take(1)).fast_close();
+            contents.clear();
+            chain.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "[[");
+        });
+    }
+
+    // Tokio's tasks don't run while the test blocks its only thread
+    #[test]
+    #[cfg(not(feature = "backend-tokio"))]
+    fn close_with_result() {
+        let file = File::open("Cargo.toml").unwrap().fast_close();
+        let result = file.close_with_result();
+        result
+            .recv()
+            .expect("result should be sent")
+            .expect("closing should succeed");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn raw_handle() {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle, IntoRawHandle};
+
+        let file = File::open("Cargo.toml").unwrap().fast_close();
+        let raw = file.as_raw_handle();
+        assert_eq!(raw, file.into_raw_handle());
+        // SAFETY: the handle was given up by the FastClose above, so is owned
+        // by this file alone
+        let mut file = unsafe { File::from_raw_handle(raw) };
+        let mut buf = [0; 9];
+        file.read_exact(&mut buf)
+            .expect("handle should still be open");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn fd() {
+        use std::os::fd::{AsFd, AsRawFd};
+
+        let file = File::open("Cargo.toml").unwrap().fast_close();
+        assert_eq!(file.as_fd().as_raw_fd(), file.as_raw_fd());
+    }
+
+    #[test]
+    fn buf_read() {
+        fn first_line(mut reader: impl BufRead) -> String {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            line
+        }
+
+        crate::fs::tests::in_runtime(|| {
+            let file = File::open("Cargo.toml").unwrap();
+            let reader = BufReader::new(file).fast_close();
+            assert_eq!(first_line(reader), "[package]\n");
+        });
+    }
+
+    #[test]
+    fn shared_references() {
+        use std::{io::Seek, thread};
+
+        crate::fs::tests::in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let file = File::create_new(dir.path().join("file"))
+                .unwrap()
+                .fast_close();
+            thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| (&file).write_all(b"hi").unwrap());
+                }
+            });
+            let mut file = &file;
+            assert_eq!(file.stream_position().unwrap(), 8);
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stdio() {
+        use std::process::Command;
+
+        crate::fs::tests::in_runtime(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("out");
+            let file = File::create(&path).unwrap().fast_close();
+            let status = Command::new("echo")
+                .arg("hello")
+                .stdout(file)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            assert_eq!(std::fs::read(&path).unwrap(), b"hello\n");
+        });
+    }
+
+    #[test]
+    fn boxed_writer() {
+        crate::fs::tests::in_runtime(|| {
+            let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+            let sinks: [Box<dyn Write + Send>; 2] = [
+                Box::new(File::create(&path).unwrap()),
+                Box::new(BufWriter::new(Vec::new())),
+            ];
+            for sink in sinks {
+                let mut sink = FastClose::new(sink);
+                sink.write_all(b"hello").unwrap();
+            }
+            assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        });
+    }
+
+    #[test]
+    fn pin_inner() {
+        use std::pin::pin;
+
+        crate::fs::tests::in_runtime(|| {
+            let file = File::open("Cargo.toml").unwrap().fast_close();
+            let mut file = pin!(file);
+            let mut buf = [0; 9];
+            file.as_mut().pin_inner().read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"[package]");
+        });
+    }
+
+    #[test]
+    fn try_clone() {
+        crate::fs::tests::in_runtime(|| {
+            let mut file = File::open("Cargo.toml").unwrap().fast_close();
+            let mut clone: FastClose<File> = file.try_clone().unwrap();
+            let mut buf = [0; 9];
+            file.read_exact(&mut buf).unwrap();
+            clone.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"\nname = \"", "clone should share the cursor");
+        });
+    }
+
+    #[test]
+    fn close_inline() {
+        let file = File::open("Cargo.toml").unwrap().fast_close();
+        file.close().expect("closing should succeed");
+        let files = [(); 3].map(|_| File::open("Cargo.toml").unwrap());
+        files.fast_close().close().expect("closing should succeed");
+    }
+
+    #[test]
+    fn io_adapters_forward_close_hooks() {
+        /// Fails whichever of its close hooks is named
+        struct Fails(&'static str);
+
+        impl Fails {
+            fn hook(&self, name: &'static str) -> io::Result<()> {
+                if self.0 == name {
+                    Err(io::Error::other(name))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        impl AsRef<[u8]> for Fails {
+            fn as_ref(&self) -> &[u8] {
+                &[]
+            }
+        }
+
+        impl FastCloseable for Fails {
+            fn before_close(&mut self) -> io::Result<()> {
+                self.hook("before_close")
+            }
+
+            fn close(self) -> io::Result<()> {
+                self.hook("close")
+            }
+        }
+
+        let cursor = Cursor::new(Fails("before_close"));
+        let why = crate::close_handle(cursor).unwrap_err();
+        assert_eq!(why.to_string(), "before_close");
+
+        let take = Cursor::new(Fails("close")).take(1);
+        let why = crate::close_handle(take).unwrap_err();
+        assert_eq!(why.to_string(), "close");
+
+        let chain =
+            Cursor::new(Fails("neither")).chain(Cursor::new(Fails("close")));
+        let why = crate::close_handle(chain).unwrap_err();
+        assert_eq!(why.to_string(), "close");
+    }
+
+    #[test]
+    fn into_inner_flushed() {
+        let mut writer = FastClose::new(BufWriter::new(Vec::new()));
+        writer.write_all(b"buffered").unwrap();
+        let writer = writer.into_inner_flushed().expect("flush should succeed");
+        assert_eq!(writer.get_ref(), b"buffered");
+    }
+
     #[test]
     fn debug_repr_hides_manually_drop() {
         let file = FastClose::new(File::open("Cargo.toml").unwrap());
@@ -700,8 +1485,7 @@ mod tests {
 
         #[cfg(feature = "backend-tokio")]
         {
-            // Dropping `FastClose` without being in a tokio runtime will cause
-            // a panic
+            // Without a runtime to spawn onto, the handle can't be offloaded
             let runtime = tokio::runtime::Builder::new_current_thread()
                 .build()
                 .unwrap();
@@ -711,23 +1495,43 @@ mod tests {
     }
 
     #[cfg(any(
+        feature = "backend-async-global-executor",
         feature = "backend-async-std",
         feature = "backend-smol",
         feature = "backend-tokio",
     ))]
     mod async_traits {
         // Import fudging spaghetti to keep the tests clean & without
-        // duplication
-        #[cfg(feature = "backend-async-std")]
+        // duplication. If several async backends are enabled, the first of
+        // tokio, smol, async-std, and async-global-executor is tested
+        #[cfg(all(
+            feature = "backend-async-std",
+            not(any(feature = "backend-smol", feature = "backend-tokio")),
+        ))]
         use async_std::{
             fs::File, io::prelude::*, io::SeekFrom, task as runtime,
         };
-        #[cfg(feature = "backend-smol")]
+        #[cfg(all(
+            feature = "backend-smol",
+            not(feature = "backend-tokio")
+        ))]
         use smol::{self as runtime, fs::File, io::*};
         #[cfg(feature = "backend-tokio")]
         use tokio::{fs::File, io::*};
         #[cfg(feature = "backend-tokio")]
         use tokio_shim::RuntimeShim as runtime;
+        #[cfg(all(
+            feature = "backend-async-global-executor",
+            not(any(
+                feature = "backend-async-std",
+                feature = "backend-smol",
+                feature = "backend-tokio",
+            )),
+        ))]
+        use {
+            async_fs::File, async_global_executor as runtime,
+            futures_lite::io::*,
+        };
 
         // This piece of jank means that I can run a future on a Tokio runtime
         // as a static function. I either write this hack, or have to re-write
@@ -780,6 +1584,42 @@ mod tests {
     }
 }
 
+/// Runs futures to completion on the async backend's runtime in tests
+#[cfg(all(
+    test,
+    not(miri),
+    any(
+        feature = "backend-async-std",
+        feature = "backend-smol",
+        feature = "backend-tokio",
+    )
+))]
+mod test_runtime {
+    use std::future::Future;
+
+    /// Blocks the current thread until `future` completes
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        #[cfg(all(
+            feature = "backend-async-std",
+            not(any(feature = "backend-smol", feature = "backend-tokio")),
+        ))]
+        {
+            async_std::task::block_on(future)
+        }
+        #[cfg(all(feature = "backend-smol", not(feature = "backend-tokio")))]
+        {
+            smol::block_on(future)
+        }
+        #[cfg(feature = "backend-tokio")]
+        {
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap()
+                .block_on(future)
+        }
+    }
+}
+
 #[cfg(all(test, miri))]
 mod miri_tests {
     use crate::{FastClose, FastCloseable};
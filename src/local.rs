@@ -0,0 +1,280 @@
+//! A thread-local alternative to [`FastClose`](crate::FastClose) for handle
+//! types that aren't `Send`
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt,
+    fmt::Arguments,
+    io,
+    io::{IoSlice, IoSliceMut, SeekFrom},
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+thread_local! {
+    /// Handles dropped on this thread that are waiting for [`poll_local`]
+    static DEFERRED: RefCell<Vec<Box<dyn Any>>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// A wrapper that defers closing a handle until [`poll_local`] is called on
+/// the thread it was dropped on
+///
+/// [`FastClose`](crate::FastClose) can only wrap `Send` handles, as they're
+/// closed on another thread. Handle types that aren't `Send` (e.g. ones with
+/// `Rc` bookkeeping) can use `FastCloseLocal` instead, which queues them up
+/// on the current thread when dropped, so they can be closed all at once at a
+/// convenient time, such as when the thread would otherwise be idle. Any
+/// handles still queued when the thread exits are closed then
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use close_already::FastCloseLocal;
+///
+/// for _ in 0..100 {
+///     let file = FastCloseLocal::new(File::create("hello.txt").unwrap());
+///     // ...
+/// }
+/// // Close all 100 files now
+/// close_already::poll_local();
+/// ```
+///
+/// The API matches [`FastClose`](crate::FastClose)'s where possible, so code
+/// can be written generically over both (e.g. with the `Read`, `Write`, and
+/// `Deref` impls). Handles that need to be closed as soon as they're dropped
+/// can be created with [`eager`](FastCloseLocal::eager) instead
+///
+/// On non-Windows targets, handles are just dropped normally
+pub struct FastCloseLocal<H: 'static> {
+    handle: ManuallyDrop<H>,
+    eager: bool,
+}
+
+impl<H: 'static> FastCloseLocal<H> {
+    /// Creates a new handle that will be closed by [`poll_local`]
+    #[inline]
+    pub fn new(handle: H) -> Self {
+        FastCloseLocal {
+            handle: ManuallyDrop::new(handle),
+            eager: false,
+        }
+    }
+
+    /// Creates a handle that will be closed inline when dropped, like an
+    /// unwrapped handle would be
+    ///
+    /// See [`FastClose::eager`](crate::FastClose::eager)
+    pub fn eager(handle: H) -> Self {
+        let mut local = FastCloseLocal::new(handle);
+        local.set_eager(true);
+        local
+    }
+
+    /// Sets whether the handle will be closed inline when dropped, instead
+    /// of being deferred until [`poll_local`]
+    pub fn set_eager(&mut self, eager: bool) {
+        self.eager = eager;
+    }
+
+    /// Gets back the inner file type
+    ///
+    /// This means that `close_already` will no longer defer closing the
+    /// handle on drop
+    pub fn into_inner(self) -> H {
+        let mut wrapped = ManuallyDrop::new(self);
+        // SAFETY: we are never going to access self.handle again because this
+        // method takes ownership of self and we've already prevented its
+        // destructor from being called
+        unsafe { ManuallyDrop::take(&mut wrapped.handle) }
+    }
+
+    /// Flushes the inner writer, then gets it back, like
+    /// [`into_inner`](FastCloseLocal::into_inner)
+    ///
+    /// See [`FastClose::into_inner_flushed`](crate::FastClose::into_inner_flushed)
+    pub fn into_inner_flushed(mut self) -> io::Result<H>
+    where
+        H: io::Write,
+    {
+        self.flush()?;
+        Ok(self.into_inner())
+    }
+}
+
+/// Closes all handles that were deferred by [`FastCloseLocal`]s dropped on
+/// the current thread, returning how many were closed
+pub fn poll_local() -> usize {
+    let deferred = DEFERRED.take();
+    let closed = deferred.len();
+    drop(deferred);
+    closed
+}
+
+impl<H: 'static> Drop for FastCloseLocal<H> {
+    /// Queues the handle to be closed by [`poll_local`], unless it's eager
+    ///
+    /// Note: on non-Windows targets, the handle is just dropped normally
+    fn drop(&mut self) {
+        // SAFETY: we're in Drop, so self.handle won't be accessed again
+        let handle = unsafe { ManuallyDrop::take(&mut self.handle) };
+        if cfg!(windows) && !self.eager {
+            // If the thread is exiting, the handle is just dropped instead
+            let _ = DEFERRED.try_with(move |deferred| {
+                deferred.borrow_mut().push(Box::new(handle));
+            });
+        }
+    }
+}
+
+impl<H: 'static> Deref for FastCloseLocal<H> {
+    type Target = H;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl<H: 'static> DerefMut for FastCloseLocal<H> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.handle
+    }
+}
+
+impl<H: 'static> From<H> for FastCloseLocal<H> {
+    fn from(handle: H) -> Self {
+        FastCloseLocal::new(handle)
+    }
+}
+
+impl<H: fmt::Debug + 'static> fmt::Debug for FastCloseLocal<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("FastCloseLocal")
+            .field(self.handle.deref())
+            .finish()
+    }
+}
+
+impl<H: io::Read + 'static> io::Read for FastCloseLocal<H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.handle.read(buf)
+    }
+
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        self.handle.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.handle.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.handle.read_to_string(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.handle.read_exact(buf)
+    }
+}
+
+impl<H: io::BufRead + 'static> io::BufRead for FastCloseLocal<H> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.handle.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.handle.consume(amt)
+    }
+
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.handle.read_until(byte, buf)
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.handle.read_line(buf)
+    }
+}
+
+impl<H: io::Write + 'static> io::Write for FastCloseLocal<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.handle.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.handle.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.flush()
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.handle.write_all(buf)
+    }
+
+    fn write_fmt(&mut self, fmt: Arguments<'_>) -> io::Result<()> {
+        self.handle.write_fmt(fmt)
+    }
+}
+
+impl<H: io::Seek + 'static> io::Seek for FastCloseLocal<H> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.handle.seek(pos)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.handle.rewind()
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.handle.stream_position()
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    /// A `!Send` handle that counts how many times it's been dropped
+    struct Handle(Rc<Cell<usize>>);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn closes_on_poll() {
+        let dropped = Rc::new(Cell::new(0));
+        for _ in 0..3 {
+            drop(FastCloseLocal::new(Handle(Rc::clone(&dropped))));
+        }
+        if cfg!(windows) {
+            assert_eq!(dropped.get(), 0, "closures should be deferred");
+            assert_eq!(poll_local(), 3);
+        }
+        assert_eq!(dropped.get(), 3);
+        assert_eq!(poll_local(), 0);
+    }
+
+    #[test]
+    fn into_inner_isnt_deferred() {
+        let dropped = Rc::new(Cell::new(0));
+        drop(FastCloseLocal::new(Handle(Rc::clone(&dropped))).into_inner());
+        assert_eq!(dropped.get(), 1);
+    }
+
+    #[test]
+    fn eager_isnt_deferred() {
+        let dropped = Rc::new(Cell::new(0));
+        drop(FastCloseLocal::eager(Handle(Rc::clone(&dropped))));
+        assert_eq!(dropped.get(), 1);
+        assert_eq!(poll_local(), 0);
+    }
+}
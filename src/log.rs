@@ -0,0 +1,113 @@
+//! Logging with the [`log`](::log) crate
+//!
+//! With the `log` feature enabled, `close_already` logs:
+//! - A warning for each error or panic encountered in the background (as also
+//!   sent to the error sink and panic handler)
+//! - A warning when the number of handles waiting to be closed reaches a
+//!   threshold (see [`set_queue_threshold`])
+//! - A warning for each handle reported by the [`watchdog`](crate::watchdog)
+//! - A debug message for each batch submitted to the backend (see
+//!   [`set_batching`](crate::set_batching))
+use std::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ::log::{debug, warn};
+
+use crate::{watchdog::SlowClose, CloseError, ClosePanic};
+
+/// The queue depth to warn at, or zero if disabled
+static QUEUE_THRESHOLD: AtomicUsize = AtomicUsize::new(10_000);
+
+/// Sets the number of handles waiting to be closed at which a warning is
+/// logged, or disables the warning if `None`
+///
+/// The warning is logged each time the queue grows to the threshold, which
+/// is 10,000 handles by default. A queue that long usually means handles are
+/// being dropped faster than the backend can close them
+pub fn set_queue_threshold(threshold: Option<NonZeroUsize>) {
+    QUEUE_THRESHOLD
+        .store(threshold.map_or(0, NonZeroUsize::get), Ordering::Relaxed);
+}
+
+/// Warns if the queue has just grown to the threshold
+pub(crate) fn queue_depth(pending: usize) {
+    if pending == QUEUE_THRESHOLD.load(Ordering::Relaxed) {
+        warn!("{pending} handles are waiting to be closed");
+    }
+}
+
+/// Warns about an error encountered in the background
+pub(crate) fn error(error: &CloseError) {
+    warn!("{error}");
+}
+
+/// Warns about a panic caught in the background
+pub(crate) fn panic(panic: &ClosePanic) {
+    warn!("{panic}");
+}
+
+/// Warns about a handle taking too long to close
+pub(crate) fn slow_close(slow: &SlowClose) {
+    warn!("{slow}");
+}
+
+/// Notes a batch of handles being submitted
+pub(crate) fn batch(size: usize) {
+    debug!("submitting a batch of {size} handles to be closed");
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{
+        io,
+        sync::{Mutex, PoisonError},
+    };
+
+    use ::log::{Level, LevelFilter, Log, Metadata, Record};
+
+    use super::*;
+
+    /// Every message logged at warning level, by any test
+    static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Logger;
+
+    impl Log for Logger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Warn
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn warns_about_errors_and_queue_depth() {
+        struct LoggedHandle;
+
+        ::log::set_logger(&Logger).unwrap();
+        ::log::set_max_level(LevelFilter::Warn);
+        crate::error::report_handle::<LoggedHandle>(
+            None,
+            io::Error::other("oops"),
+        );
+        queue_depth(10_000);
+        let warnings = WARNINGS.lock().unwrap();
+        assert!(warnings.iter().any(|warning| {
+            warning.contains("LoggedHandle") && warning.ends_with("oops")
+        }));
+        assert!(
+            warnings.contains(&"10000 handles are waiting to be closed".into())
+        );
+    }
+}
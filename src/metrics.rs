@@ -0,0 +1,70 @@
+//! Reporting to the [`metrics`](::metrics) facade
+//!
+//! With the `metrics` feature enabled, the following are emitted to whichever
+//! recorder the application has installed:
+//! - `close_already.closes_submitted`: a counter of the handles submitted to be
+//!   closed in the background
+//! - `close_already.close_duration_seconds`: a histogram of how long each
+//!   handle took to close
+//! - `close_already.queue_depth`: a gauge of the number of handles waiting to
+//!   be closed (see [`pending`](crate::pending()))
+use std::time::Duration;
+
+/// Records a handle as having been submitted
+pub(crate) fn submitted() {
+    ::metrics::counter!("close_already.closes_submitted").increment(1);
+    queue_depth();
+}
+
+/// Records a handle as having been closed, taking `duration`
+pub(crate) fn closed(duration: Duration) {
+    ::metrics::histogram!("close_already.close_duration_seconds")
+        .record(duration);
+    queue_depth();
+}
+
+fn queue_depth() {
+    ::metrics::gauge!("close_already.queue_depth")
+        .set(crate::pending::count() as f64);
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use metrics_util::{
+        debugging::{DebugValue, DebuggingRecorder},
+        MetricKind,
+    };
+
+    use super::*;
+
+    #[test]
+    fn emits_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        ::metrics::with_local_recorder(&recorder, || {
+            submitted();
+            closed(Duration::from_millis(5));
+        });
+        let metrics = snapshotter.snapshot().into_vec();
+        let value = |kind, name| {
+            metrics
+                .iter()
+                .find(|(key, ..)| {
+                    key.kind() == kind && key.key().name() == name
+                })
+                .map(|(.., value)| value)
+        };
+        assert_eq!(
+            value(MetricKind::Counter, "close_already.closes_submitted"),
+            Some(&DebugValue::Counter(1))
+        );
+        assert!(matches!(
+            value(MetricKind::Histogram, "close_already.close_duration_seconds"),
+            Some(DebugValue::Histogram(durations)) if durations.len() == 1
+        ));
+        assert!(matches!(
+            value(MetricKind::Gauge, "close_already.queue_depth"),
+            Some(DebugValue::Gauge(_))
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+//! Per-path ordering of closures, for programs that reopen files they've just
+//! dropped
+//!
+//! Closing a handle in the background means that dropping a file and then
+//! immediately reopening, replacing, or deleting the same path can race the
+//! closure (on Windows, usually surfacing as a sharing violation). With
+//! [`set_ordering`] enabled, the closures of handles opened by the
+//! [`fs`](crate::fs) module are tracked by path: closures of the same path
+//! happen one at a time, in the order the handles were opened, and
+//! [`wait_for_path`] can be used to wait for just that path's closures to
+//! finish, rather than every closure in the backend's queue
+//!
+//! Alternatively, [`set_reopen_safety`] makes the `fs` module's functions
+//! wait for the backend's queue to empty, and try again, if opening a file
+//! fails because it's still open. This doesn't need handles to be tracked,
+//! but only helps code that opens files through the `fs` module. With both
+//! enabled, only the closures of the file being opened are waited for
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use close_already::{fs, paths};
+//!
+//! paths::set_ordering(true);
+//! fs::write("config.toml", b"...")?;
+//! paths::wait_for_path("config.toml");
+//! // The handle used to write config.toml has definitely been closed
+//! std::fs::remove_file("config.toml")?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    ops::{Deref, DerefMut},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, MutexGuard, OnceLock, PoisonError,
+    },
+    time::Duration,
+};
+
+use crate::FastCloseable;
+
+/// Whether handles opened by the `fs` module should be tracked by path
+static ORDERING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the `fs` module waits for pending closures when a file it opens
+/// is still open
+static REOPEN_SAFETY: AtomicBool = AtomicBool::new(false);
+
+/// Every path with closures that are outstanding
+static REGISTRY: OnceLock<Mutex<HashMap<PathBuf, PathQueue>>> = OnceLock::new();
+
+/// Notified whenever a path's closures have all finished
+static FINISHED: Condvar = Condvar::new();
+
+/// Enables or disables per-path ordering of closures for handles opened by
+/// the [`fs`](crate::fs) module
+///
+/// This is disabled by default, as it costs some bookkeeping (and resolving
+/// the path) for every file opened. Handles opened while it was enabled stay
+/// tracked if it's later disabled
+pub fn set_ordering(enabled: bool) {
+    ORDERING.store(enabled, Ordering::Relaxed);
+}
+
+/// Enables or disables waiting for pending closures when files opened by the
+/// [`fs`](crate::fs) module are still open
+///
+/// Closing handles in the background means that, on Windows, writing a file
+/// with [`fs::write`](crate::fs::write) and then immediately reading it back
+/// with [`fs::read`](crate::fs::read) can fail with a sharing violation, as
+/// the first handle hasn't been closed yet. With this enabled, if opening a
+/// file fails that way, the `fs` functions wait (for up to a second) for the
+/// backend to close the handles waiting in its queue, or just the tracked
+/// handles to that file if [`set_ordering`] is enabled, then try once more.
+/// If the file is held open by something else, the error is returned as
+/// usual
+///
+/// This is disabled by default. It costs nothing unless opening a file fails
+pub fn set_reopen_safety(enabled: bool) {
+    REOPEN_SAFETY.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the `fs` module should wait for pending closures if a file it
+/// opens is still open (see [`set_reopen_safety`])
+pub(crate) fn reopen_safety() -> bool {
+    REOPEN_SAFETY.load(Ordering::Relaxed)
+}
+
+/// Blocks the current thread until all tracked handles to `path` have been
+/// closed
+///
+/// This only knows about handles opened by the [`fs`](crate::fs) module while
+/// [`set_ordering`] was enabled. Note that this includes handles that haven't
+/// been dropped yet, so waiting on a path you still have a tracked handle to
+/// will block forever
+pub fn wait_for_path(path: impl AsRef<Path>) {
+    let key = resolve(path.as_ref());
+    let mut registry = lock_registry();
+    while registry.contains_key(&key) {
+        registry = FINISHED
+            .wait(registry)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+/// Blocks the current thread for up to `timeout` until all tracked handles to
+/// `path` have been closed, if [`set_ordering`] is enabled
+///
+/// Returns whether any handles to `path` were tracked, so whether waiting
+/// for them was worthwhile. Unlike [`wait_for_path`], this gives up after
+/// `timeout`, as the caller may hold one of the handles itself
+pub(crate) fn wait_for_tracked_path(path: &Path, timeout: Duration) -> bool {
+    if !ORDERING.load(Ordering::Relaxed) {
+        return false;
+    }
+    let key = resolve(path);
+    let registry = lock_registry();
+    if !registry.contains_key(&key) {
+        return false;
+    }
+    let _ = FINISHED
+        .wait_timeout_while(registry, timeout, |registry| {
+            registry.contains_key(&key)
+        })
+        .unwrap_or_else(PoisonError::into_inner);
+    true
+}
+
+/// The closures outstanding for one path
+#[derive(Default)]
+struct PathQueue {
+    /// The ticket to give to the next handle opened
+    next: u64,
+    /// The ticket of the next handle to close
+    serving: u64,
+    /// Handles that have been dropped but have to wait for earlier handles
+    /// to close first
+    ready: BTreeMap<u64, Box<dyn Send>>,
+    /// Whether a thread is currently closing this path's handles
+    closing: bool,
+}
+
+/// A handle that may be tracked by path, depending on whether
+/// [`set_ordering`] was enabled when it was created
+///
+/// This is used by the `fs` module, which is the only place paths are known
+#[derive(Debug)]
+pub(crate) struct Tracked<H: Send + 'static> {
+    handle: Option<H>,
+    ticket: Option<(PathBuf, u64)>,
+}
+
+impl<H: Send + 'static> Tracked<H> {
+    /// Wraps a handle to `path`, tracking it if ordering is enabled
+    pub(crate) fn new(handle: H, path: &Path) -> Self {
+        let ticket = ORDERING.load(Ordering::Relaxed).then(|| {
+            let key = resolve(path);
+            let mut registry = lock_registry();
+            let queue = registry.entry(key.clone()).or_default();
+            let ticket = queue.next;
+            queue.next += 1;
+            (key, ticket)
+        });
+        Tracked {
+            handle: Some(handle),
+            ticket,
+        }
+    }
+}
+
+impl<H: Send + 'static> Deref for Tracked<H> {
+    type Target = H;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.as_ref().expect("handle is only taken on drop")
+    }
+}
+
+impl<H: Send + 'static> DerefMut for Tracked<H> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.handle.as_mut().expect("handle is only taken on drop")
+    }
+}
+
+impl<H: FastCloseable + 'static> FastCloseable for Tracked<H> {
+    fn before_close(&mut self) -> io::Result<()> {
+        self.deref_mut().before_close()
+    }
+
+    /// Closes the handle inline if it isn't tracked. Tracked handles are
+    /// closed in order, as when dropped, so errors closing them are ignored
+    fn close(mut self) -> io::Result<()> {
+        match self.ticket {
+            Some(_) => {
+                drop(self);
+                Ok(())
+            },
+            None => self.handle.take().map_or(Ok(()), H::close),
+        }
+    }
+}
+
+impl<H: io::Read + Send + 'static> io::Read for Tracked<H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.deref_mut().read(buf)
+    }
+}
+
+impl<H: io::Write + Send + 'static> io::Write for Tracked<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.deref_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deref_mut().flush()
+    }
+}
+
+impl<H: Send + 'static> Drop for Tracked<H> {
+    /// Closes the handle, as well as any later handles to the same path that
+    /// were waiting on it. If earlier handles to the path are yet to be
+    /// closed, the handle is left for whichever thread closes them
+    ///
+    /// This never blocks waiting for other handles, so can't deadlock a
+    /// backend's thread pool. If closing a handle panics, the rest are still
+    /// closed before the panic is resumed, so the path's queue never stalls
+    fn drop(&mut self) {
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+        let Some((key, ticket)) = self.ticket.take() else {
+            return;
+        };
+        let mut registry = lock_registry();
+        let queue = registry.get_mut(&key).expect("tracked path is registered");
+        queue.ready.insert(ticket, Box::new(handle));
+        if queue.closing {
+            return;
+        }
+        queue.closing = true;
+        let mut panicked = None;
+        loop {
+            let queue = registry.get_mut(&key).expect("path is being closed");
+            let serving = queue.serving;
+            let Some(handle) = queue.ready.remove(&serving) else {
+                queue.closing = false;
+                if queue.serving == queue.next {
+                    registry.remove(&key);
+                    FINISHED.notify_all();
+                }
+                if let Some(payload) = panicked {
+                    drop(registry);
+                    panic::resume_unwind(payload);
+                }
+                return;
+            };
+            // Don't hold up everyone else while closing
+            drop(registry);
+            if let Err(payload) =
+                panic::catch_unwind(AssertUnwindSafe(|| drop(handle)))
+            {
+                panicked.get_or_insert(payload);
+            }
+            registry = lock_registry();
+            registry
+                .get_mut(&key)
+                .expect("path is being closed")
+                .serving += 1;
+        }
+    }
+}
+
+/// Locks the registry, ignoring poisoning as it's never left inconsistent
+fn lock_registry() -> MutexGuard<'static, HashMap<PathBuf, PathQueue>> {
+    REGISTRY
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Resolves `path` to the key it's tracked by. The parent directory is
+/// canonicalised rather than the path itself, so that the key stays the same
+/// once the file has been deleted or replaced
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.into());
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => match parent.canonicalize() {
+            Ok(parent) => parent.join(name),
+            Err(_) => absolute,
+        },
+        _ => absolute,
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+    use crate::fs::{read, tests::in_runtime, write};
+
+    /// Held by tests that enable ordering, so they don't turn it off under
+    /// each other
+    static ORDERING_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Enables ordering until dropped, even if the test fails, along with
+    /// reopen safety if the test enabled it
+    struct Ordered {
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Ordered {
+        fn enable() -> Self {
+            let lock =
+                ORDERING_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+            set_ordering(true);
+            Ordered { _lock: lock }
+        }
+    }
+
+    impl Drop for Ordered {
+        fn drop(&mut self) {
+            set_ordering(false);
+            set_reopen_safety(false);
+        }
+    }
+
+    #[test]
+    fn closes_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let _ordered = Ordered::enable();
+        let handles = (0..3)
+            .map(|_| Tracked::new(Vec::<u8>::new(), &path))
+            .collect::<Vec<_>>();
+        let key = resolve(&path);
+        let mut handles = handles.into_iter();
+        let first = handles.next().unwrap();
+
+        // Later handles have to wait for the first one
+        handles.for_each(drop);
+        assert_eq!(lock_registry()[&key].ready.len(), 2);
+        drop(first);
+        assert!(!lock_registry().contains_key(&key));
+    }
+
+    #[test]
+    fn wait_for_fs_write() {
+        in_runtime(|| {
+            let _ordered = Ordered::enable();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            write(&path, b"hello").unwrap();
+            wait_for_path(&path);
+            assert_eq!(read(&path).unwrap(), b"hello");
+            wait_for_path(dir.path().join(".").join("file"));
+        });
+    }
+
+    #[test]
+    fn panicking_close_advances_queue() {
+        struct Panics;
+
+        impl Drop for Panics {
+            fn drop(&mut self) {
+                panic!("oh no");
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let _ordered = Ordered::enable();
+        let first = Tracked::new(Panics, &path);
+        let second = Tracked::new(Vec::<u8>::new(), &path);
+        let third = Tracked::new(Vec::<u8>::new(), &path);
+        drop(second);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(first))).is_err());
+        // The second handle was closed despite the panic, so the third
+        // doesn't have to wait
+        drop(third);
+        assert!(!lock_registry().contains_key(&resolve(&path)));
+    }
+
+    #[test]
+    fn wait_for_tracked_path_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let timeout = Duration::from_millis(10);
+        let _ordered = Ordered::enable();
+        assert!(!wait_for_tracked_path(&path, timeout), "nothing tracked");
+        let handle = Tracked::new(Vec::<u8>::new(), &path);
+        // The handle is still held, so this gives up
+        assert!(wait_for_tracked_path(&path, timeout));
+        drop(handle);
+        assert!(!wait_for_tracked_path(&path, timeout));
+    }
+
+    #[test]
+    fn reopen_safely_after_write() {
+        in_runtime(|| {
+            let _ordered = Ordered::enable();
+            set_reopen_safety(true);
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file");
+            for n in 0..10u8 {
+                write(&path, [n]).unwrap();
+                assert_eq!(read(&path).unwrap(), [n]);
+            }
+        });
+    }
+}
@@ -0,0 +1,438 @@
+//! Tracking of handles that have been submitted to the backend, but not yet
+//! closed, and limiting how many there can be
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// The handles submitted to the backend that haven't been closed
+static QUEUE: Queue = Queue::new();
+
+/// The count of handles that have been submitted but not closed, and the
+/// limit on how many there can be
+///
+/// There's only one, [`QUEUE`], outside of tests
+struct Queue {
+    /// The number of handles submitted that haven't been closed
+    pending: AtomicUsize,
+    /// Held while checking or emptying the count, so waiters can't miss the
+    /// notification
+    drain_lock: Mutex<()>,
+    /// Notified when the last pending handle is closed, or when any is
+    /// closed while a submitter is blocked by the queue's capacity
+    drained: Condvar,
+    /// The most handles that can be pending before the [`SubmitPolicy`]
+    /// applies
+    capacity: AtomicUsize,
+    /// The [`SubmitPolicy`], as its discriminant
+    policy: AtomicU8,
+    /// The number of submitters blocked by the queue's capacity
+    blocked: AtomicUsize,
+}
+
+/// What happens to a dropped handle when the backend's queue is full (see
+/// [`set_queue_capacity`])
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SubmitPolicy {
+    /// The queue has no capacity, and handles are always submitted
+    #[default]
+    Unbounded,
+    /// The thread dropping the handle waits until the queue has room
+    Block,
+    /// The handle is closed inline, as if `close_already` wasn't being used
+    CloseInline,
+}
+
+/// Limits how many handles can be waiting to be closed by the backend, and
+/// sets what happens to handles dropped once that many are
+///
+/// Every handle waiting in the backend's queue is still open, so under heavy
+/// churn an unbounded queue can use up the process' handle quota. With a
+/// capacity set, handles dropped while the queue is full are either closed
+/// inline, or block until there's room, depending on `policy`.
+/// The capacity is approximate, as many threads may submit handles at once.
+/// [`SubmitPolicy::Unbounded`] removes the limit, which is the default
+///
+/// Don't use [`SubmitPolicy::Block`] if handles are dropped by the backend's
+/// own threads or tasks (e.g. within an async runtime's task when using an
+/// async backend), as they could end up waiting on themselves
+///
+/// This can be changed at any time
+pub fn set_queue_capacity(capacity: NonZeroUsize, policy: SubmitPolicy) {
+    let capacity = match policy {
+        SubmitPolicy::Unbounded => usize::MAX,
+        _ => capacity.get(),
+    };
+    QUEUE.set_capacity(capacity, policy);
+}
+
+/// Blocks until every handle submitted to the backend has been closed
+///
+/// Use this before doing something that needs all files to be closed, like
+/// removing the directory they're in, or exiting. Handles dropped by other
+/// threads while waiting are waited for too, so this may not return while
+/// they're still being dropped.
+/// Only handles closed in the background are waited for, so on non-Windows
+/// platforms this returns immediately
+///
+/// When using an async backend, don't call this from a task on the same
+/// runtime, as it may prevent the closures from running
+pub fn drain() {
+    let mut lock = QUEUE.lock();
+    while count() > 0 {
+        lock = QUEUE
+            .drained
+            .wait(lock)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+/// The number of handles submitted to the backend that haven't been closed
+/// yet
+///
+/// This counts handles waiting in, or being closed by, any backend or
+/// [`Closer`](crate::Closer). Use [`drain`] to wait for it to reach zero, or
+/// [`assert_no_pending_closes!`](crate::assert_no_pending_closes) to check
+/// that it has in tests
+pub fn pending() -> usize {
+    count()
+}
+
+/// Asserts that no handles are waiting to be closed by the backend (i.e.
+/// that [`pending`](crate::pending()) is zero)
+///
+/// On failure, the panic message says how many handles are still pending.
+/// With the `audit` feature enabled, it also lists the labels of the
+/// `FastClose`s that haven't been dropped yet. Like [`assert!`], a custom
+/// message can be given
+///
+/// ```
+/// use close_already::{assert_no_pending_closes, fs};
+///
+/// # let dir = tempfile::tempdir()?;
+/// # let path = dir.path().join("file");
+/// fs::write(&path, "hi")?;
+/// close_already::drain();
+/// assert_no_pending_closes!("{} should have been closed", path.display());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[macro_export]
+macro_rules! assert_no_pending_closes {
+    () => {
+        if let Some(report) = $crate::__pending_report() {
+            panic!("assertion failed: no pending closes\n{report}");
+        }
+    };
+    ($($arg:tt)+) => {
+        if let Some(report) = $crate::__pending_report() {
+            panic!("{}\n{report}", format_args!($($arg)+));
+        }
+    };
+}
+
+/// Describes the handles that are still pending, for
+/// [`assert_no_pending_closes!`](crate::assert_no_pending_closes), if there
+/// are any
+#[doc(hidden)]
+pub fn pending_report() -> Option<String> {
+    let pending = count();
+    if pending == 0 {
+        return None;
+    }
+    #[allow(unused_mut)]
+    let mut report = format!("{pending} handles are waiting to be closed");
+    #[cfg(feature = "audit")]
+    {
+        use std::fmt::Write;
+
+        let outstanding = crate::audit::outstanding();
+        if !outstanding.is_empty() {
+            report.push_str("\nhandles that haven't been dropped:");
+        }
+        for handle in outstanding {
+            let label = handle.label.as_deref().unwrap_or("unlabeled");
+            let _ = write!(report, "\n- {label} ({})", handle.type_name);
+            #[cfg(feature = "diagnostics")]
+            if let Some(origin) = &handle.origin {
+                let _ = write!(report, ", created at {origin}");
+            }
+        }
+    }
+    Some(report)
+}
+
+/// Whether a handle can be submitted to the backend, given the queue's
+/// capacity, blocking until it can if the policy is to block. If not, it
+/// should be closed inline
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn admit() -> bool {
+    QUEUE.admit()
+}
+
+/// Waits up to `timeout` for every handle submitted to the backend to be
+/// closed, returning how many still hadn't been by then
+///
+/// This is a bounded [`drain`], for services that need to shut down within
+/// a deadline. Handles that aren't closed in time are left to the backend,
+/// or to the OS when the process exits, and are counted so that they can be
+/// logged
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let abandoned = close_already::shutdown_timeout(Duration::from_secs(5));
+/// if abandoned > 0 {
+///     eprintln!("{abandoned} files weren't closed before shutting down");
+/// }
+/// ```
+pub fn shutdown_timeout(timeout: Duration) -> usize {
+    let deadline = Instant::now() + timeout;
+    let mut lock = QUEUE.lock();
+    loop {
+        let pending = count();
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if pending == 0 || remaining.is_zero() {
+            return pending;
+        }
+        lock = QUEUE
+            .drained
+            .wait_timeout(lock, remaining)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+    }
+}
+
+/// Blocks until every handle submitted to the backend has been closed when
+/// it's dropped, e.g. at the end of `main`
+///
+/// The backends' threads don't keep the process alive, so if `main` returns
+/// while handles are still waiting to be closed, the OS closes them when the
+/// process exits, skipping anything else their destructors would have done
+/// (e.g. flushing buffers). Hold one of these for the length of `main` to
+/// [`drain`] the backend before exiting, including if `main` panics or
+/// returns an error
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use close_already::{fs, ExitGuard};
+///
+/// let _guard = ExitGuard::new();
+/// for n in 0..1000 {
+///     fs::write(format!("{n}.txt"), "Hello, world!")?;
+/// }
+/// // Every file is closed before main returns
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+#[must_use = "the backend is drained when the guard is dropped, so it should \
+              be held until the end of main"]
+#[non_exhaustive]
+pub struct ExitGuard;
+
+impl ExitGuard {
+    /// Creates a guard that drains the backend when it's dropped
+    pub fn new() -> Self {
+        ExitGuard
+    }
+}
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        drain();
+    }
+}
+
+/// The number of handles submitted to the backend that haven't been closed
+pub(crate) fn count() -> usize {
+    QUEUE.count()
+}
+
+/// Records a handle as having been submitted to the backend
+pub(crate) fn submitted() {
+    crate::stats::submitted();
+    let _pending = QUEUE.submitted();
+    #[cfg(feature = "log")]
+    crate::log::queue_depth(_pending);
+    #[cfg(feature = "metrics")]
+    crate::metrics::submitted();
+}
+
+/// Records a handle as having been closed by the backend, waking anything
+/// waiting in [`drain`] if it was the last one, or blocked in [`admit`]
+pub(crate) fn closed() {
+    crate::stats::completed();
+    QUEUE.closed();
+}
+
+impl Queue {
+    /// An empty queue, with no capacity
+    const fn new() -> Self {
+        Queue {
+            pending: AtomicUsize::new(0),
+            drain_lock: Mutex::new(()),
+            drained: Condvar::new(),
+            capacity: AtomicUsize::new(usize::MAX),
+            policy: AtomicU8::new(SubmitPolicy::Unbounded as u8),
+            blocked: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes the lock waiters hold while checking the count
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.drain_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The number of handles submitted that haven't been closed
+    fn count(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Sets the capacity (`usize::MAX` for none) and policy, see
+    /// [`set_queue_capacity`]
+    fn set_capacity(&self, capacity: usize, policy: SubmitPolicy) {
+        self.capacity.store(capacity, Ordering::SeqCst);
+        self.policy.store(policy as u8, Ordering::SeqCst);
+        // Let blocked submitters check the new capacity
+        let _lock = self.lock();
+        self.drained.notify_all();
+    }
+
+    /// Counts a handle as pending, returning how many now are
+    fn submitted(&self) -> usize {
+        self.pending.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Counts a handle as closed, waking any waiters that need to know
+    fn closed(&self) {
+        let last = self.pending.fetch_sub(1, Ordering::SeqCst) == 1;
+        if last || self.blocked.load(Ordering::SeqCst) > 0 {
+            let _lock = self.lock();
+            self.drained.notify_all();
+        }
+    }
+
+    /// See [`admit`]
+    fn admit(&self) -> bool {
+        if self.count() < self.capacity.load(Ordering::Relaxed) {
+            return true;
+        }
+        match self.policy.load(Ordering::SeqCst) {
+            policy if policy == SubmitPolicy::CloseInline as u8 => false,
+            policy if policy == SubmitPolicy::Block as u8 => {
+                self.blocked.fetch_add(1, Ordering::SeqCst);
+                let mut lock = self.lock();
+                while self.count() >= self.capacity.load(Ordering::SeqCst) {
+                    lock = self
+                        .drained
+                        .wait(lock)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                self.blocked.fetch_sub(1, Ordering::SeqCst);
+                true
+            },
+            _ => true,
+        }
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn assert_no_pending_closes_fails() {
+        submitted();
+        let failed = std::panic::catch_unwind(|| {
+            crate::assert_no_pending_closes!("custom");
+        });
+        closed();
+        let message = *failed.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("custom\n"), "{message}");
+        assert!(message.contains("waiting to be closed"), "{message}");
+    }
+
+    #[test]
+    fn drain_waits_for_closures() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        submitted();
+        let closer = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(10));
+            CLOSED.store(true, Ordering::SeqCst);
+            closed();
+        });
+        drain();
+        assert!(CLOSED.load(Ordering::SeqCst), "drain returned too early");
+        closer.join().unwrap();
+    }
+
+    #[test]
+    fn shutdown_timeout_counts_abandoned() {
+        submitted();
+        // Other tests may have handles pending too
+        let abandoned = shutdown_timeout(Duration::from_millis(10));
+        assert!(abandoned >= 1, "handle should be abandoned");
+        closed();
+    }
+
+    #[test]
+    fn exit_guard_drains() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        let guard = ExitGuard::new();
+        submitted();
+        let closer = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(10));
+            CLOSED.store(true, Ordering::SeqCst);
+            closed();
+        });
+        drop(guard);
+        assert!(CLOSED.load(Ordering::SeqCst), "guard dropped too early");
+        closer.join().unwrap();
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        // Use a queue of its own, so other tests' handles don't block it
+        let queue = Arc::new(Queue::new());
+        queue.set_capacity(1, SubmitPolicy::Block);
+        queue.submitted();
+        let closer = thread::spawn({
+            let queue = Arc::clone(&queue);
+            move || {
+                thread::sleep(Duration::from_millis(10));
+                CLOSED.store(true, Ordering::SeqCst);
+                queue.closed();
+            }
+        });
+        assert!(queue.admit(), "blocked handles should be submitted");
+        assert!(CLOSED.load(Ordering::SeqCst), "admit returned too early");
+        closer.join().unwrap();
+    }
+
+    #[test]
+    fn close_inline_when_full() {
+        let queue = Queue::new();
+        queue.set_capacity(1, SubmitPolicy::CloseInline);
+        assert!(queue.admit());
+        queue.submitted();
+        assert!(!queue.admit(), "full queue should close inline");
+        queue.closed();
+        assert!(queue.admit());
+    }
+}
@@ -0,0 +1,465 @@
+//! The thread pool used by the `threadpool` backend
+//!
+//! Jobs are pushed onto one of several queues (shards), chosen by the
+//! submitting thread, so that many threads dropping handles at once don't
+//! all contend on a single lock. Each worker takes jobs from its own shard
+//! first, stealing from the others once that's empty. Each shard has a queue
+//! per [`Priority`], and workers look for jobs of a higher priority across
+//! all the shards before lower ones. If an idle timeout
+//! is configured, workers exit once they've been idle for that long, and are
+//! respawned when a job is next queued on their shard
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::Priority;
+
+/// A closure to be run by the pool
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The number of [`Priority`] levels
+const PRIORITIES: usize = 3;
+
+/// A shard's job queues, one per [`Priority`], highest first
+type Queues = [VecDeque<Job>; PRIORITIES];
+
+/// Assigns submitting threads to shards round-robin
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The shard the current thread submits jobs to, before being reduced
+    /// modulo the number of shards
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Settings for the `threadpool` backend's thread pool, given to
+/// [`configure`](crate::configure)
+///
+/// ```no_run
+/// use std::{num::NonZeroUsize, time::Duration};
+///
+/// use close_already::PoolConfig;
+///
+/// let config = PoolConfig::default()
+///     .threads(NonZeroUsize::new(4).unwrap())
+///     .thread_name("file-closer")
+///     .stack_size(64 * 1024)
+///     .idle_timeout(Duration::from_secs(30));
+/// close_already::configure(config).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    threads: Option<NonZeroUsize>,
+    thread_name: String,
+    stack_size: Option<usize>,
+    idle_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            threads: None,
+            thread_name: String::from("close_already"),
+            stack_size: None,
+            idle_timeout: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Sets the number of worker threads, which defaults to the number of CPU
+    /// cores
+    pub fn threads(mut self, threads: NonZeroUsize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Sets the prefix of the worker threads' names, which are numbered
+    /// after it (e.g. `close_already-0`, the default)
+    pub fn thread_name(mut self, prefix: impl Into<String>) -> Self {
+        self.thread_name = prefix.into();
+        self
+    }
+
+    /// Sets the stack size of the worker threads, in bytes, which defaults
+    /// to that of [`std::thread::Builder`]
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Lets worker threads exit after they've had nothing to do for
+    /// `timeout`. They're spawned again when they're next needed, so this
+    /// suits programs that only close files in bursts.
+    /// By default, worker threads run for the rest of the program
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+}
+
+/// One of the pool's job queues, with its own lock
+#[derive(Default)]
+struct Shard {
+    queues: Mutex<Queues>,
+    /// The number of jobs queued of each priority, so workers can skip empty
+    /// shards without locking them. Only changed with `queues` locked, which
+    /// orders it, so it's a hint for anyone who doesn't hold the lock
+    queued: [AtomicUsize; PRIORITIES],
+}
+
+impl Shard {
+    fn lock(&self) -> MutexGuard<'_, Queues> {
+        lock(&self.queues)
+    }
+
+    /// Takes the shard's next job of the given priority, if it might have one
+    fn pop(&self, priority: usize) -> Option<Job> {
+        if self.queued[priority].load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let job = self.lock()[priority].pop_front()?;
+        self.queued[priority].fetch_sub(1, Ordering::Relaxed);
+        Some(job)
+    }
+}
+
+/// A fixed-size pool of worker threads with sharded job queues
+pub(crate) struct Pool {
+    shards: Box<[Shard]>,
+    /// Whether each shard's worker is running
+    live: Box<[AtomicBool]>,
+    thread_name: String,
+    stack_size: Option<usize>,
+    idle_timeout: Option<Duration>,
+    /// The number of workers waiting for jobs
+    sleeping: AtomicUsize,
+    /// Held by workers while going to sleep, so they can't miss a wake-up
+    sleep: Mutex<()>,
+    wake: Condvar,
+}
+
+impl Pool {
+    /// Creates a pool with one worker and one shard per thread, which
+    /// defaults to one per CPU core
+    pub(crate) fn new(config: PoolConfig) -> Arc<Pool> {
+        let threads = config
+            .threads
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get);
+        let pool = Arc::new(Pool {
+            shards: (0..threads).map(|_| Shard::default()).collect(),
+            live: (0..threads).map(|_| AtomicBool::new(true)).collect(),
+            thread_name: config.thread_name,
+            stack_size: config.stack_size,
+            idle_timeout: config.idle_timeout,
+            sleeping: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+        });
+        for home in 0..threads {
+            Worker::spawn(Arc::clone(&pool), home);
+        }
+        pool
+    }
+
+    /// Queues a job to be run by one of the pool's workers, respawning the
+    /// shard's worker if it has exited after being idle
+    pub(crate) fn execute(
+        self: &Arc<Self>,
+        job: impl FnOnce() + Send + 'static,
+        priority: Priority,
+    ) {
+        let shard = SHARD.with(|shard| shard % self.shards.len());
+        let priority = priority as usize;
+        {
+            let mut queues = self.shards[shard].lock();
+            queues[priority].push_back(Box::new(job));
+            self.shards[shard].queued[priority].fetch_add(1, Ordering::Relaxed);
+        }
+        if self.revive(shard) {
+            return;
+        }
+        // Only changes as workers go to sleep or wake up, so is rarely
+        // written to while the pool is busy
+        if self.sleeping.load(Ordering::SeqCst) > 0 {
+            // Lock so the notification can't happen between a worker
+            // checking for jobs and it going to sleep
+            let _sleep = lock(&self.sleep);
+            self.wake.notify_one();
+        }
+    }
+
+    /// The number of workers running
+    pub(crate) fn workers(&self) -> usize {
+        self.live
+            .iter()
+            .filter(|live| live.load(Ordering::Relaxed))
+            .count()
+    }
+
+    /// The number of jobs waiting for a worker
+    pub(crate) fn queue_len(&self) -> usize {
+        self.shards
+            .iter()
+            .flat_map(|shard| &shard.queued)
+            .map(|queued| queued.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Makes sure the workers of the first `workers` shards are running
+    pub(crate) fn warm_up(self: &Arc<Self>, workers: usize) {
+        for shard in 0..workers.min(self.shards.len()) {
+            self.revive(shard);
+        }
+    }
+
+    /// Spawns the shard's worker if it isn't running, returning whether it
+    /// did
+    fn revive(self: &Arc<Self>, shard: usize) -> bool {
+        let revived = self.live[shard]
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+        if revived {
+            Worker::spawn(Arc::clone(self), shard);
+        }
+        revived
+    }
+
+    /// Takes the highest priority job from the worker's home shard, or steals
+    /// one from another if they have jobs of a higher priority
+    ///
+    /// Shards are skipped by their counts, which may be out of date, so a job
+    /// can be missed here. [`Worker::sleep`] checks again with the locks held
+    /// before going to sleep
+    fn find_job(&self, home: usize) -> Option<Job> {
+        let shards = self.shards.len();
+        (0..PRIORITIES).find_map(|priority| {
+            (0..shards)
+                .map(|offset| (home + offset) % shards)
+                .find_map(|shard| self.shards[shard].pop(priority))
+        })
+    }
+}
+
+/// Locks a mutex, ignoring poisoning as jobs are never run while a lock is
+/// held
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether a shard has no jobs queued
+fn is_empty(queues: &Queues) -> bool {
+    queues.iter().all(VecDeque::is_empty)
+}
+
+/// A worker thread, which replaces itself if a job panics
+struct Worker {
+    pool: Arc<Pool>,
+    home: usize,
+}
+
+impl Worker {
+    fn spawn(pool: Arc<Pool>, home: usize) {
+        let mut builder =
+            thread::Builder::new().name(format!("{}-{home}", pool.thread_name));
+        if let Some(stack_size) = pool.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        builder
+            .spawn(move || Worker { pool, home }.run())
+            .expect("failed to spawn close_already worker thread");
+    }
+
+    fn run(self) {
+        loop {
+            match self.pool.find_job(self.home) {
+                Some(job) => job(),
+                None if self.sleep() => {},
+                None => return,
+            }
+        }
+    }
+
+    /// Waits until a job might be available, returning false if the worker
+    /// should exit after being idle for too long
+    fn sleep(&self) -> bool {
+        let sleep = lock(&self.pool.sleep);
+        self.pool.sleeping.fetch_add(1, Ordering::SeqCst);
+        // Check again now that submitters know to wake us up
+        let idle = self.pool.shards.iter().all(|shard| is_empty(&shard.lock()));
+        let (sleep, timed_out) = match self.pool.idle_timeout {
+            _ if !idle => (sleep, false),
+            Some(timeout) => {
+                let (sleep, result) = self
+                    .pool
+                    .wake
+                    .wait_timeout(sleep, timeout)
+                    .unwrap_or_else(PoisonError::into_inner);
+                (sleep, result.timed_out())
+            },
+            None => {
+                let sleep = self
+                    .pool
+                    .wake
+                    .wait(sleep)
+                    .unwrap_or_else(PoisonError::into_inner);
+                (sleep, false)
+            },
+        };
+        self.pool.sleeping.fetch_sub(1, Ordering::SeqCst);
+        drop(sleep);
+        !timed_out || !self.retire()
+    }
+
+    /// Marks the worker as no longer running, returning false if it has to
+    /// keep running after all
+    fn retire(&self) -> bool {
+        let live = &self.pool.live[self.home];
+        live.store(false, Ordering::SeqCst);
+        // A job may have been queued on our shard before its submitter could
+        // see we'd stopped, in which case one of us has to run it
+        let stranded = !is_empty(&self.pool.shards[self.home].lock());
+        !(stranded
+            && live
+                .compare_exchange(
+                    false,
+                    true,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok())
+    }
+}
+
+impl Drop for Worker {
+    /// Replaces the worker's thread if it's unwinding due to a job panicking
+    fn drop(&mut self) {
+        if thread::panicking() {
+            Worker::spawn(Arc::clone(&self.pool), self.home);
+        }
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn runs_jobs_from_many_threads() {
+        let pool = Pool::new(PoolConfig::default());
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let sender = sender.clone();
+                        pool.execute(
+                            move || sender.send(()).unwrap(),
+                            Priority::Normal,
+                        );
+                    }
+                });
+            }
+        });
+        drop(sender);
+        assert_eq!(receiver.iter().count(), 800);
+    }
+
+    #[test]
+    fn survives_panicking_job() {
+        let pool = Pool::new(PoolConfig::default());
+        let threads = pool.shards.len();
+        for _ in 0..threads {
+            pool.execute(|| panic!("expected panic"), Priority::Normal);
+        }
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send(()).unwrap(), Priority::Normal);
+        receiver.recv().expect("job should run after panics");
+    }
+
+    #[test]
+    fn uses_config() {
+        let config = PoolConfig::default()
+            .threads(NonZeroUsize::new(2).unwrap())
+            .thread_name("test-closer")
+            .stack_size(256 * 1024);
+        let pool = Pool::new(config);
+        assert_eq!(pool.shards.len(), 2);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(
+            move || {
+                let name = thread::current().name().map(String::from);
+                sender.send(name).unwrap();
+            },
+            Priority::Normal,
+        );
+        let name = receiver.recv().unwrap().unwrap();
+        assert!(name.starts_with("test-closer-"), "unexpected name {name}");
+    }
+
+    #[test]
+    fn respawns_idle_workers() {
+        let config = PoolConfig::default()
+            .threads(NonZeroUsize::new(1).unwrap())
+            .idle_timeout(Duration::from_millis(10));
+        let pool = Pool::new(config);
+        while pool.live[0].load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send(()).unwrap(), Priority::Normal);
+        receiver.recv().expect("worker should be respawned");
+    }
+
+    #[test]
+    fn warm_up_respawns_idle_workers() {
+        let config = PoolConfig::default()
+            .threads(NonZeroUsize::new(2).unwrap())
+            .idle_timeout(Duration::from_millis(10));
+        let pool = Pool::new(config);
+        while pool.live.iter().any(|live| live.load(Ordering::SeqCst)) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        pool.warm_up(1);
+        assert!(pool.live[0].load(Ordering::SeqCst));
+        assert!(!pool.live[1].load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn runs_higher_priority_jobs_first() {
+        let pool = Pool::new(PoolConfig::default().threads(NonZeroUsize::MIN));
+        let (started, wait_started) = mpsc::channel();
+        let (release, wait_release) = mpsc::channel::<()>();
+        pool.execute(
+            move || {
+                started.send(()).unwrap();
+                wait_release.recv().unwrap();
+            },
+            Priority::Normal,
+        );
+        // Queue behind the running job, so they're all waiting at once
+        wait_started.recv().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        for priority in [Priority::Low, Priority::Normal, Priority::High] {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(priority).unwrap(), priority);
+        }
+        drop(sender);
+        release.send(()).unwrap();
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), [
+            Priority::High,
+            Priority::Normal,
+            Priority::Low
+        ]);
+    }
+}
@@ -0,0 +1,23 @@
+//! Re-exports of the commonly used parts of `close_already`
+//!
+//! `FastCloseAsyncExt` isn't included, as its methods would be ambiguous
+//! with `std::io::Write`'s when both are imported
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use std::{fs::OpenOptions, io::Write};
+//!
+//! use close_already::prelude::*;
+//!
+//! let mut out = create_fast("out.txt")?;
+//! out.write_all(b"Hello, world!")?;
+//! let log = OpenOptions::new().append(true).open_fast("log.txt")?;
+//! let input = open_fast("in.txt")?;
+//! # drop((log, input));
+//! # Ok(())
+//! # }
+//! ```
+pub use crate::{
+    fs::{create_fast, open_fast, OpenOptionsExt},
+    FastClose, FastCloseable,
+};
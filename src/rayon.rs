@@ -0,0 +1,93 @@
+//! Configuration for the `rayon` backend
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+use ::rayon::ThreadPool;
+
+/// The thread pool set by [`set_thread_pool`], if any
+pub(crate) static THREAD_POOL: OnceLock<Arc<ThreadPool>> = OnceLock::new();
+
+/// Whether closures are spawned in FIFO order, set by [`set_fifo`]
+static FIFO: AtomicBool = AtomicBool::new(false);
+
+/// Sets a dedicated thread pool that handle closures are spawned onto
+///
+/// By default, closures are spawned onto `rayon`'s global thread pool, where
+/// they compete with the rest of your program's parallel work. Use this to
+/// give them a [`ThreadPool`] of their own
+///
+/// This only affects handles dropped after it is called
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// let pool = rayon::ThreadPoolBuilder::new()
+///     .num_threads(2)
+///     .thread_name(|n| format!("closer-{n}"))
+///     .build()
+///     .unwrap();
+/// close_already::rayon::set_thread_pool(Arc::new(pool)).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Only one thread pool can be set. If one has already been set, `pool` is
+/// returned back
+pub fn set_thread_pool(pool: Arc<ThreadPool>) -> Result<(), Arc<ThreadPool>> {
+    THREAD_POOL.set(pool)
+}
+
+/// Sets whether handle closures are spawned in first-in, first-out order
+///
+/// By default, closures are spawned with [`rayon::spawn`](::rayon::spawn),
+/// which runs the most recently spawned jobs on a thread first, so handles
+/// dropped earlier can stay open for a while when many are dropped at once.
+/// Enabling this uses [`rayon::spawn_fifo`](::rayon::spawn_fifo) instead, so
+/// handles are closed roughly in the order they were dropped
+///
+/// This can be changed at any time, and affects handles dropped afterwards
+pub fn set_fifo(fifo: bool) {
+    FIFO.store(fifo, Ordering::Relaxed);
+}
+
+/// Whether closures should be spawned in FIFO order (see [`set_fifo`])
+pub(crate) fn fifo() -> bool {
+    FIFO.load(Ordering::Relaxed)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use ::rayon::ThreadPoolBuilder;
+
+    use super::*;
+
+    #[test]
+    fn spawns_onto_set_thread_pool() {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(1)
+            .thread_name(|_| String::from("test-closer"))
+            .build()
+            .unwrap();
+        set_thread_pool(Arc::new(pool)).unwrap();
+        set_fifo(true);
+
+        /// Sends the name of the thread it's dropped on
+        struct Handle(mpsc::Sender<Option<String>>);
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                let name = thread::current().name().map(String::from);
+                self.0.send(name).unwrap();
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        crate::backend::submit_rayon(Handle(sender)).unwrap();
+        assert_eq!(receiver.recv().unwrap().as_deref(), Some("test-closer"));
+        set_fifo(false);
+    }
+}
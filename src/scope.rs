@@ -0,0 +1,148 @@
+//! Waiting for the handles dropped within a section of code to be closed
+use std::{cell::RefCell, fmt, sync::Arc};
+
+use crate::group::Outstanding;
+
+thread_local! {
+    /// The scopes the current thread has entered, innermost last
+    static CURRENT: RefCell<Vec<Arc<Outstanding>>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f`, then blocks until every [`FastClose`](crate::FastClose) it
+/// dropped has been closed by the backend
+///
+/// Like [`std::thread::scope`], this lets the code inside it carry on
+/// without waiting for its handles to be closed, while guaranteeing they've
+/// all been released once it returns (e.g. before handing off or removing the
+/// directory they were in). Handles dropped by the current thread are
+/// tracked. To also track handles dropped by other threads, run their code
+/// inside [`Scope::enter`]. Scopes can be nested, in which case handles are
+/// tracked by the innermost one
+///
+/// If `f` panics, the handles it dropped are still waited for before the
+/// panic continues
+///
+/// When using an async backend, don't call this from a task on the same
+/// runtime, as it may prevent the closures from running
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::thread;
+///
+/// use close_already::fs;
+///
+/// close_already::scope(|s| {
+///     thread::scope(|threads| {
+///         for n in 0..4 {
+///             threads.spawn(move || {
+///                 s.enter(|| fs::write(format!("out/{n}"), "hi"))
+///             });
+///         }
+///     });
+///     fs::write("out/main", "hi")
+/// })?;
+/// // Every file written above has been closed
+/// std::fs::rename("out", "done")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn scope<R>(f: impl FnOnce(&Scope) -> R) -> R {
+    let scope = Scope {
+        outstanding: Arc::default(),
+    };
+    let _wait = WaitOnDrop(&scope);
+    scope.enter(|| f(&scope))
+}
+
+/// A scope in which dropped handles are tracked, created by [`scope`]
+pub struct Scope {
+    outstanding: Arc<Outstanding>,
+}
+
+impl Scope {
+    /// Runs `f` on the current thread, tracking the handles it drops as part
+    /// of this scope
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        CURRENT.with_borrow_mut(|current| {
+            current.push(Arc::clone(&self.outstanding));
+        });
+        let _exit = Exit;
+        f()
+    }
+
+    /// The number of handles dropped within the scope that haven't been
+    /// closed yet
+    pub fn pending(&self) -> usize {
+        self.outstanding.count()
+    }
+}
+
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+/// Leaves the current thread's innermost scope when dropped, even if the
+/// code inside it panicked
+struct Exit;
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        CURRENT.with_borrow_mut(|current| current.pop());
+    }
+}
+
+/// Waits for a scope's handles when dropped, even if the code inside it
+/// panicked
+struct WaitOnDrop<'a>(&'a Scope);
+
+impl Drop for WaitOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.outstanding.wait();
+    }
+}
+
+/// The innermost scope the current thread has entered, if any
+pub(crate) fn current() -> Option<Arc<Outstanding>> {
+    CURRENT.with_borrow(|current| current.last().cloned())
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    // Tokio's tasks don't run while the test blocks its only thread
+    #[test]
+    #[cfg(not(feature = "backend-tokio"))]
+    fn waits_for_dropped_handles() {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            thread,
+            time::Duration,
+        };
+
+        use super::{current, scope};
+
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        struct Handle;
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                thread::sleep(Duration::from_millis(10));
+                CLOSED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        impl crate::FastCloseable for Handle {}
+
+        scope(|_| {
+            crate::backend::close(Handle);
+            assert!(current().is_some(), "scope should be entered");
+        });
+        assert!(current().is_none(), "scope should be exited");
+        assert!(CLOSED.load(Ordering::SeqCst), "handle should be closed");
+    }
+}
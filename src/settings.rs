@@ -0,0 +1,296 @@
+//! A [`FastClose`] with settings for how it's closed
+use std::{
+    borrow::Cow,
+    fmt, io,
+    io::SeekFrom,
+    mem::{self, ManuallyDrop},
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use crate::{Closer, FastClose, FastCloseable, Priority};
+
+/// A fast-closing file handle with settings for how it's closed, such as
+/// its [`Priority`]
+///
+/// [`FastClose`] is kept the same size as the handle it wraps, so it has
+/// nowhere to store per-handle settings. This holds them alongside it
+/// instead, and applies them when dropped. Create one with
+/// [`FastClose::eager`], [`FastClose::with_closer`],
+/// [`FastClose::with_priority`], or [`FastClose::with_label`], or from an
+/// existing `FastClose` with [`From`]
+///
+/// Note: on non-Windows targets, handles are always closed inline, so only
+/// the label is used, by the `audit` table
+pub struct FastCloseWith<H: FastCloseable + 'static> {
+    fast_close: ManuallyDrop<FastClose<H>>,
+    settings: Settings,
+}
+
+/// How a [`FastCloseWith`]'s handle is closed
+#[derive(Default)]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) struct Settings {
+    /// Whether the handle should be closed inline on drop
+    pub(crate) eager: bool,
+    /// The closer to submit the handle to, instead of the global one
+    pub(crate) closer: Option<Arc<dyn Closer>>,
+    /// How urgently the handle should be closed
+    pub(crate) priority: Priority,
+    /// The label to identify the handle by in reports about it
+    pub(crate) label: Option<Cow<'static, str>>,
+}
+
+impl<H: FastCloseable> FastClose<H> {
+    /// Creates a file handle that will be closed inline when dropped, like an
+    /// unwrapped handle would be
+    ///
+    /// This is for the few handles whose closure must have completed before
+    /// the next statement (e.g. before removing the directory they're in).
+    /// See also [`set_eager`](FastCloseWith::set_eager)
+    #[track_caller]
+    pub fn eager(handle: H) -> FastCloseWith<H> {
+        let mut fast_close = FastCloseWith::new(handle);
+        fast_close.set_eager(true);
+        fast_close
+    }
+
+    /// Creates a new fast-closing file handle that will be submitted to
+    /// `closer` when dropped, instead of the global closer or backend
+    ///
+    /// Use this to send different handles to different executors or thread
+    /// pools, for example to keep the closures of low-priority files from
+    /// holding up important ones
+    #[track_caller]
+    pub fn with_closer(handle: H, closer: Arc<dyn Closer>) -> FastCloseWith<H> {
+        let mut fast_close = FastCloseWith::new(handle);
+        fast_close.set_closer(closer);
+        fast_close
+    }
+
+    /// Creates a new fast-closing file handle that will be closed ahead of,
+    /// or behind, others waiting to be closed when dropped (see
+    /// [`Priority`])
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use std::{fs::File, io::Write};
+    ///
+    /// use close_already::{FastClose, Priority};
+    ///
+    /// let mut file =
+    ///     FastClose::with_priority(File::create("out.tmp")?, Priority::High);
+    /// file.write_all(b"Hello, world!")?;
+    /// drop(file);
+    /// // Closed before other handles, so the rename isn't held up for long
+    /// close_already::fs::rename("out.tmp", "out.txt")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn with_priority(handle: H, priority: Priority) -> FastCloseWith<H> {
+        let mut fast_close = FastCloseWith::new(handle);
+        fast_close.set_priority(priority);
+        fast_close
+    }
+
+    /// Creates a new fast-closing file handle with a label, such as the path
+    /// it was opened from, to identify it by
+    ///
+    /// The label is included in [`CloseError`](crate::CloseError)s,
+    /// [`ClosePanic`](crate::ClosePanic)s, the `events` stream, and `tracing`
+    /// spans for the handle, and identifies it in the `audit` table. With the
+    /// `audit` or `diagnostics` features enabled, handles opened by the
+    /// [`fs`](crate::fs) module are labeled with their path automatically
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use std::fs::File;
+    ///
+    /// use close_already::FastClose;
+    ///
+    /// let file = FastClose::with_label(File::open("data.bin")?, "dataset");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn with_label(
+        handle: H,
+        label: impl Into<Cow<'static, str>>,
+    ) -> FastCloseWith<H> {
+        let mut fast_close = FastCloseWith::new(handle);
+        fast_close.set_label(label);
+        fast_close
+    }
+}
+
+impl<H: FastCloseable> FastCloseWith<H> {
+    /// Creates a new fast-closing file handle, with the default settings
+    #[track_caller]
+    pub fn new(handle: H) -> Self {
+        FastClose::new(handle).into()
+    }
+}
+
+impl<H: FastCloseable + 'static> FastCloseWith<H> {
+    /// Sets whether the handle will be closed inline when dropped,
+    /// instead of being sent to the backend
+    pub fn set_eager(&mut self, eager: bool) {
+        self.settings.eager = eager;
+    }
+
+    /// Sets the closer the handle will be submitted to when dropped,
+    /// instead of the global one
+    pub fn set_closer(&mut self, closer: Arc<dyn Closer>) {
+        self.settings.closer = Some(closer);
+    }
+
+    /// Sets how urgently the handle will be closed when dropped,
+    /// relative to others waiting to be closed
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.settings.priority = priority;
+    }
+
+    /// Sets the label the handle is identified by in errors, events, and
+    /// other reports about its closure
+    pub fn set_label(&mut self, label: impl Into<Cow<'static, str>>) {
+        let label = label.into();
+        self.fast_close.note_label(label.clone());
+        self.settings.label = Some(label);
+    }
+
+    /// Gets back the [`FastClose`], without its settings
+    pub fn into_fast_close(self) -> FastClose<H> {
+        let mut wrapped = ManuallyDrop::new(self);
+        // The settings would otherwise be leaked with the wrapper
+        wrapped.settings = Settings::default();
+        // SAFETY: the destructor has been prevented from running, so the
+        // handle is only moved out of self once, here
+        unsafe { ManuallyDrop::take(&mut wrapped.fast_close) }
+    }
+
+    /// Gets back the inner file type
+    ///
+    /// See [`FastClose::into_inner`]
+    pub fn into_inner(self) -> H {
+        self.into_fast_close().into_inner()
+    }
+}
+
+impl<H: FastCloseable + 'static> Drop for FastCloseWith<H> {
+    /// Submits the file handle to be closed, as set by its settings
+    fn drop(&mut self) {
+        // SAFETY: we're in Drop, so self.fast_close won't be accessed again
+        let fast_close = unsafe { ManuallyDrop::take(&mut self.fast_close) };
+        fast_close.submit_with(mem::take(&mut self.settings));
+    }
+}
+
+impl<H: FastCloseable + 'static> From<FastClose<H>> for FastCloseWith<H> {
+    fn from(fast_close: FastClose<H>) -> Self {
+        FastCloseWith {
+            fast_close: ManuallyDrop::new(fast_close),
+            settings: Settings::default(),
+        }
+    }
+}
+
+impl<H: FastCloseable + 'static> Deref for FastCloseWith<H> {
+    type Target = H;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fast_close
+    }
+}
+
+impl<H: FastCloseable + 'static> DerefMut for FastCloseWith<H> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fast_close
+    }
+}
+
+impl<H: fmt::Debug + FastCloseable + 'static> fmt::Debug for FastCloseWith<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("FastCloseWith").field(self.deref()).finish()
+    }
+}
+
+impl<H: io::Read + FastCloseable + 'static> io::Read for FastCloseWith<H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.deref_mut().read(buf)
+    }
+}
+
+impl<H: io::Write + FastCloseable + 'static> io::Write for FastCloseWith<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.deref_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deref_mut().flush()
+    }
+}
+
+impl<H: io::Seek + FastCloseable + 'static> io::Seek for FastCloseWith<H> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.deref_mut().seek(pos)
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{
+        fs::File,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn with_closer() {
+        struct Counting(AtomicUsize);
+
+        impl Closer for Counting {
+            fn submit(&self, job: Box<dyn FnOnce() + Send>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                job();
+            }
+        }
+
+        in_runtime(|| {
+            let closer = Arc::new(Counting(AtomicUsize::new(0)));
+            let file = File::open("Cargo.toml").unwrap();
+            drop(FastClose::with_closer(file, closer.clone()));
+            let expected = if cfg!(windows) { 1 } else { 0 };
+            assert_eq!(closer.0.load(Ordering::SeqCst), expected);
+
+            // Settings are left behind when taking the FastClose back out
+            let file = File::open("Cargo.toml").unwrap();
+            let file = FastClose::with_closer(file, closer.clone());
+            drop(file.into_fast_close());
+            assert_eq!(closer.0.load(Ordering::SeqCst), expected);
+            assert_eq!(Arc::strong_count(&closer), 1);
+        });
+    }
+
+    #[test]
+    fn priority_drop() {
+        in_runtime(|| {
+            let mut file = FastClose::with_priority(
+                File::open("Cargo.toml").unwrap(),
+                Priority::Low,
+            );
+            file.set_priority(Priority::High);
+            drop(file);
+        });
+    }
+
+    #[test]
+    fn eager_drop() {
+        let mut file = FastClose::eager(File::open("Cargo.toml").unwrap());
+        file.set_eager(false);
+        file.set_eager(true);
+        drop(file);
+    }
+}
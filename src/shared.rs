@@ -0,0 +1,163 @@
+//! A reference-counted [`FastClose`], for handles shared between owners
+use std::{fmt, io, io::SeekFrom, ops::Deref, sync::Arc};
+
+use crate::{FastClose, FastCloseable};
+
+/// A shared, fast-closing file handle, which is submitted to the backend once
+/// the last clone of it is dropped
+///
+/// This is an [`Arc`] around a [`FastClose`], which is the correct way round
+/// to share a handle (an `Arc` inside a `FastClose` would only hand the
+/// reference count to the backend). Unlike a plain `Arc<FastClose<H>>`, the
+/// handle can be taken back out with [`try_unwrap`](FastCloseArc::try_unwrap)
+/// by whichever owner finishes last, to flush it or check it closed cleanly
+/// before letting go of it
+///
+/// Files can be read from, written to, and seeked through any clone, as
+/// `File` supports IO through a shared reference
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use std::{fs::File, io::Write, thread};
+///
+/// use close_already::FastClose;
+///
+/// let log = FastClose::new(File::create("log.txt")?).shared();
+/// let workers = (0..4)
+///     .map(|n| {
+///         let mut log = log.clone();
+///         thread::spawn(move || writeln!(log, "hello from {n}"))
+///     })
+///     .collect::<Vec<_>>();
+/// for worker in workers {
+///     worker.join().unwrap()?;
+/// }
+/// assert_eq!(log.strong_count(), 1);
+/// // Only the last clone closes the file
+/// log.try_unwrap().unwrap().close()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FastCloseArc<H: FastCloseable + 'static>(Arc<FastClose<H>>);
+
+impl<H: FastCloseable> FastCloseArc<H> {
+    /// Creates a new shared, fast-closing file handle
+    pub fn new(handle: H) -> Self {
+        FastCloseArc(Arc::new(FastClose::new(handle)))
+    }
+}
+
+impl<H: FastCloseable + 'static> FastCloseArc<H> {
+    /// The number of clones of this handle, including itself
+    ///
+    /// See [`Arc::strong_count`]
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Gets back the [`FastClose`] if this is the only clone, otherwise
+    /// returns `self` unchanged
+    ///
+    /// See [`Arc::try_unwrap`]
+    pub fn try_unwrap(self) -> Result<FastClose<H>, Self> {
+        Arc::try_unwrap(self.0).map_err(FastCloseArc)
+    }
+}
+
+impl<H: FastCloseable + 'static> FastClose<H> {
+    /// Moves the handle into a [`FastCloseArc`], so it can be shared between
+    /// owners, and is only closed once they've all dropped it
+    pub fn shared(self) -> FastCloseArc<H> {
+        FastCloseArc(Arc::new(self))
+    }
+}
+
+impl<H: FastCloseable + 'static> Clone for FastCloseArc<H> {
+    fn clone(&self) -> Self {
+        FastCloseArc(Arc::clone(&self.0))
+    }
+}
+
+impl<H: FastCloseable + 'static> Deref for FastCloseArc<H> {
+    type Target = H;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<H: FastCloseable + 'static> From<FastClose<H>> for FastCloseArc<H> {
+    fn from(fast_close: FastClose<H>) -> Self {
+        fast_close.shared()
+    }
+}
+
+impl<H: fmt::Debug + FastCloseable + 'static> fmt::Debug for FastCloseArc<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("FastCloseArc").field(self.deref()).finish()
+    }
+}
+
+impl<H> io::Read for FastCloseArc<H>
+where
+    H: FastCloseable + 'static,
+    for<'a> &'a H: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut handle: &H = self;
+        handle.read(buf)
+    }
+}
+
+impl<H> io::Write for FastCloseArc<H>
+where
+    H: FastCloseable + 'static,
+    for<'a> &'a H: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut handle: &H = self;
+        handle.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut handle: &H = self;
+        handle.flush()
+    }
+}
+
+impl<H> io::Seek for FastCloseArc<H>
+where
+    H: FastCloseable + 'static,
+    for<'a> &'a H: io::Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut handle: &H = self;
+        handle.seek(pos)
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::io::{Read, Seek, Write};
+
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn closes_with_last_clone() {
+        in_runtime(|| {
+            let file = FastClose::new(tempfile::tempfile().unwrap()).shared();
+            let mut writer = file.clone();
+            assert_eq!(file.strong_count(), 2);
+            writer.write_all(b"hello").unwrap();
+
+            let file = file.try_unwrap().unwrap_err();
+            drop(writer);
+            let mut file = FastCloseArc::from(file.try_unwrap().unwrap());
+            file.rewind().unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "hello");
+        });
+    }
+}
@@ -0,0 +1,88 @@
+//! Configuration for the `smol` backend
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+use ::smol::Executor;
+
+/// The executor set by [`set_executor`], if any
+pub(crate) static EXECUTOR: OnceLock<Arc<Executor<'static>>> = OnceLock::new();
+
+/// Whether closures are spawned as tasks, set by [`set_spawn`]
+static SPAWN: AtomicBool = AtomicBool::new(false);
+
+/// Sets the executor that handle closures are spawned onto as tasks
+///
+/// Use this if your application runs its own [`Executor`]s instead of
+/// `smol`'s global one. Setting an executor also enables spawning closures
+/// as tasks (see [`set_spawn`]), instead of running them on `smol`'s
+/// blocking thread pool. You're responsible for running the executor;
+/// handles won't be closed if it isn't being run
+///
+/// This only affects handles dropped after it is called
+///
+/// # Errors
+///
+/// Only one executor can be set. If one has already been set, `executor` is
+/// returned back
+pub fn set_executor(
+    executor: Arc<Executor<'static>>,
+) -> Result<(), Arc<Executor<'static>>> {
+    EXECUTOR.set(executor)?;
+    set_spawn(true);
+    Ok(())
+}
+
+/// Sets whether handle closures are spawned as tasks onto an executor,
+/// rather than run on `smol`'s blocking thread pool
+///
+/// By default, closures are run with [`unblock`](::smol::unblock), as
+/// closing a handle blocks, and slow closes could otherwise hold up the
+/// executor's other tasks. Spawning them as tasks avoids starting threads
+/// for the blocking pool, which may suit executors that already run on a
+/// thread pool of their own. Tasks are spawned onto `smol`'s global
+/// executor, unless one has been given to [`set_executor`]
+///
+/// This can be changed at any time, and affects handles dropped afterwards
+pub fn set_spawn(spawn: bool) {
+    SPAWN.store(spawn, Ordering::Relaxed);
+}
+
+/// Whether closures should be spawned as tasks (see [`set_spawn`])
+pub(crate) fn spawn() -> bool {
+    SPAWN.load(Ordering::Relaxed)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn unblocks_or_spawns_on_set_executor() {
+        // The channel disconnects once the sender is dropped
+        let (sender, receiver) = mpsc::channel::<()>();
+        crate::backend::submit_smol(sender).unwrap();
+        assert!(receiver.recv().is_err(), "should close without executor");
+
+        let executor = Arc::new(Executor::new());
+        set_executor(Arc::clone(&executor)).unwrap();
+        assert!(spawn(), "setting an executor should enable spawning");
+        let (sender, receiver) = mpsc::channel::<()>();
+        crate::backend::submit_smol(sender).unwrap();
+        assert_eq!(
+            receiver.try_recv(),
+            Err(mpsc::TryRecvError::Empty),
+            "executor hasn't run yet"
+        );
+        while executor.try_tick() {}
+        assert_eq!(
+            receiver.try_recv(),
+            Err(mpsc::TryRecvError::Disconnected),
+            "executor should close handle"
+        );
+        set_spawn(false);
+    }
+}
@@ -0,0 +1,130 @@
+//! Configuration for the `spawn` backend
+//!
+//! This backend routes handle closures through any executor implementing
+//! [`Spawn`] or [`LocalSpawn`] from the `futures` ecosystem, for executors
+//! that `close_already` doesn't have a dedicated backend for. Install one
+//! with [`set_spawner`] (and/or [`set_local_spawner`]) at startup. Until a
+//! spawner is installed, handles are closed inline as if `close_already`
+//! wasn't being used
+use std::{cell::RefCell, sync::OnceLock};
+
+use futures_task::{LocalSpawn, Spawn};
+
+/// The spawner set by [`set_spawner`], if any
+pub(crate) static SPAWNER: OnceLock<Box<dyn Spawn + Send + Sync>> =
+    OnceLock::new();
+
+thread_local! {
+    /// The spawner set by [`set_local_spawner`] on this thread, if any
+    pub(crate) static LOCAL_SPAWNER: RefCell<Option<Box<dyn LocalSpawn>>> =
+        const { RefCell::new(None) };
+}
+
+/// Sets the spawner that handle closures are submitted to
+///
+/// This only affects handles dropped after it is called
+///
+/// For example, to reuse a `futures` [`ThreadPool`] your application already
+/// has:
+///
+/// ```
+/// use futures_executor::ThreadPool;
+///
+/// let pool = ThreadPool::new()?;
+/// close_already::spawn::set_spawner(pool.clone()).unwrap();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// [`ThreadPool`]: https://docs.rs/futures/latest/futures/executor/struct.ThreadPool.html
+///
+/// # Errors
+///
+/// Only one spawner can be set. If one has already been set, `spawner` is
+/// returned back
+pub fn set_spawner<S>(spawner: S) -> Result<(), S>
+where
+    S: Spawn + Send + Sync + 'static,
+{
+    let mut spawner = Some(spawner);
+    SPAWNER.get_or_init(|| Box::new(spawner.take().unwrap()));
+    match spawner {
+        None => Ok(()),
+        Some(spawner) => Err(spawner),
+    }
+}
+
+/// Sets the spawner that handles dropped on the current thread are submitted
+/// to, replacing any previously set for this thread
+///
+/// This takes priority over the spawner set by [`set_spawner`], so it can be
+/// used to keep handle closures on a thread's own executor (e.g. a
+/// `LocalPool`)
+pub fn set_local_spawner<S>(spawner: S)
+where
+    S: LocalSpawn + 'static,
+{
+    LOCAL_SPAWNER.with_borrow_mut(|local| *local = Some(Box::new(spawner)));
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Waker},
+    };
+
+    use futures_task::{FutureObj, LocalFutureObj, SpawnError};
+
+    use super::*;
+
+    static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Handle;
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            CLOSED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Polls spawned futures once, immediately
+    struct ImmediateSpawner;
+
+    impl Spawn for ImmediateSpawner {
+        fn spawn_obj(
+            &self,
+            future: FutureObj<'static, ()>,
+        ) -> Result<(), SpawnError> {
+            let _ = pin!(future).poll(&mut Context::from_waker(Waker::noop()));
+            Ok(())
+        }
+    }
+
+    impl LocalSpawn for ImmediateSpawner {
+        fn spawn_local_obj(
+            &self,
+            future: LocalFutureObj<'static, ()>,
+        ) -> Result<(), SpawnError> {
+            let _ = pin!(future).poll(&mut Context::from_waker(Waker::noop()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spawners_close_handles() {
+        assert!(set_spawner(ImmediateSpawner).is_ok());
+        assert!(set_spawner(ImmediateSpawner).is_err(), "can only set once");
+        crate::backend::submit_spawn(Handle).unwrap();
+        assert_eq!(CLOSED.load(Ordering::SeqCst), 1);
+
+        std::thread::spawn(|| {
+            set_local_spawner(ImmediateSpawner);
+            crate::backend::submit_spawn(Handle).unwrap();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(CLOSED.load(Ordering::SeqCst), 2);
+    }
+}
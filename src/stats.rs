@@ -0,0 +1,184 @@
+//! Lightweight statistics about the handles closed in the background
+//!
+//! Every backend and [`Closer`](crate::Closer) updates these counters, which
+//! are plain atomics, so they're always available without a metrics stack.
+//! Services can poll [`stats`] to watch for handles accumulating
+//!
+//! ```no_run
+//! let stats = close_already::stats();
+//! if stats.in_flight > 10_000 {
+//!     eprintln!("{} handles waiting to be closed", stats.in_flight);
+//! }
+//! ```
+//!
+//! With the `histogram` feature enabled, how long each handle took to close
+//! is also recorded, and can be retrieved with `close_durations`
+//!
+//! To collect everything at once, e.g. to report periodically, take a
+//! [`snapshot`], which can be serialized with the `serde` feature enabled
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+#[cfg(feature = "histogram")]
+pub(crate) use histogram::record as record_duration;
+#[cfg(feature = "histogram")]
+pub use histogram::{
+    close_durations, reset_close_durations, Bucket, Histogram,
+};
+
+#[cfg(feature = "histogram")]
+mod histogram;
+
+/// The number of handles submitted to be closed in the background
+static SUBMITTED: AtomicU64 = AtomicU64::new(0);
+
+/// The number of handles closed in the background
+static COMPLETED: AtomicU64 = AtomicU64::new(0);
+
+/// The number of errors and panics reported
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts of the handles closed in the background since the program
+/// started, returned by [`stats`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of handles submitted to be closed in the background
+    pub submitted: u64,
+    /// The number of handles that have been closed in the background
+    pub completed: u64,
+    /// The number of handles submitted that haven't been closed yet (see
+    /// [`pending`](crate::pending()))
+    pub in_flight: u64,
+    /// The number of errors and panics encountered in the background (see
+    /// [`set_error_sink`](crate::set_error_sink) and
+    /// [`set_panic_handler`](crate::set_panic_handler))
+    pub errors: u64,
+}
+
+/// Gets the current counts of handles closed in the background
+///
+/// Handles closed inline (e.g. on non-Windows platforms) aren't counted.
+/// The counts are read one at a time while other threads may be updating
+/// them, so they're only approximately consistent with each other
+pub fn stats() -> Stats {
+    Stats {
+        submitted: SUBMITTED.load(Ordering::Relaxed),
+        completed: COMPLETED.load(Ordering::Relaxed),
+        in_flight: crate::pending::count() as u64,
+        errors: ERRORS.load(Ordering::Relaxed),
+    }
+}
+
+/// Details of the backend's threads and queue, as part of a
+/// [`StatsSnapshot`]
+///
+/// These are only available for backends that manage their own threads,
+/// rather than using an executor's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct BackendStats {
+    /// The [active](crate::Backend::active) backend's
+    /// [name](crate::Backend::name). Handles submitted to a global
+    /// [`Closer`](crate::Closer) don't go through it
+    pub name: &'static str,
+    /// The number of threads the backend is running
+    pub threads: Option<usize>,
+    /// The number of handles waiting in the backend's queue for a thread
+    pub queued: Option<usize>,
+}
+
+/// Everything known about the handles closed in the background, at one
+/// point in time, returned by [`snapshot`]
+///
+/// With the `serde` feature enabled, this implements `serde::Serialize`, so
+/// it can be sent straight to a telemetry pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct StatsSnapshot {
+    /// When the snapshot was taken
+    pub taken: SystemTime,
+    /// The counts of handles closed in the background (see [`stats`])
+    pub counts: Stats,
+    /// The non-empty buckets of the histogram of how long handles took to
+    /// close, shortest first (see `close_durations`)
+    ///
+    /// Requires the `histogram` feature
+    #[cfg(feature = "histogram")]
+    pub close_durations: Vec<Bucket>,
+    /// Details of the active backend
+    pub backend: BackendStats,
+}
+
+/// Takes a snapshot of all the statistics available
+///
+/// Like [`stats`], each figure is read separately, so they're only
+/// approximately consistent with each other
+///
+/// ```no_run
+/// let snapshot = close_already::stats::snapshot();
+/// println!(
+///     "{} handles waiting to be closed by {}",
+///     snapshot.counts.in_flight, snapshot.backend.name,
+/// );
+/// ```
+pub fn snapshot() -> StatsSnapshot {
+    StatsSnapshot {
+        taken: SystemTime::now(),
+        counts: stats(),
+        #[cfg(feature = "histogram")]
+        close_durations: close_durations().buckets().collect(),
+        backend: crate::backend::stats(),
+    }
+}
+
+/// Counts a handle as submitted
+#[inline]
+pub(crate) fn submitted() {
+    SUBMITTED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a handle as closed
+#[inline]
+pub(crate) fn completed() {
+    COMPLETED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts an error or panic
+#[inline]
+pub(crate) fn error() {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+    use crate::fs::tests::in_runtime;
+
+    #[test]
+    fn counts_closes() {
+        let before = stats();
+        in_runtime(|| crate::backend::close(Vec::<std::fs::File>::new()));
+        crate::drain();
+        let after = stats();
+        assert!(after.submitted > before.submitted);
+        assert!(after.completed > before.completed);
+    }
+
+    #[test]
+    fn snapshot_describes_backend() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.backend.name, crate::Backend::active().name());
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&snapshot).unwrap();
+            assert_eq!(json["backend"]["name"], snapshot.backend.name);
+            assert!(json["counts"]["in_flight"].is_u64());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! A histogram of how long handles took to close in the background
+use std::{
+    array,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// How many bits of precision each bucket has. Each power of two is split
+/// into this many linear buckets, so every bucket is within 12.5% of the
+/// durations in it
+const SUB_BITS: u32 = 3;
+
+/// The number of linear buckets per power of two
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+
+/// Enough buckets for any number of nanoseconds that fits in a `u64`
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+/// The number of closures in each bucket
+static COUNTS: [AtomicU64; BUCKETS] = [const { AtomicU64::new(0) }; BUCKETS];
+
+/// The total time spent closing handles, in nanoseconds
+static TOTAL_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// How long handles took to close in the background, returned by
+/// [`close_durations`]
+///
+/// Durations are recorded in buckets with a precision of 3 significant bits
+/// (within 12.5%), like an HDR histogram, from nanoseconds up to centuries
+///
+/// ```no_run
+/// let durations = close_already::stats::close_durations();
+/// println!(
+///     "{} closes, taking {:?} on average, {:?} at the 99th percentile",
+///     durations.count(),
+///     durations.mean(),
+///     durations.percentile(99.0),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Box<[u64; BUCKETS]>,
+    total: Duration,
+}
+
+/// A range of durations in a [`Histogram`], and how many closures took that
+/// long
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct Bucket {
+    /// The shortest duration in the bucket
+    pub low: Duration,
+    /// The longest duration in the bucket
+    pub high: Duration,
+    /// The number of closures that took between `low` and `high`
+    pub count: u64,
+}
+
+impl Histogram {
+    /// The number of closures recorded
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The total time spent closing handles
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The average time taken to close a handle, or zero if none have been
+    /// recorded
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => {
+                let nanos = self.total.as_nanos() / u128::from(count);
+                Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+            },
+        }
+    }
+
+    /// The shortest time taken to close a handle (to the precision of the
+    /// buckets), or `None` if none have been recorded
+    pub fn min(&self) -> Option<Duration> {
+        self.buckets().next().map(|bucket| bucket.low)
+    }
+
+    /// The longest time taken to close a handle (to the precision of the
+    /// buckets), or `None` if none have been recorded
+    pub fn max(&self) -> Option<Duration> {
+        self.buckets().last().map(|bucket| bucket.high)
+    }
+
+    /// The time within which `percentile`% of handles were closed, or zero
+    /// if none have been recorded. `percentile` is clamped to between 0 and
+    /// 100
+    ///
+    /// This is the upper bound of the bucket containing the percentile, so
+    /// overestimates by at most 12.5%
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let count = self.count();
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * count as f64)
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+        for bucket in self.buckets() {
+            seen += bucket.count;
+            if seen >= rank {
+                return bucket.high;
+            }
+        }
+        Duration::ZERO
+    }
+
+    /// The buckets that closures have been recorded in, shortest first
+    pub fn buckets(&self) -> impl DoubleEndedIterator<Item = Bucket> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(index, &count)| {
+                let (low, high) = bounds(index);
+                Bucket {
+                    low: Duration::from_nanos(low),
+                    high: Duration::from_nanos(high),
+                    count,
+                }
+            })
+    }
+}
+
+/// Gets a histogram of how long handles have taken to close in the
+/// background, since the program started or it was last
+/// [reset](reset_close_durations)
+///
+/// Every handle closed by the backend or a [`Closer`](crate::Closer) is
+/// timed, which can be compared against the time it takes to close handles
+/// inline to show how much time `close_already` saves
+///
+/// Requires the `histogram` feature
+pub fn close_durations() -> Histogram {
+    Histogram {
+        counts: Box::new(array::from_fn(|index| {
+            COUNTS[index].load(Ordering::Relaxed)
+        })),
+        total: Duration::from_nanos(TOTAL_NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Clears the histogram returned by [`close_durations`]
+///
+/// Requires the `histogram` feature
+pub fn reset_close_durations() {
+    for count in &COUNTS {
+        count.store(0, Ordering::Relaxed);
+    }
+    TOTAL_NANOS.store(0, Ordering::Relaxed);
+}
+
+/// Records how long a handle took to close
+pub(crate) fn record(duration: Duration) {
+    let nanos = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+    COUNTS[index(nanos)].fetch_add(1, Ordering::Relaxed);
+    TOTAL_NANOS.fetch_add(nanos, Ordering::Relaxed);
+}
+
+/// The bucket a number of nanoseconds goes in
+fn index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    // Keep the top SUB_BITS + 1 bits, of which the first is always set
+    let shift = nanos.ilog2() - SUB_BITS;
+    shift as usize * SUB_BUCKETS + (nanos >> shift) as usize
+}
+
+/// The lowest and highest number of nanoseconds in a bucket
+fn bounds(index: usize) -> (u64, u64) {
+    if index < SUB_BUCKETS {
+        return (index as u64, index as u64);
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let top = (index % SUB_BUCKETS + SUB_BUCKETS) as u64;
+    let low = top << shift;
+    let high = low.saturating_add((1 << shift) - 1);
+    (low, high)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_contain_their_values() {
+        let values = (0..64).flat_map(|bit| {
+            let power = 1u64 << bit;
+            [power - 1, power, power + power / 3]
+        });
+        for nanos in values.chain([u64::MAX]) {
+            let (low, high) = bounds(index(nanos));
+            assert!((low..=high).contains(&nanos), "{nanos} not in bucket");
+        }
+        assert_eq!(index(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut counts = Box::new([0; BUCKETS]);
+        counts[index(100)] = 99;
+        counts[index(10_000)] = 1;
+        let histogram = Histogram {
+            counts,
+            total: Duration::from_nanos(99 * 100 + 10_000),
+        };
+        assert_eq!(histogram.count(), 100);
+        assert!(histogram.percentile(50.0) < Duration::from_nanos(113));
+        assert!(histogram.percentile(100.0) >= Duration::from_nanos(10_000));
+        assert_eq!(histogram.min(), Some(Duration::from_nanos(96)));
+    }
+}
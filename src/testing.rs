@@ -0,0 +1,161 @@
+//! Helpers for testing code that uses `close_already`
+//!
+//! Use [`RecordingCloser`] to check that handles are going through
+//! `FastClose`, rather than being closed some other way. To make closes
+//! deterministic without checking them, enable `backend-sync` instead
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use crate::Closer;
+
+/// A [`Closer`] that records every handle submitted to it, then closes it
+/// straight away on the submitting thread
+///
+/// Install a clone of it with
+/// [`set_global_closer`](crate::set_global_closer) (or give it to
+/// individual handles with `FastClose::with_closer`), and keep the original
+/// to check what was submitted. Clones share the same records
+///
+/// ```
+/// use close_already::{fs, testing::RecordingCloser};
+///
+/// let recorder = RecordingCloser::new();
+/// close_already::set_global_closer(recorder.clone()).unwrap();
+///
+/// # let dir = tempfile::tempdir()?;
+/// # let path = dir.path().join("file");
+/// fs::write(&path, "hi")?;
+/// if cfg!(windows) {
+///     assert_eq!(recorder.count(), 1);
+/// }
+/// recorder.reset();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// As only one global closer can be set, install it once for the whole test
+/// binary (e.g. in a `OnceLock`), and [`reset`](RecordingCloser::reset) it
+/// between tests, which should then run one at a time
+#[derive(Clone, Default)]
+pub struct RecordingCloser(Arc<Mutex<Records>>);
+
+#[derive(Default)]
+struct Records {
+    next_id: u64,
+    records: Vec<Record>,
+}
+
+/// A handle that was submitted to a [`RecordingCloser`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Record {
+    /// The handle's number, counting up from zero in order of submission,
+    /// and not reused after a [`reset`](RecordingCloser::reset)
+    pub id: u64,
+    /// When the handle was submitted
+    pub submitted: SystemTime,
+    /// The handle's label, if it had one (see
+    /// [`FastClose::with_label`](crate::FastClose::with_label))
+    pub label: Option<String>,
+}
+
+impl RecordingCloser {
+    /// Creates a closer with no records
+    pub fn new() -> Self {
+        RecordingCloser::default()
+    }
+
+    /// The handles submitted since this was created, or last reset, in
+    /// order of submission
+    pub fn records(&self) -> Vec<Record> {
+        self.lock().records.clone()
+    }
+
+    /// The number of handles submitted since this was created, or last
+    /// reset
+    pub fn count(&self) -> usize {
+        self.lock().records.len()
+    }
+
+    /// Forgets the handles submitted so far
+    pub fn reset(&self) {
+        self.lock().records.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Records> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Closer for RecordingCloser {
+    fn submit(&self, job: Box<dyn FnOnce() + Send>) {
+        self.submit_labeled(None, job);
+    }
+
+    fn submit_labeled(
+        &self,
+        label: Option<&str>,
+        job: Box<dyn FnOnce() + Send>,
+    ) {
+        let mut records = self.lock();
+        let id = records.next_id;
+        records.next_id += 1;
+        records.records.push(Record {
+            id,
+            submitted: SystemTime::now(),
+            label: label.map(str::to_owned),
+        });
+        drop(records);
+        job();
+    }
+}
+
+impl fmt::Debug for RecordingCloser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingCloser")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::Priority;
+
+    #[test]
+    fn records_and_closes_handles() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        struct Handle;
+
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                CLOSED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        impl crate::FastCloseable for Handle {}
+
+        let recorder = RecordingCloser::new();
+        crate::backend::close_on(
+            Some(&recorder),
+            Priority::Normal,
+            Some("handle".into()),
+            None,
+            Handle,
+        );
+        assert!(CLOSED.load(Ordering::SeqCst), "handle should be closed");
+        assert_eq!(recorder.records()[0].id, 0);
+        assert_eq!(recorder.records()[0].label.as_deref(), Some("handle"));
+
+        recorder.reset();
+        assert_eq!(recorder.count(), 0);
+        recorder.clone().submit(Box::new(|| {}));
+        assert_eq!(recorder.records()[0].id, 1, "ids aren't reused");
+    }
+}
@@ -0,0 +1,63 @@
+//! `tokio` IO utilities that offload closing the handles they're given
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{FastClose, FastCloseable};
+
+/// Copies the entire contents of `reader` into `writer`, then shuts down
+/// `writer` and offloads closing both handles to the backend
+///
+/// This replaces the common pattern of calling [`tokio::io::copy`], shutting
+/// down the writer, and then dropping both handles.
+/// On success, the total number of bytes copied is returned.
+/// The handles are closed in the background regardless of whether the copy
+/// succeeded
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use close_already::{tokio_io::copy_and_close, FastCloseable};
+/// use tokio::fs::File;
+///
+/// let from = File::open("from.txt").await?.fast_close();
+/// let to = File::create("to.txt").await?.fast_close();
+/// let copied = copy_and_close(from, to).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn copy_and_close<R, W>(
+    mut reader: FastClose<R>,
+    mut writer: FastClose<W>,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + FastCloseable + 'static,
+    W: AsyncWrite + Unpin + FastCloseable + 'static,
+{
+    let copied = tokio::io::copy(&mut reader, &mut writer).await?;
+    writer.shutdown().await?;
+    Ok(copied)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use tokio::fs::File;
+
+    use super::*;
+    use crate::FastCloseable;
+
+    #[tokio::test]
+    async fn copies_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        let from_path = dir.path().join("from");
+        let to_path = dir.path().join("to");
+        std::fs::write(&from_path, b"hello world").unwrap();
+
+        let from = File::open(&from_path).await.unwrap().fast_close();
+        let to = File::create(&to_path).await.unwrap().fast_close();
+        let copied =
+            copy_and_close(from, to).await.expect("copy should succeed");
+
+        assert_eq!(copied, 11);
+        assert_eq!(std::fs::read(&to_path).unwrap(), b"hello world");
+    }
+}
@@ -0,0 +1,114 @@
+//! Instrumentation with [`tracing`](::tracing)
+//!
+//! With the `tracing` feature enabled, each handle submitted to be closed in
+//! the background gets a `close` span, at the debug level, with the handle's
+//! type, its label (if it has one), and the backend it was submitted to. An
+//! event is recorded in it with
+//! the queue depth when it's submitted, and the span is entered while the
+//! handle is closed, so slow closes show up in traces and flamegraphs, with
+//! another event once it's closed
+use std::{any, time::Duration};
+
+use ::tracing::{debug, debug_span, warn, Span};
+
+use crate::Backend;
+
+/// Creates the span for a handle of type `H` that's being submitted to a
+/// [`Closer`](crate::Closer), if `closer`, or the active backend
+pub(crate) fn submitted<H: ?Sized>(label: Option<&str>, closer: bool) -> Span {
+    let backend = match closer {
+        true => "closer",
+        false => Backend::active().name(),
+    };
+    let span =
+        debug_span!("close", handle = any::type_name::<H>(), label, backend);
+    debug!(
+        parent: &span,
+        queue_depth = crate::pending::count(),
+        "submitted handle"
+    );
+    span
+}
+
+/// Records a handle as having been closed, in its span
+pub(crate) fn closed(duration: Duration, panicked: bool) {
+    match panicked {
+        true => warn!(?duration, "panicked closing handle"),
+        false => debug!(?duration, "closed handle"),
+    }
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ::tracing::{
+        span::{Attributes, Id, Record},
+        subscriber, Event, Metadata, Subscriber,
+    };
+
+    use crate::{backend::close_on, testing::RecordingCloser, Priority};
+
+    /// Records the names of the spans created, and the messages of events
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.0
+                .lock()
+                .unwrap()
+                .push(span.metadata().name().to_owned());
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            struct Message<'a>(&'a mut Vec<String>);
+
+            impl ::tracing::field::Visit for Message<'_> {
+                fn record_debug(
+                    &mut self,
+                    field: &::tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{value:?}"));
+                    }
+                }
+            }
+
+            event.record(&mut Message(&mut self.0.lock().unwrap()));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn records_span_and_events() {
+        let recorder = Recorder::default();
+        subscriber::with_default(recorder.clone(), || {
+            close_on(
+                Some(&RecordingCloser::new()),
+                Priority::Normal,
+                None,
+                None,
+                Vec::<std::fs::File>::new(),
+            );
+        });
+        assert_eq!(*recorder.0.lock().unwrap(), [
+            "close",
+            "submitted handle",
+            "closed handle"
+        ]);
+    }
+}
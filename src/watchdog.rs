@@ -0,0 +1,264 @@
+//! Reporting handles that are taking too long to close
+//!
+//! Closing a handle can occasionally hang for seconds, e.g. for files on a
+//! network share. With a threshold set by [`set_threshold`], a background
+//! thread checks on every handle submitted to be closed in the background,
+//! and reports each one that hasn't been closed within the threshold (once)
+//! to the handler set by [`set_handler`]. With the `log` feature enabled,
+//! they're also logged as warnings
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! close_already::watchdog::set_handler(|slow| eprintln!("{slow}"));
+//! close_already::watchdog::set_threshold(Some(Duration::from_secs(5)));
+//! ```
+use std::{
+    any,
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::diagnostics::Origin;
+
+/// A function that slow closes are sent to
+type Handler = Arc<dyn Fn(SlowClose) + Send + Sync>;
+
+/// The handler set by [`set_handler`], if any
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+/// Whether there's a threshold, checked before watching a handle
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The threshold, and the handles being watched
+static STATE: Mutex<State> = Mutex::new(State {
+    threshold: None,
+    next_id: 0,
+    watching: BTreeMap::new(),
+    running: false,
+});
+
+/// Notified when the threshold changes
+static CHANGED: Condvar = Condvar::new();
+
+/// The longest the watchdog waits between checks
+const MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The shortest the watchdog waits between checks, so a tiny threshold
+/// doesn't make it spin
+const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+struct State {
+    threshold: Option<Duration>,
+    next_id: u64,
+    watching: BTreeMap<u64, Watching>,
+    /// Whether the watchdog thread has been spawned
+    running: bool,
+}
+
+/// A handle that's been submitted to be closed, and hasn't been closed yet
+struct Watching {
+    handle_debug: &'static str,
+    label: Option<Cow<'static, str>>,
+    #[cfg(feature = "diagnostics")]
+    origin: Option<Origin>,
+    submitted: Instant,
+    /// Whether the handle has already been reported as slow
+    reported: bool,
+}
+
+/// A handle that hasn't been closed within the watchdog's threshold, which
+/// has been sent to the handler set by [`set_handler`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SlowClose {
+    /// The type name of the handle being closed
+    pub handle_debug: String,
+    /// The label given to the handle, if any (see
+    /// [`FastClose::with_label`](crate::FastClose::with_label))
+    pub label: Option<Cow<'static, str>>,
+    /// How long it's been since the handle was submitted to be closed
+    pub elapsed: Duration,
+    /// Where the handle was created
+    #[cfg(feature = "diagnostics")]
+    pub origin: Option<Origin>,
+}
+
+impl fmt::Display for SlowClose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "closing ")?;
+        crate::error::describe(f, &self.handle_debug, self.label.as_deref())?;
+        write!(f, " has taken {:?} so far", self.elapsed)?;
+        #[cfg(feature = "diagnostics")]
+        if let Some(origin) = &self.origin {
+            write!(f, ", created at {origin}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets how long a handle can take to close before it's reported, or stops
+/// reporting them if `None`
+///
+/// The time is measured from when the handle is submitted to the backend,
+/// so it includes any time spent waiting in its queue. Only handles
+/// submitted after a threshold is set are watched. The watchdog thread is
+/// spawned the first time a threshold is set
+pub fn set_threshold(threshold: Option<Duration>) {
+    let mut state = lock();
+    state.threshold = threshold;
+    ENABLED.store(threshold.is_some(), Ordering::Relaxed);
+    if threshold.is_some() && !mem::replace(&mut state.running, true) {
+        thread::Builder::new()
+            .name(String::from("close_already-watchdog"))
+            .spawn(watch_forever)
+            .expect("failed to spawn close_already watchdog thread");
+    }
+    drop(state);
+    CHANGED.notify_all();
+}
+
+/// Sets a function to send slow closes to, replacing any set before
+///
+/// The handler is called from the watchdog thread, so while it runs, no
+/// other handles are checked on
+pub fn set_handler(handler: impl Fn(SlowClose) + Send + Sync + 'static) {
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) =
+        Some(Arc::new(handler));
+}
+
+/// Removes the handler set by [`set_handler`]
+pub fn clear_handler() {
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// A handle being watched, until this is dropped
+pub(crate) struct Watch(u64);
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        lock().watching.remove(&self.0);
+    }
+}
+
+/// Starts watching a handle of type `H` that's being submitted to be
+/// closed, if there's a threshold
+pub(crate) fn watch<H: ?Sized>(
+    label: Option<&Cow<'static, str>>,
+    origin: Option<Origin>,
+) -> Option<Watch> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    #[cfg(not(feature = "diagnostics"))]
+    let _ = origin;
+    let mut state = lock();
+    let id = state.next_id;
+    state.next_id += 1;
+    state.watching.insert(id, Watching {
+        handle_debug: any::type_name::<H>(),
+        label: label.cloned(),
+        #[cfg(feature = "diagnostics")]
+        origin,
+        submitted: Instant::now(),
+        reported: false,
+    });
+    Some(Watch(id))
+}
+
+/// Reports handles that have been closing for longer than the threshold,
+/// forever
+fn watch_forever() {
+    let mut state = lock();
+    loop {
+        let Some(threshold) = state.threshold else {
+            state = CHANGED.wait(state).unwrap_or_else(PoisonError::into_inner);
+            continue;
+        };
+        let slow = state
+            .watching
+            .values_mut()
+            .filter(|watching| {
+                !watching.reported && watching.submitted.elapsed() >= threshold
+            })
+            .map(|watching| {
+                watching.reported = true;
+                SlowClose {
+                    handle_debug: watching.handle_debug.to_owned(),
+                    label: watching.label.clone(),
+                    elapsed: watching.submitted.elapsed(),
+                    #[cfg(feature = "diagnostics")]
+                    origin: watching.origin.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        if !slow.is_empty() {
+            drop(state);
+            slow.into_iter().for_each(report);
+            state = lock();
+        }
+        let interval = (threshold / 4).clamp(MIN_INTERVAL, MAX_INTERVAL);
+        state = CHANGED
+            .wait_timeout(state, interval)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+    }
+}
+
+fn report(slow: SlowClose) {
+    #[cfg(feature = "log")]
+    crate::log::slow_close(&slow);
+    let handler = HANDLER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(handler) = handler {
+        handler(slow);
+    }
+}
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn reports_slow_closes() {
+        struct Slow;
+
+        let (sender, receiver) = mpsc::channel();
+        set_handler(move |slow| {
+            let _ = sender.send(slow);
+        });
+        set_threshold(Some(Duration::from_millis(20)));
+        let watch = watch::<Slow>(
+            Some(&Cow::Borrowed("slow handle")),
+            Origin::capture(),
+        );
+        let slow = loop {
+            let slow = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            if slow.handle_debug == any::type_name::<Slow>() {
+                break slow;
+            }
+        };
+        set_threshold(None);
+        clear_handler();
+        drop(watch);
+        assert!(slow.elapsed >= Duration::from_millis(20));
+        assert!(slow.to_string().starts_with("closing slow handle ("));
+        #[cfg(feature = "diagnostics")]
+        assert!(slow.to_string().contains(file!()));
+    }
+}
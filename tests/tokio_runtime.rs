@@ -0,0 +1,33 @@
+//! Tests for [`set_tokio_runtime`], in their own process as the runtime can
+//! only be set once
+#![cfg(all(feature = "backend-tokio", not(miri)))]
+
+use std::{future, sync::mpsc, thread};
+
+use close_already::{set_tokio_runtime, Backend, Closer, DefaultCloser};
+
+#[test]
+fn spawns_onto_set_runtime() {
+    close_already::set_backend(Backend::Tokio).unwrap();
+
+    let (send_runtime, runtime) = mpsc::channel();
+    // Runs for the rest of the test, so the closure can run on it
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .thread_name("set-runtime")
+            .build()
+            .unwrap();
+        send_runtime.send(runtime.handle().clone()).unwrap();
+        runtime.block_on(future::pending::<()>());
+    });
+    set_tokio_runtime(runtime.recv().unwrap()).unwrap();
+    assert!(tokio::runtime::Handle::try_current().is_err());
+
+    let (sender, receiver) = mpsc::channel();
+    DefaultCloser::default().submit(Box::new(move || {
+        let name = thread::current().name().map(String::from);
+        sender.send(name).unwrap();
+    }));
+    let name = receiver.recv().expect("closure should run");
+    assert_eq!(name.as_deref(), Some("set-runtime"));
+}